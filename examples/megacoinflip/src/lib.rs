@@ -5,11 +5,14 @@ use megadrive_graphics::Renderer;
 use megadrive_input::{Controllers, Button};
 use megadrive_util::rng::PseudoRng;
 use megadrive_sys::vdp::VDP;
+use megadrive_sys::interrupt::{self, Interrupt};
+use megadrive_rt::RomHeader;
 use megadrive_graphics::default_ascii::DEFAULT_FONT_1X1;
 
 static mut NEW_FRAME: u16 = 0;
 
-#[no_mangle]
+megadrive_rt::entry!(main, header = RomHeader::new().name("MEGACOINFLIP"));
+
 pub fn main() -> ! {
     let mut renderer = Renderer::new();
     let mut controllers = Controllers::new();
@@ -24,6 +27,7 @@ pub fn main() -> ! {
     let x_off = 104 + half_screen_width;
     let y_off = 128 + half_screen_height;
 
+    interrupt::set_handler(Interrupt::VBlank, vblank);
     vdp.enable_interrupts(false, true, false);
     vdp.enable_display(true);
 
@@ -78,7 +82,6 @@ fn wait_for_vblank() {
     }
 }
 
-#[no_mangle]
-fn vblank() {
+extern "C" fn vblank() {
     unsafe { write_volatile(&mut NEW_FRAME, 1) };
 }
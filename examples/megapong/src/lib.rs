@@ -3,51 +3,22 @@
 use core::panic::PanicInfo;
 use core::ptr::{read_volatile, write_volatile};
 use megadrive_sys::vdp::{VDP, Sprite, SpriteSize, TileFlags, Tile};
-use megadrive_sys::fm::{FM, Note, Panning, Channel};
+use megadrive_sys::fm::{FM, FmPatch, Note, Channel};
+use megadrive_sys::interrupt::{self, Interrupt};
+use megadrive_rt::RomHeader;
 use megadrive_input::{Controllers, Button};
 use megadrive_graphics::Renderer;
 
 static mut NEW_FRAME: u16 = 0;
 
+megadrive_rt::entry!(main, header = RomHeader::new().name("MEGAPONG"));
+
 extern "C" {
     fn wait_for_interrupt();
 }
 
 fn setup_piano(ch: &Channel) {
-    let op0 = ch.operator(0);
-    op0.set_multiplier(1, 7);
-    op0.set_total_level(35);
-    op0.set_attack_rate(63, 1);
-    op0.set_decay_rate(5, false);
-    op0.set_sustain_rate(2);
-    op0.set_release_rate(1, 1);
-
-    let op1 = ch.operator(1);
-    op1.set_multiplier(13, 0);
-    op1.set_total_level(45);
-    op1.set_attack_rate(25, 2);
-    op1.set_decay_rate(5, false);
-    op1.set_sustain_rate(2);
-    op1.set_release_rate(1, 1);
-
-    let op2 = ch.operator(2);
-    op2.set_multiplier(3, 3);
-    op2.set_total_level(38);
-    op2.set_attack_rate(63, 1);
-    op2.set_decay_rate(5, false);
-    op2.set_sustain_rate(2);
-    op2.set_release_rate(1, 1);
-
-    let op3 = ch.operator(3);
-    op3.set_multiplier(1, 0);
-    op3.set_total_level(0);
-    op3.set_attack_rate(20, 2);
-    op3.set_decay_rate(7, false);
-    op3.set_sustain_rate(2);
-    op3.set_release_rate(6, 10);
-
-    ch.set_algorithm(2, 6);
-    ch.set_panning(Panning::Both, 0, 0);
+    ch.apply_patch(&FmPatch::PIANO);
     ch.set_frequency(Note::F, 5);
 }
 
@@ -190,7 +161,6 @@ fn upload_graphics(vdp: &mut VDP) {
     vdp.set_tiles(1, TILE_DATA);
 }
 
-#[no_mangle]
 pub fn main() -> ! {
     let fm = FM::new();
     let mut vdp = VDP::new();
@@ -237,6 +207,7 @@ pub fn main() -> ! {
         }
     };
 
+    interrupt::set_handler(Interrupt::VBlank, vblank);
     vdp.enable_interrupts(false, true, false);
     vdp.enable_display(true);
 
@@ -372,8 +343,7 @@ fn wait_for_vblank() {
     }
 }
 
-#[no_mangle]
-fn vblank() {
+extern "C" fn vblank() {
     unsafe { write_volatile(&mut NEW_FRAME, 1) };
 }
 
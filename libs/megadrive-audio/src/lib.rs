@@ -0,0 +1,6 @@
+//! Higher-level audio support for the Sega Mega Drive (Genesis).
+//!
+//! At the moment this provides a player for VGM music, see `vgm`.
+#![no_std]
+
+pub mod vgm;
@@ -0,0 +1,477 @@
+//! Playback of VGM register logs from ROM.
+//!
+//! VGM files record every write to the sound chips, along with the time
+//! between them. They can be exported by most Mega Drive trackers. Only
+//! uncompressed files are supported, so `.vgz` files must be unpacked with
+//! `gzip -d` first.
+//!
+//! ```ignore
+//! static SONG: &[u8] = include_bytes!("song.vgm");
+//!
+//! let mut player = Player::new(Vgm::new(SONG).unwrap(), FM::new(), PSG::new());
+//! player.play();
+//! loop {
+//!     wait_for_vblank();
+//!     player.update();
+//! }
+//! ```
+//!
+//! The YM2612's DAC is fed from PCM data blocks, but only at the rate the
+//! player is updated. Drums played this way sound much better when the player
+//! is driven by timer A, see `Player::use_timer_a`.
+//!
+//! The player writes to the YM2612 from the 68k, so it cannot be used at the
+//! same time as `megadrive_sys::pcm::Pcm`.
+
+use megadrive_sys::fm::FM;
+use megadrive_sys::psg::PSG;
+
+/// The rate at which VGM waits are counted.
+pub const SAMPLE_RATE: u32 = 44100;
+
+const IDENT: &[u8; 4] = b"Vgm ";
+const HEADER_SIZE: usize = 0x40;
+const EOF_OFFSET: usize = 0x04;
+const VERSION: usize = 0x08;
+const LOOP_OFFSET: usize = 0x1c;
+const DATA_OFFSET: usize = 0x34;
+
+const SAMPLES_PER_FRAME_NTSC: u32 = SAMPLE_RATE / 60;
+const SAMPLES_PER_FRAME_PAL: u32 = SAMPLE_RATE / 50;
+
+/// Samples per timer A step, in 8.8 fixed point.
+///
+/// Timer A counts at the YM2612's sample rate, which is 53267Hz on NTSC
+/// consoles and 52781Hz on PAL ones.
+const TIMER_A_STEP_NTSC: u32 = 212;
+const TIMER_A_STEP_PAL: u32 = 214;
+
+/// The number of separate YM2612 PCM data blocks which can be used.
+const MAX_PCM_BLOCKS: usize = 4;
+const PCM_BLOCK_YM2612: u8 = 0x00;
+
+const FM_TIMER_A_HI: u8 = 0x24;
+const FM_TIMER_A_LO: u8 = 0x25;
+const FM_TIMER_CTRL: u8 = 0x27;
+const FM_KEY_ON: u8 = 0x28;
+const FM_DAC_DATA: u8 = 0x2a;
+
+/// An error found in a VGM file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The data does not start with a VGM header.
+    NotVgm,
+    /// The file is shorter than its header says.
+    Truncated,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// A VGM file.
+#[derive(Clone, Copy, Debug)]
+pub struct Vgm<'a> {
+    data: &'a [u8],
+    start: usize,
+    loop_start: Option<usize>,
+    version: u32,
+}
+
+impl<'a> Vgm<'a> {
+    /// Parse the header of a VGM file.
+    pub fn new(data: &'a [u8]) -> Result<Vgm<'a>, Error> {
+        if data.len() < HEADER_SIZE || &data[..4] != IDENT {
+            return Err(Error::NotVgm);
+        }
+
+        let version = read_u32(data, VERSION);
+        let data_offset = read_u32(data, DATA_OFFSET);
+        let loop_offset = read_u32(data, LOOP_OFFSET);
+
+        let end = match EOF_OFFSET.checked_add(read_u32(data, EOF_OFFSET) as usize) {
+            Some(end) if end >= HEADER_SIZE && end <= data.len() => end,
+            _ => return Err(Error::Truncated),
+        };
+        let data = &data[..end];
+
+        let start = if version >= 0x150 && data_offset != 0 {
+            DATA_OFFSET.checked_add(data_offset as usize)
+        } else {
+            Some(HEADER_SIZE)
+        };
+        let start = match start {
+            Some(start) if start <= end => start,
+            _ => return Err(Error::Truncated),
+        };
+        let loop_start = match loop_offset {
+            0 => None,
+            offset => match LOOP_OFFSET.checked_add(offset as usize) {
+                Some(loop_start) if loop_start <= end => Some(loop_start),
+                _ => return Err(Error::Truncated),
+            },
+        };
+
+        Ok(Vgm { data, start, loop_start, version })
+    }
+
+    /// The version of the file, in BCD, e.g. `0x171` for 1.71.
+    pub fn version(&self) -> u32 { self.version }
+
+    /// Returns true if the music loops.
+    pub fn loops(&self) -> bool { self.loop_start.is_some() }
+}
+
+/// The state of a `Player`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+/// Plays a VGM file through the YM2612 & PSG.
+pub struct Player<'a> {
+    vgm: Vgm<'a>,
+    fm: FM,
+    psg: PSG,
+    state: State,
+    looping: bool,
+
+    pos: usize,
+    /// Samples until the next command is due, in 8.8 fixed point.
+    ///
+    /// This goes negative when a frame or tick ends part way through a wait,
+    /// so that the remainder is carried into the next one.
+    wait: i32,
+    samples_per_frame: u32,
+    timer_a_step: u32,
+    timer_a: Option<u32>,
+    /// Set once a wait has been played since the start or loop point, so a
+    /// loop without any waits can't hang the player.
+    waited: bool,
+
+    pcm_blocks: [&'a [u8]; MAX_PCM_BLOCKS],
+    pcm_pos: usize,
+
+    psg_latch: u8,
+    psg_volumes: [u8; 4],
+    paused_fm: Option<megadrive_sys::fm::FmState>,
+}
+
+impl<'a> Player<'a> {
+    /// Create a player, which starts off stopped.
+    ///
+    /// The rate at which the music is played is adjusted for NTSC or PAL
+    /// consoles, so that it plays at the same speed on both.
+    pub fn new(vgm: Vgm<'a>, fm: FM, psg: PSG) -> Player<'a> {
+        let (samples_per_frame, timer_a_step) = if megadrive_sys::version().is_pal() {
+            (SAMPLES_PER_FRAME_PAL, TIMER_A_STEP_PAL)
+        } else {
+            (SAMPLES_PER_FRAME_NTSC, TIMER_A_STEP_NTSC)
+        };
+
+        Player {
+            vgm,
+            fm,
+            psg,
+            state: State::Stopped,
+            looping: true,
+            pos: vgm.start,
+            wait: 0,
+            samples_per_frame,
+            timer_a_step,
+            timer_a: None,
+            waited: false,
+            pcm_blocks: [&[]; MAX_PCM_BLOCKS],
+            pcm_pos: 0,
+            psg_latch: 0,
+            psg_volumes: [0xf; 4],
+            paused_fm: None,
+        }
+    }
+
+    /// Get the state of the player.
+    pub fn state(&self) -> State { self.state }
+
+    /// Returns true if music is playing.
+    pub fn is_playing(&self) -> bool { self.state == State::Playing }
+
+    /// Set whether the music loops, if it has a loop point. This defaults to
+    /// true.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    /// Start playing from the beginning.
+    pub fn play(&mut self) {
+        self.stop();
+        self.state = State::Playing;
+    }
+
+    /// Stop playing and silence the chips.
+    pub fn stop(&mut self) {
+        self.silence();
+        self.state = State::Stopped;
+        self.pos = self.vgm.start;
+        self.wait = 0;
+        self.waited = false;
+        self.pcm_blocks = [&[]; MAX_PCM_BLOCKS];
+        self.pcm_pos = 0;
+        self.psg_volumes = [0xf; 4];
+        self.paused_fm = None;
+    }
+
+    /// Pause the music, silencing the chips until it is resumed.
+    ///
+    /// Notes which are held continue to fade whilst paused.
+    pub fn pause(&mut self) {
+        if self.state != State::Playing {
+            return;
+        }
+
+        self.paused_fm = Some(self.fm.save());
+        for ch in self.fm.channels() {
+            ch.set_pan(megadrive_sys::fm::Panning::None);
+        }
+        for ch in 0..4 {
+            self.psg.write_raw(0x9f | (ch << 5));
+        }
+        self.state = State::Paused;
+    }
+
+    /// Resume the music after it has been paused.
+    pub fn resume(&mut self) {
+        if self.state != State::Paused {
+            return;
+        }
+
+        if let Some(state) = self.paused_fm.take() {
+            self.fm.restore(&state);
+        }
+        for (ch, &volume) in self.psg_volumes.iter().enumerate() {
+            self.psg.write_raw(0x90 | ((ch as u8) << 5) | volume);
+        }
+        self.state = State::Playing;
+    }
+
+    /// Drive the player from timer A rather than calling `update`.
+    ///
+    /// `value` is as for `FM::set_timer_a`. Writes to timer A from the music
+    /// are ignored whilst it is in use. `update_timer_a` must then be called
+    /// often enough to catch every tick.
+    pub fn use_timer_a(&mut self, value: u16) {
+        let step = (1024 - (value & 0x3ff) as u32) * self.timer_a_step;
+        self.timer_a = Some(step);
+        self.fm.set_timer_a(value);
+        self.start_timer_a();
+    }
+
+    fn start_timer_a(&self) {
+        // Keep the channel 3 mode & timer B, then load & reset timer A.
+        let ctrl = self.fm.register(false, FM_TIMER_CTRL);
+        self.fm.write_register(false, FM_TIMER_CTRL, (ctrl & 0xca) | 0x15);
+    }
+
+    /// Advance the music by one frame.
+    ///
+    /// This should be called once per vertical blank.
+    pub fn update(&mut self) {
+        self.advance(self.samples_per_frame << 8);
+    }
+
+    /// Advance the music if timer A has ticked.
+    ///
+    /// Returns true if it had.
+    pub fn update_timer_a(&mut self) -> bool {
+        let step = match self.timer_a {
+            Some(step) => step,
+            None => return false,
+        };
+        if !self.fm.timer_status().0 {
+            return false;
+        }
+
+        self.start_timer_a();
+        self.advance(step);
+        true
+    }
+
+    /// Play commands for `samples` 8.8 fixed point samples at 44.1kHz.
+    fn advance(&mut self, samples: u32) {
+        if self.state != State::Playing {
+            return;
+        }
+
+        self.wait -= samples as i32;
+        while self.wait <= 0 && self.state == State::Playing {
+            self.step();
+        }
+    }
+
+    fn byte(&mut self) -> u8 {
+        match self.vgm.data.get(self.pos) {
+            Some(&b) => {
+                self.pos += 1;
+                b
+            },
+            None => {
+                self.state = State::Stopped;
+                0
+            },
+        }
+    }
+
+    fn u16(&mut self) -> u16 {
+        let lo = self.byte() as u16;
+        lo | ((self.byte() as u16) << 8)
+    }
+
+    fn u32(&mut self) -> u32 {
+        let lo = self.u16() as u32;
+        lo | ((self.u16() as u32) << 16)
+    }
+
+    fn skip(&mut self, n: usize) {
+        self.pos = self.pos.saturating_add(n);
+    }
+
+    fn delay(&mut self, samples: u32) {
+        self.wait += (samples << 8) as i32;
+        self.waited |= samples != 0;
+    }
+
+    /// Run a single command.
+    fn step(&mut self) {
+        let command = self.byte();
+        match command {
+            0x4f => self.skip(1),
+            0x50 => {
+                let v = self.byte();
+                self.write_psg(v);
+            },
+            0x52 | 0x53 => {
+                let addr = self.byte();
+                let value = self.byte();
+                self.write_fm(command == 0x53, addr, value);
+            },
+            0x61 => {
+                let n = self.u16() as u32;
+                self.delay(n);
+            },
+            0x62 => self.delay(SAMPLES_PER_FRAME_NTSC),
+            0x63 => self.delay(SAMPLES_PER_FRAME_PAL),
+            0x66 => self.end(),
+            0x67 => self.data_block(),
+            0x70..=0x7f => self.delay((command & 0xf) as u32 + 1),
+            0x80..=0x8f => {
+                let v = self.pcm_byte();
+                self.fm.dac_write(v);
+                self.delay((command & 0xf) as u32);
+            },
+            0xe0 => self.pcm_pos = self.u32() as usize,
+
+            // DAC stream control, which is not supported.
+            0x90 | 0x91 | 0x95 => self.skip(4),
+            0x92 => self.skip(5),
+            0x93 => self.skip(10),
+            0x94 => self.skip(1),
+
+            // Commands for other chips.
+            0x30..=0x3f => self.skip(1),
+            0x40..=0x4e | 0x51 | 0x54..=0x5f | 0xa0..=0xbf => self.skip(2),
+            0xc0..=0xdf => self.skip(3),
+            0xe1..=0xff => self.skip(4),
+
+            _ => self.state = State::Stopped,
+        }
+    }
+
+    fn end(&mut self) {
+        match self.vgm.loop_start {
+            Some(start) if self.looping && self.waited => {
+                self.pos = start;
+                self.waited = false;
+            },
+            _ => {
+                self.silence();
+                self.state = State::Stopped;
+            },
+        }
+    }
+
+    fn data_block(&mut self) {
+        // 0x66 marks the end of the block header, for compatibility.
+        self.skip(1);
+        let kind = self.byte();
+        let len = self.u32() as usize;
+        let start = self.pos.min(self.vgm.data.len());
+        let end = start.saturating_add(len).min(self.vgm.data.len());
+        self.pos = start.saturating_add(len);
+
+        if kind == PCM_BLOCK_YM2612 {
+            // Blocks of the same type are treated as one.
+            if let Some(slot) = self.pcm_blocks.iter_mut().find(|b| b.is_empty()) {
+                *slot = &self.vgm.data[start..end];
+            }
+        }
+    }
+
+    fn pcm_byte(&mut self) -> u8 {
+        let mut offset = self.pcm_pos;
+        self.pcm_pos += 1;
+        for block in self.pcm_blocks.iter() {
+            if offset < block.len() {
+                return block[offset];
+            }
+            offset -= block.len();
+        }
+        0x80
+    }
+
+    fn write_fm(&mut self, second: bool, addr: u8, value: u8) {
+        if self.timer_a.is_some() && !second {
+            match addr {
+                FM_TIMER_A_HI | FM_TIMER_A_LO => return,
+                FM_TIMER_CTRL => {
+                    // Keep the music's channel 3 mode & timer B, but not timer A.
+                    let ctrl = self.fm.register(false, FM_TIMER_CTRL);
+                    let v = (value & 0xea) | (ctrl & 0x05);
+                    self.fm.write_register(false, FM_TIMER_CTRL, v);
+                    return;
+                },
+                _ => {},
+            }
+        }
+        if addr == FM_DAC_DATA && !second {
+            self.fm.dac_write(value);
+            return;
+        }
+        self.fm.write_register(second, addr, value);
+    }
+
+    fn write_psg(&mut self, v: u8) {
+        if (v & 0x80) != 0 {
+            self.psg_latch = v;
+        }
+
+        // Track the volumes, so they can be restored after a pause.
+        if (self.psg_latch & 0x10) != 0 {
+            let ch = ((self.psg_latch >> 5) & 3) as usize;
+            self.psg_volumes[ch] = v & 0xf;
+        }
+
+        self.psg.write_raw(v);
+    }
+
+    /// Key off every FM channel and mute the PSG.
+    fn silence(&mut self) {
+        for ch in 0..6u8 {
+            let ch = if ch >= 3 { ch + 1 } else { ch };
+            self.fm.write_register(false, FM_KEY_ON, ch);
+        }
+        for ch in 0..4 {
+            self.psg.write_raw(0x9f | (ch << 5));
+        }
+    }
+}
@@ -0,0 +1,310 @@
+#![cfg(feature = "mock")]
+
+use std::sync::{Mutex, MutexGuard};
+
+use megadrive_audio::vgm::{Error, Player, State, Vgm};
+use megadrive_sys::fm::FM;
+use megadrive_sys::mmio::mock;
+use megadrive_sys::psg::PSG;
+
+const FM_PORT_1: u32 = 0xa04000;
+const FM_PORT_2: u32 = 0xa04002;
+const PSG_PORT: u32 = 0xc00011;
+const VERSION_REG: u32 = 0xa10001;
+
+/// The FM register shadow is shared by every thread, so tests which use it
+/// must not run at the same time.
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn lock() -> MutexGuard<'static, ()> {
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    mock::reset();
+    guard
+}
+
+/// Build a version 1.50 VGM file around some commands.
+///
+/// `loop_at` is an offset into `commands` to loop back to.
+fn vgm(commands: &[u8], loop_at: Option<usize>) -> Vec<u8> {
+    let mut data = vec![0; 0x40];
+    data[..4].copy_from_slice(b"Vgm ");
+    data[0x08..0x0c].copy_from_slice(&0x150u32.to_le_bytes());
+    data[0x34..0x38].copy_from_slice(&0x0cu32.to_le_bytes());
+    if let Some(at) = loop_at {
+        data[0x1c..0x20].copy_from_slice(&((0x40 + at - 0x1c) as u32).to_le_bytes());
+    }
+    data.extend_from_slice(commands);
+    let eof = (data.len() - 4) as u32;
+    data[0x04..0x08].copy_from_slice(&eof.to_le_bytes());
+    data
+}
+
+fn player(data: &[u8]) -> Player<'_> {
+    let mut player = Player::new(Vgm::new(data).unwrap(), FM::new(), PSG::new());
+    player.play();
+    mock::take_log();
+    player
+}
+
+/// Every write to a YM2612 register as (second bank, address, value).
+fn fm_writes(writes: &[(u32, u32)]) -> Vec<(bool, u8, u8)> {
+    let fm: Vec<_> = writes.iter()
+        .filter(|&&(addr, _)| (FM_PORT_1..FM_PORT_1 + 4).contains(&addr))
+        .collect();
+    fm.chunks(2)
+        .map(|w| (w[0].0 == FM_PORT_2, w[0].1 as u8, w[1].1 as u8))
+        .collect()
+}
+
+fn dac_writes(writes: &[(u32, u32)]) -> Vec<u8> {
+    fm_writes(writes).into_iter()
+        .filter(|&(second, addr, _)| !second && addr == 0x2a)
+        .map(|(_, _, v)| v)
+        .collect()
+}
+
+fn psg_writes(writes: &[(u32, u32)]) -> Vec<u8> {
+    writes.iter()
+        .filter(|&&(addr, _)| addr == PSG_PORT)
+        .map(|&(_, v)| v as u8)
+        .collect()
+}
+
+#[test]
+fn header() {
+    let data = vgm(&[0x66], Some(0));
+    let v = Vgm::new(&data).unwrap();
+    assert_eq!(v.version(), 0x150);
+    assert!(v.loops());
+    assert!(!Vgm::new(&vgm(&[0x66], None)).unwrap().loops());
+
+    assert_eq!(Vgm::new(&data[..0x3f]).unwrap_err(), Error::NotVgm);
+    let mut bad = data.clone();
+    bad[0] = b'v';
+    assert_eq!(Vgm::new(&bad).unwrap_err(), Error::NotVgm);
+}
+
+#[test]
+fn truncated() {
+    let data = vgm(&[0x66], None);
+    assert_eq!(Vgm::new(&data[..data.len() - 1]).unwrap_err(), Error::Truncated);
+
+    let with_u32 = |offset: usize, value: u32| {
+        let mut data = data.clone();
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        Vgm::new(&data).map(|_| ())
+    };
+    assert_eq!(with_u32(0x04, 0), Err(Error::Truncated));
+    assert_eq!(with_u32(0x04, 0x3b), Err(Error::Truncated));
+    assert_eq!(with_u32(0x04, u32::MAX), Err(Error::Truncated));
+    assert_eq!(with_u32(0x34, u32::MAX), Err(Error::Truncated));
+    assert_eq!(with_u32(0x1c, u32::MAX), Err(Error::Truncated));
+    assert_eq!(with_u32(0x1c, 0x100), Err(Error::Truncated));
+
+    // Before 1.50, the data always starts after the header.
+    let mut old = data.clone();
+    old[0x08..0x0c].copy_from_slice(&0x110u32.to_le_bytes());
+    old[0x34..0x38].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(Vgm::new(&old).is_ok());
+}
+
+#[test]
+fn commands() {
+    let _guard = lock();
+    let data = vgm(&[
+        0x52, 0xb0, 0x32,
+        0x53, 0xb4, 0xc0,
+        0x50, 0x9a,
+        0x4f, 0x00,
+        0xb4, 0x00, 0x00,
+        0x66,
+    ], None);
+    let mut player = player(&data);
+    player.update();
+    let writes = mock::writes();
+    assert_eq!(psg_writes(&writes)[0], 0x9a);
+    assert_eq!(&fm_writes(&writes)[..2], [(false, 0xb0, 0x32), (true, 0xb4, 0xc0)]);
+    assert_eq!(player.state(), State::Stopped);
+}
+
+#[test]
+fn waits() {
+    let _guard = lock();
+    let data = vgm(&[
+        0x50, 0x90, 0x61, 0x00, 0x01,
+        0x50, 0x91, 0x7f,
+        0x50, 0x92, 0x70,
+        0x50, 0x93, 0x63,
+        0x50, 0x94, 0x66,
+    ], None);
+    let mut player = player(&data);
+
+    // 256 + 16 + 1 + 882 samples is between one and two frames.
+    player.update();
+    assert_eq!(psg_writes(&mock::writes()), [0x90, 0x91, 0x92, 0x93]);
+    assert!(player.is_playing());
+    player.update();
+    assert_eq!(&psg_writes(&mock::writes())[..1], [0x94]);
+    assert!(!player.is_playing());
+}
+
+#[test]
+fn pal_frame_rate() {
+    let _guard = lock();
+    mock::set_read_value(VERSION_REG, 0x40);
+
+    // Six NTSC frames should play in five PAL frames.
+    let mut commands = Vec::new();
+    for i in 0..6 {
+        commands.extend_from_slice(&[0x50, 0x90 | i, 0x62]);
+    }
+    commands.push(0x66);
+    let data = vgm(&commands, None);
+    let mut player = player(&data);
+
+    let mut played = 0;
+    for expected in [2, 3, 4, 5, 6].iter() {
+        player.update();
+        played += psg_writes(&mock::writes()).iter().filter(|&&v| v < 0x9f).count();
+        assert_eq!(played, *expected);
+    }
+    assert!(!player.is_playing());
+}
+
+#[test]
+fn looping() {
+    let _guard = lock();
+    let data = vgm(&[0x50, 0x91, 0x62, 0x50, 0x92, 0x62, 0x66], Some(3));
+    let mut player = player(&data);
+    player.update();
+    assert_eq!(psg_writes(&mock::writes()), [0x91, 0x92]);
+    for _ in 0..3 {
+        player.update();
+        assert_eq!(psg_writes(&mock::writes()), [0x92]);
+    }
+    assert!(player.is_playing());
+
+    player.set_looping(false);
+    player.update();
+    assert_eq!(psg_writes(&mock::writes()), [0x9f, 0xbf, 0xdf, 0xff]);
+    assert_eq!(player.state(), State::Stopped);
+}
+
+#[test]
+fn data_blocks() {
+    let _guard = lock();
+    let data = vgm(&[
+        0x67, 0x66, 0x00, 0x04, 0x00, 0x00, 0x00, 0x10, 0x11, 0x12, 0x13,
+        // Other kinds of block are skipped.
+        0x67, 0x66, 0xc0, 0x02, 0x00, 0x00, 0x00, 0xee, 0xee,
+        0x67, 0x66, 0x00, 0x02, 0x00, 0x00, 0x00, 0x14, 0x15,
+        0x80, 0x80,
+        0xe0, 0x04, 0x00, 0x00, 0x00, 0x80,
+        0xe0, 0x02, 0x00, 0x00, 0x00, 0x80,
+        0xe0, 0x10, 0x00, 0x00, 0x00, 0x80,
+        0x66,
+    ], None);
+    let mut player = player(&data);
+    player.update();
+    assert_eq!(dac_writes(&mock::writes()), [0x10, 0x11, 0x14, 0x12, 0x80]);
+}
+
+#[test]
+fn truncated_data_block() {
+    let _guard = lock();
+    let data = vgm(&[0x50, 0x90, 0x67, 0x66, 0x00, 0xff, 0xff, 0xff, 0xff, 0x10], None);
+    let mut player = player(&data);
+    player.update();
+    assert_eq!(psg_writes(&mock::writes())[0], 0x90);
+    assert_eq!(player.state(), State::Stopped);
+}
+
+#[test]
+fn timer_a() {
+    let _guard = lock();
+    let mut commands = vec![0x67, 0x66, 0x00, 0x20, 0x00, 0x00, 0x00];
+    commands.extend(0..0x20u8);
+    commands.extend_from_slice(&[0xe0, 0x00, 0x00, 0x00, 0x00]);
+    commands.extend_from_slice(&[0x81; 0x20]);
+    commands.push(0x66);
+    let data = vgm(&commands, None);
+    let mut player = player(&data);
+
+    // Each tick is 5 YM2612 samples, which is a little over 4 VGM samples.
+    player.use_timer_a(1019);
+    mock::take_log();
+    assert!(!player.update_timer_a());
+    mock::set_read_value(FM_PORT_1, 0x01);
+    for _ in 0..3 {
+        assert!(player.update_timer_a());
+        assert!(fm_writes(&mock::writes()).contains(&(false, 0x27, 0x15)));
+    }
+}
+
+#[test]
+fn timer_a_dac_rate() {
+    let _guard = lock();
+    let mut commands = vec![0x67, 0x66, 0x00, 0x20, 0x00, 0x00, 0x00];
+    commands.extend(0..0x20u8);
+    commands.extend_from_slice(&[0x81; 0x20]);
+    commands.push(0x66);
+    let data = vgm(&commands, None);
+    let mut player = player(&data);
+    player.use_timer_a(1019);
+    mock::set_read_value(FM_PORT_1, 0x01);
+    mock::take_log();
+
+    let mut played = Vec::new();
+    for _ in 0..3 {
+        player.update_timer_a();
+        played.extend(dac_writes(&mock::writes()));
+    }
+    assert_eq!(played, (0..13).collect::<Vec<u8>>());
+}
+
+#[test]
+fn timer_a_ignores_music() {
+    let _guard = lock();
+    let data = vgm(&[0x52, 0x24, 0x00, 0x52, 0x25, 0x00, 0x52, 0x27, 0x4a, 0x62, 0x66], None);
+    let mut player = player(&data);
+    player.use_timer_a(1019);
+    mock::take_log();
+    player.update();
+    let fm = fm_writes(&mock::writes());
+    assert!(fm.iter().all(|&(_, addr, _)| addr != 0x24 && addr != 0x25));
+    assert!(fm.contains(&(false, 0x27, 0x4f)));
+}
+
+#[test]
+fn loop_without_waits() {
+    let _guard = lock();
+    let data = vgm(&[0x62, 0x50, 0x90, 0x66], Some(1));
+    let mut player = player(&data);
+    player.update();
+    assert_eq!(player.state(), State::Stopped);
+}
+
+#[test]
+fn pause() {
+    let _guard = lock();
+    let data = vgm(&[0x52, 0xb4, 0xc0, 0x50, 0xd3, 0x62, 0x62, 0x50, 0x9a, 0x66], None);
+    let mut player = player(&data);
+    player.update();
+    mock::take_log();
+
+    player.pause();
+    assert_eq!(player.state(), State::Paused);
+    let writes = mock::writes();
+    assert!(fm_writes(&writes).contains(&(false, 0xb4, 0x00)));
+    assert_eq!(psg_writes(&writes), [0x9f, 0xbf, 0xdf, 0xff]);
+
+    player.update();
+    assert_eq!(mock::writes(), []);
+
+    player.resume();
+    let writes = mock::writes();
+    assert!(fm_writes(&writes).contains(&(false, 0xb4, 0xc0)));
+    assert_eq!(psg_writes(&writes), [0x9f, 0xbf, 0xd3, 0xff]);
+    player.update();
+    assert_eq!(&psg_writes(&mock::writes())[..1], [0x9a]);
+}
@@ -0,0 +1,186 @@
+use megadrive_sys::vdp::{AddrKind, Color, DmaQueue, RgbPalette, VDP};
+
+const NUM_PALETTES: usize = 4;
+const NUM_COLORS: usize = 16;
+
+/// A palette fading engine.
+///
+/// This keeps a copy of what is in CRAM for all 4 palettes, along with the
+/// palettes it is fading towards. Each call to `step` moves the current
+/// palettes one frame closer to the target, and `queue` schedules only the
+/// colours which have changed to be copied to CRAM at the next vertical
+/// blank.
+pub struct PaletteFader {
+    start: [RgbPalette; NUM_PALETTES],
+    current: [RgbPalette; NUM_PALETTES],
+    target: [RgbPalette; NUM_PALETTES],
+    dirty: [u16; NUM_PALETTES],
+    frame: u8,
+    duration: u8,
+}
+
+impl PaletteFader {
+    /// Create a new fader, assuming CRAM currently contains `palettes`.
+    pub fn new(palettes: [RgbPalette; NUM_PALETTES]) -> PaletteFader {
+        PaletteFader {
+            start: palettes,
+            current: palettes,
+            target: palettes,
+            dirty: [0; NUM_PALETTES],
+            frame: 0,
+            duration: 0,
+        }
+    }
+
+    /// Fetch the palettes as they currently are.
+    pub fn current(&self) -> &[RgbPalette; NUM_PALETTES] { &self.current }
+
+    /// Fetch the palettes being faded towards.
+    pub fn target(&self) -> &[RgbPalette; NUM_PALETTES] { &self.target }
+
+    /// Returns true if a fade is still in progress.
+    pub fn is_fading(&self) -> bool { self.frame < self.duration }
+
+    /// Returns true if there are colours waiting to be uploaded.
+    pub fn is_dirty(&self) -> bool { self.dirty.iter().any(|&d| d != 0) }
+
+    /// Immediately set the palettes, without fading.
+    pub fn set(&mut self, palettes: &[RgbPalette; NUM_PALETTES]) {
+        self.fade_to(palettes, 0);
+    }
+
+    /// Fade from the current palettes to `palettes` over a number of frames.
+    pub fn fade_to(&mut self, palettes: &[RgbPalette; NUM_PALETTES], frames: u8) {
+        self.start = self.current;
+        self.target = *palettes;
+        self.frame = 0;
+        self.duration = frames;
+
+        if frames == 0 {
+            self.apply(|_, _, target| target);
+        }
+    }
+
+    /// Fade from `from` to `to` over a number of frames.
+    ///
+    /// The first frame of the fade shows `from`.
+    pub fn cross_fade(&mut self, from: &[RgbPalette; NUM_PALETTES], to: &[RgbPalette; NUM_PALETTES], frames: u8) {
+        let from = *from;
+        self.apply(|p, c, _| from[p][c]);
+        self.fade_to(to, frames);
+    }
+
+    /// Fade every colour to black over a number of frames.
+    pub fn fade_to_black(&mut self, frames: u8) {
+        self.fade_to(&[RgbPalette([Color::new(0, 0, 0); NUM_COLORS]); NUM_PALETTES], frames);
+    }
+
+    /// Fade every colour to white over a number of frames.
+    pub fn fade_to_white(&mut self, frames: u8) {
+        self.fade_to(&[RgbPalette([Color::new(7, 7, 7); NUM_COLORS]); NUM_PALETTES], frames);
+    }
+
+    /// Advance the fade by a single frame.
+    ///
+    /// Returns true if the fade is still in progress afterwards.
+    pub fn step(&mut self) -> bool {
+        if !self.is_fading() {
+            return false;
+        }
+
+        self.frame += 1;
+        let frame = self.frame as i16;
+        let duration = self.duration as i16;
+        let start = self.start;
+
+        self.apply(|p, c, target| {
+            let from = start[p][c];
+            let lerp = |a: u8, b: u8| {
+                let delta = b as i16 - a as i16;
+                (a as i16 + (delta * frame) / duration) as u8
+            };
+            Color::new(lerp(from.r(), target.r()),
+                       lerp(from.g(), target.g()),
+                       lerp(from.b(), target.b()))
+        });
+
+        self.is_fading()
+    }
+
+    /// Queue any changed colours to be copied to CRAM when `queue` is next
+    /// flushed.
+    ///
+    /// Writing CRAM whilst the display is active causes CRAM dots, so this
+    /// should be preferred over `upload` when called from game logic. Colours
+    /// which do not fit in the queue stay dirty and are queued by the next
+    /// call. Returns false if the queue filled up.
+    ///
+    /// # Safety
+    /// The queue reads the colours from this fader when it is flushed, so the
+    /// fader must not be moved or dropped until then.
+    pub unsafe fn queue(&mut self, queue: &mut DmaQueue) -> bool {
+        for (p, dirty) in self.dirty.iter_mut().enumerate() {
+            let mut c = 0;
+            while c < NUM_COLORS {
+                if (*dirty & (1 << c)) == 0 {
+                    c += 1;
+                    continue;
+                }
+
+                // Queue each run of changes as a single transfer.
+                let start = c;
+                while c < NUM_COLORS && (*dirty & (1 << c)) != 0 {
+                    c += 1;
+                }
+
+                let src = &self.current[p][start] as *const Color as *const ();
+                let addr = ((p << 5) | (start << 1)) as u16;
+                if !queue.push_raw(AddrKind::CRAM, addr, src, (c - start) as u16) {
+                    return false;
+                }
+
+                *dirty &= !(((1u32 << c) - (1u32 << start)) as u16);
+            }
+        }
+
+        true
+    }
+
+    /// Write any changed colours to CRAM immediately.
+    ///
+    /// This must only be called during vertical blank, otherwise use `queue`.
+    pub fn upload(&mut self, vdp: &mut VDP) {
+        vdp.set_increment(2);
+
+        for (p, dirty) in self.dirty.iter_mut().enumerate() {
+            let mut mask = *dirty;
+            let mut next = None;
+
+            for c in 0..NUM_COLORS {
+                if (mask & 1) != 0 {
+                    // Only set the address at the start of a run of changes.
+                    if next != Some(c) {
+                        vdp.set_address(AddrKind::CRAM, ((p << 5) | (c << 1)) as u16);
+                    }
+                    vdp.write_data(self.current[p][c].raw());
+                    next = Some(c + 1);
+                }
+                mask >>= 1;
+            }
+
+            *dirty = 0;
+        }
+    }
+
+    fn apply(&mut self, f: impl Fn(usize, usize, Color) -> Color) {
+        for p in 0..NUM_PALETTES {
+            for c in 0..NUM_COLORS {
+                let v = f(p, c, self.target[p][c]);
+                if v != self.current[p][c] {
+                    self.current[p][c] = v;
+                    self.dirty[p] |= 1 << c;
+                }
+            }
+        }
+    }
+}
@@ -8,6 +8,7 @@ pub mod default_ascii {
     include!(concat!(env!("OUT_DIR"), "/default_ascii.rs"));
 }
 mod font;
+pub mod fade;
 
 const MAX_SPRITES: usize = 80;
 
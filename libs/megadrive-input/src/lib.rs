@@ -1,11 +1,10 @@
 #![no_std]
 
-use megadrive_sys::ports;
-use core::ptr::read_volatile;
+use megadrive_sys::{mmio, ports};
 
 // HACK: This isn't really a NOP but it'll take at least as long as a NOP.
 fn nop() {
-    unsafe { read_volatile(0 as _) }
+    unsafe { mmio::read_u8(0); }
 }
 
 #[repr(u8)]
@@ -0,0 +1,155 @@
+use megadrive_sys::sram::ByteLane;
+
+/// The 256-byte ROM header which lives at 0x100.
+///
+/// All of the text fields are ASCII and padded with spaces. Text which is too
+/// long for its field is truncated.
+///
+/// This is built with `const` methods so that it can be placed in the ROM by
+/// the `entry!` macro:
+///
+/// ```ignore
+/// const HEADER: RomHeader = RomHeader::new()
+///     .name("MEGAPONG")
+///     .copyright("(C)RUST 2021.JAN");
+/// ```
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct RomHeader {
+    system_type: [u8; 16],
+    copyright: [u8; 16],
+    domestic_name: [u8; 48],
+    overseas_name: [u8; 48],
+    serial: [u8; 14],
+    checksum: [u8; 2],
+    io_support: [u8; 16],
+    rom_start: [u8; 4],
+    rom_end: [u8; 4],
+    ram_start: [u8; 4],
+    ram_end: [u8; 4],
+    sram: [u8; 12],
+    modem: [u8; 12],
+    memo: [u8; 40],
+    region: [u8; 16],
+}
+
+impl RomHeader {
+    /// Create a header with the default values.
+    ///
+    /// This passes TMSS, declares a 512KiB ROM with no SRAM and allows the
+    /// game to run on any region.
+    pub const fn new() -> RomHeader {
+        RomHeader {
+            system_type: ascii(b"SEGA MEGADRIVE"),
+            copyright: ascii(b"(C)2021.JAN"),
+            domestic_name: ascii(b"UNTITLED"),
+            overseas_name: ascii(b"UNTITLED"),
+            serial: ascii(b"GM MK-0000 -01"),
+            checksum: [0; 2],
+            io_support: ascii(b"J6"),
+            rom_start: 0u32.to_be_bytes(),
+            rom_end: 0x7ffffu32.to_be_bytes(),
+            ram_start: 0xff0000u32.to_be_bytes(),
+            ram_end: 0xffffffu32.to_be_bytes(),
+            sram: ascii(b""),
+            modem: ascii(b""),
+            memo: ascii(b""),
+            region: ascii(b"F"),
+        }
+    }
+
+    /// Set the system type.
+    ///
+    /// This must start with "SEGA" for the game to pass TMSS.
+    pub const fn system_type(mut self, v: &str) -> Self {
+        self.system_type = ascii(v.as_bytes());
+        self
+    }
+
+    /// Set the copyright & release date, usually of the form "(C)XXXX YYYY.MMM".
+    pub const fn copyright(mut self, v: &str) -> Self {
+        self.copyright = ascii(v.as_bytes());
+        self
+    }
+
+    /// Set both the domestic & overseas names.
+    pub const fn name(self, v: &str) -> Self {
+        self.domestic_name(v).overseas_name(v)
+    }
+
+    /// Set the domestic (Japanese) name.
+    pub const fn domestic_name(mut self, v: &str) -> Self {
+        self.domestic_name = ascii(v.as_bytes());
+        self
+    }
+
+    /// Set the overseas name.
+    pub const fn overseas_name(mut self, v: &str) -> Self {
+        self.overseas_name = ascii(v.as_bytes());
+        self
+    }
+
+    /// Set the serial number, usually of the form "GM XXXXXXXX-XX".
+    pub const fn serial(mut self, v: &str) -> Self {
+        self.serial = ascii(v.as_bytes());
+        self
+    }
+
+    /// Set the supported devices, e.g. "J6" for 3 and 6 button controllers.
+    pub const fn io_support(mut self, v: &str) -> Self {
+        self.io_support = ascii(v.as_bytes());
+        self
+    }
+
+    /// Set the address of the last byte of the ROM.
+    pub const fn rom_end(mut self, v: u32) -> Self {
+        self.rom_end = v.to_be_bytes();
+        self
+    }
+
+    /// Declare battery-backed SRAM from `start` to `end` inclusive.
+    ///
+    /// The addresses are those of the first and last usable bytes, e.g.
+    /// 0x200001 to 0x203fff for 8KiB of SRAM on the odd bytes.
+    pub const fn sram(mut self, start: u32, end: u32, lane: ByteLane) -> Self {
+        let kind = match lane {
+            ByteLane::Odd => 0xf8,
+            ByteLane::Even => 0xf0,
+            ByteLane::Both => 0xe0,
+        };
+        let start = start.to_be_bytes();
+        let end = end.to_be_bytes();
+        self.sram = [b'R', b'A', kind, 0x20,
+            start[0], start[1], start[2], start[3],
+            end[0], end[1], end[2], end[3]];
+        self
+    }
+
+    /// Set the memo field.
+    pub const fn memo(mut self, v: &str) -> Self {
+        self.memo = ascii(v.as_bytes());
+        self
+    }
+
+    /// Set the supported regions, e.g. "JUE".
+    pub const fn region(mut self, v: &str) -> Self {
+        self.region = ascii(v.as_bytes());
+        self
+    }
+}
+
+impl Default for RomHeader {
+    fn default() -> Self {
+        RomHeader::new()
+    }
+}
+
+const fn ascii<const N: usize>(s: &[u8]) -> [u8; N] {
+    let mut out = [b' '; N];
+    let mut i = 0;
+    while i < N && i < s.len() {
+        out[i] = s[i];
+        i += 1;
+    }
+    out
+}
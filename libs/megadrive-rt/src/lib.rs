@@ -0,0 +1,100 @@
+//! Runtime support for Mega Drive games.
+//!
+//! This provides everything which previously had to be written in an
+//! `entry.S` file: the vector table, the ROM header, `_start`, `abort` and
+//! `wait_for_interrupt`. A game only needs to name its entry point:
+//!
+//! ```ignore
+//! use megadrive_rt::RomHeader;
+//!
+//! megadrive_rt::entry!(main, header = RomHeader::new().name("MY GAME"));
+//!
+//! fn main() -> ! {
+//!     loop {}
+//! }
+//! ```
+//!
+//! Interrupts are dispatched to the handlers installed with
+//! `megadrive_sys::interrupt`.
+#![no_std]
+#![cfg_attr(target_arch = "m68k", feature(global_asm))]
+
+// Provides `_init_runtime` and the interrupt dispatch routines.
+extern crate megadrive_sys;
+
+mod header;
+
+pub use header::RomHeader;
+
+/// Declare the entry point of the game.
+///
+/// The function must have the signature `fn() -> !`. It is called once the
+/// runtime has been initialised, with only the vertical interrupt unmasked.
+///
+/// The ROM header can be configured by passing a `RomHeader` expression as
+/// `header`, otherwise `RomHeader::new()` is used.
+#[macro_export]
+macro_rules! entry {
+    ($path:path) => {
+        $crate::entry!($path, header = $crate::RomHeader::new());
+    };
+    ($path:path, header = $header:expr) => {
+        #[doc(hidden)]
+        #[export_name = "_megadrive_main"]
+        pub extern "C" fn __megadrive_main() -> ! {
+            let f: fn() -> ! = $path;
+            f()
+        }
+
+        #[doc(hidden)]
+        #[used]
+        #[export_name = "_rom_header"]
+        #[link_section = ".header"]
+        pub static __MEGADRIVE_ROM_HEADER: $crate::RomHeader = $header;
+    };
+}
+
+#[cfg(target_arch = "m68k")]
+global_asm!(r#"
+.section .vectors, "a"
+.long _stack_top      ; Initial stack address
+.long _start          ; Start of program code
+.rept 22              ; Processor exceptions
+.long _int
+.endr
+.long _int            ; Spurious interrupt
+.long _int            ; IRQ level 1
+.long _irq_ext        ; IRQ level 2 (external interrupt)
+.long _int            ; IRQ level 3
+.long _irq_hblank     ; IRQ level 4 (VDP horizontal interrupt)
+.long _int            ; IRQ level 5
+.long _irq_vblank     ; IRQ level 6 (VDP vertical interrupt)
+.long _int            ; IRQ level 7
+.long _trap_0, _trap_1, _trap_2, _trap_3
+.long _trap_4, _trap_5, _trap_6, _trap_7
+.long _trap_8, _trap_9, _trap_10, _trap_11
+.long _trap_12, _trap_13, _trap_14, _trap_15
+.rept 16              ; Unused on the 68000
+.long _int
+.endr
+
+.text
+.global _start
+_start:
+  move.l #_stack_top, sp
+  move.w #0x2500, sr
+  jsr _init_runtime
+  bra #_megadrive_main
+
+.global abort
+abort:
+  bra #abort
+
+_int:
+  rte
+
+.global wait_for_interrupt
+wait_for_interrupt:
+  stop #0x2500
+  rts
+"#);
@@ -0,0 +1,23 @@
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+/// Symbols from the PCM driver which are needed by `pcm.rs`.
+const PCM_SYMBOLS: &[&str] = &[
+    "TIMER_A", "ch0", "ch1", "CH_STATE", "CH_MAILBOX",
+];
+
+fn main() {
+    let program = z80asm::build("z80/pcm.s");
+
+    let mut out = String::new();
+    for name in PCM_SYMBOLS {
+        let value = program.symbol(name)
+            .unwrap_or_else(|| panic!("PCM driver does not define `{}`", name));
+        writeln!(out, "const {}: u16 = 0x{:04x};", name.to_ascii_uppercase(), value).unwrap();
+    }
+
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("pcm_symbols.rs"), out).unwrap();
+}
@@ -1,10 +1,10 @@
-use core::ptr::{write_volatile, read_volatile};
+use crate::mmio;
 
-const FM_BASE: *mut u8 = 0xa04000 as _;
+const FM_BASE: u32 = 0xa04000;
 const FM_LFO: u8 = 0x22;
 const FM_TIMER_A_HI: u8 = 0x24;
 const FM_TIMER_A_LO: u8 = 0x25;
-const FM_TIMER_B: u8 = 0x25;
+const FM_TIMER_B: u8 = 0x26;
 const FM_TIMER_CTRL: u8 = 0x27;
 const FM_KEY_ON: u8 = 0x28;
 const FM_DAC_DATA: u8 = 0x2a;
@@ -21,8 +21,17 @@ const FM_FREQUENCY_HI: u8 = 0xa4;
 const FM_ALGORITHM: u8 = 0xb0;
 const FM_PANNING: u8 = 0xb4;
 
-static FM_SPECIAL_FREQUENCY_LO: [u8; 4] = [0xa9, 0xaa, 0xa8, 0xa2];
-static FM_SPECIAL_FREQUENCY_HI: [u8; 4] = [0xad, 0xae, 0xac, 0xa6];
+// These are in register order, like `Operator`.
+static FM_SPECIAL_FREQUENCY_LO: [u8; 4] = [0xa9, 0xa8, 0xaa, 0xa2];
+static FM_SPECIAL_FREQUENCY_HI: [u8; 4] = [0xad, 0xac, 0xae, 0xa6];
+
+/// The first register held in the shadow.
+const SHADOW_BASE: u8 = 0x20;
+/// The number of registers held in the shadow, up to 0xb7.
+const SHADOW_SIZE: usize = 0xb8 - SHADOW_BASE as usize;
+
+/// A copy of every register written, since the YM2612 cannot be read.
+static mut SHADOW: [[u8; SHADOW_SIZE]; 2] = [[0; SHADOW_SIZE]; 2];
 
 static ALL_CHANNELS: [u8; 6] = [0, 1, 2, 4, 5, 6];
 const NUM_CHANNELS: u8 = 6;
@@ -41,7 +50,8 @@ pub enum Panning {
 /// Note frequencies for use with the YM2612.
 ///
 /// These are slightly inaccurate since the NTSC & PAL versions of the console
-/// operate the FM chip at slightly different frequencies.
+/// operate the FM chip at slightly different frequencies. `pitch::Clock::fm`
+/// gives exact values for either.
 #[repr(u16)]
 #[derive(Clone, Copy, Debug)]
 pub enum Note {
@@ -65,6 +75,30 @@ impl From<Note> for u16 {
     }
 }
 
+impl Panning {
+    fn from_bits(v: u8) -> Panning {
+        match v & 3 {
+            0b00 => Panning::None,
+            0b10 => Panning::Left,
+            0b01 => Panning::Right,
+            _ => Panning::Both,
+        }
+    }
+}
+
+/// The mode of channel 3.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ch3Mode {
+    /// Channel 3 behaves like the other channels.
+    Normal,
+    /// Each operator of channel 3 has its own frequency, set with
+    /// `Operator::set_frequency_special`.
+    Special,
+    /// Special mode, where timer A also keys channel 3 on each time it
+    /// overflows.
+    Csm,
+}
+
 /// Frequency enumeration for the Low-Frequency Oscillator.
 #[repr(u8)]
 #[derive(Clone, Copy, Debug)]
@@ -126,7 +160,7 @@ impl TimerConfig {
         let v = if v {
             self.0 | 0x10
         } else {
-            self.0 &! 0x10
+            self.0 & !0x10
         };
         TimerConfig(v)
     }
@@ -136,16 +170,207 @@ impl TimerConfig {
         let v = if v {
             self.0 | 0x20
         } else {
-            self.0 &! 0x20
+            self.0 & !0x20
         };
         TimerConfig(v)
     }
 }
 
+/// The settings for a single operator of an `FmPatch`.
+///
+/// The fields are as described on the matching `Operator` setters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FmOperator {
+    pub multiply: u8,
+    pub detune: u8,
+    pub total_level: u8,
+    pub attack_rate: u8,
+    pub rate_scale: u8,
+    pub decay_rate: u8,
+    pub amplitude_modulation: bool,
+    pub sustain_rate: u8,
+    pub sustain_level: u8,
+    pub release_rate: u8,
+    pub ssg_eg: u8,
+}
+
+impl FmOperator {
+    /// Create an operator with every setting at zero.
+    ///
+    /// With an attack rate of zero, the operator is silent.
+    pub const fn new() -> FmOperator {
+        FmOperator {
+            multiply: 0,
+            detune: 0,
+            total_level: 0,
+            attack_rate: 0,
+            rate_scale: 0,
+            decay_rate: 0,
+            amplitude_modulation: false,
+            sustain_rate: 0,
+            sustain_level: 0,
+            release_rate: 0,
+            ssg_eg: 0,
+        }
+    }
+
+    /// Set the multiplier & detuning.
+    pub const fn multiplier(mut self, multiply: u8, detune: u8) -> FmOperator {
+        self.multiply = multiply & 0xf;
+        self.detune = detune & 7;
+        self
+    }
+
+    /// Set the total level (volume).
+    pub const fn total_level(mut self, level: u8) -> FmOperator {
+        self.total_level = level & 0x7f;
+        self
+    }
+
+    /// Set the attack rate and attack rate scaling.
+    pub const fn attack_rate(mut self, attack_rate: u8, rate_scale: u8) -> FmOperator {
+        self.attack_rate = attack_rate & 0x1f;
+        self.rate_scale = rate_scale & 3;
+        self
+    }
+
+    /// Set the decay rate and whether amplitude modulation is enabled.
+    pub const fn decay_rate(mut self, decay_rate: u8, amon: bool) -> FmOperator {
+        self.decay_rate = decay_rate & 0x1f;
+        self.amplitude_modulation = amon;
+        self
+    }
+
+    /// Set the sustain rate.
+    pub const fn sustain_rate(mut self, sustain_rate: u8) -> FmOperator {
+        self.sustain_rate = sustain_rate & 0x1f;
+        self
+    }
+
+    /// Set the release rate and sustain level.
+    pub const fn release_rate(mut self, release_rate: u8, sustain_level: u8) -> FmOperator {
+        self.release_rate = release_rate & 0xf;
+        self.sustain_level = sustain_level & 0xf;
+        self
+    }
+
+    /// Set the SSG-EG mode.
+    pub const fn ssg_eg(mut self, value: u8) -> FmOperator {
+        self.ssg_eg = value & 0xf;
+        self
+    }
+}
+
+impl Default for FmOperator {
+    fn default() -> Self {
+        FmOperator::new()
+    }
+}
+
+/// A complete FM instrument, which can be applied to a channel at once.
+///
+/// The operators are in register order, the same as `Channel::operator`,
+/// which is operators 1, 3, 2 & 4 in most documentation.
+#[derive(Clone, Copy, Debug)]
+pub struct FmPatch {
+    pub algorithm: u8,
+    pub feedback: u8,
+    pub panning: Panning,
+    pub ams: u8,
+    pub fms: u8,
+    pub operators: [FmOperator; 4],
+}
+
+impl FmPatch {
+    /// Create a patch which plays on both speakers, without modulation.
+    pub const fn new(algorithm: u8, feedback: u8, operators: [FmOperator; 4]) -> FmPatch {
+        FmPatch {
+            algorithm: algorithm & 7,
+            feedback: feedback & 7,
+            panning: Panning::Both,
+            ams: 0,
+            fms: 0,
+            operators,
+        }
+    }
+
+    /// Set the panning.
+    pub const fn panning(mut self, panning: Panning) -> FmPatch {
+        self.panning = panning;
+        self
+    }
+
+    /// Set the sensitivity to the LFO's amplitude & frequency modulation.
+    pub const fn modulation(mut self, ams: u8, fms: u8) -> FmPatch {
+        self.ams = ams & 3;
+        self.fms = fms & 7;
+        self
+    }
+
+    /// A bright electric piano.
+    pub const PIANO: FmPatch = FmPatch::new(2, 6, [
+        FmOperator::new().multiplier(1, 7).total_level(35).attack_rate(31, 1)
+            .decay_rate(5, false).sustain_rate(2).release_rate(1, 1),
+        FmOperator::new().multiplier(13, 0).total_level(45).attack_rate(25, 2)
+            .decay_rate(5, false).sustain_rate(2).release_rate(1, 1),
+        FmOperator::new().multiplier(3, 3).total_level(38).attack_rate(31, 1)
+            .decay_rate(5, false).sustain_rate(2).release_rate(1, 1),
+        FmOperator::new().multiplier(1, 0).total_level(0).attack_rate(20, 2)
+            .decay_rate(7, false).sustain_rate(2).release_rate(6, 10),
+    ]);
+
+    /// A punchy synth bass.
+    pub const BASS: FmPatch = FmPatch::new(0, 5, [
+        FmOperator::new().multiplier(0, 3).total_level(30).attack_rate(31, 0)
+            .decay_rate(12, false).sustain_rate(4).release_rate(15, 3),
+        FmOperator::new().multiplier(1, 0).total_level(24).attack_rate(31, 0)
+            .decay_rate(10, false).sustain_rate(3).release_rate(15, 4),
+        FmOperator::new().multiplier(0, 5).total_level(20).attack_rate(31, 0)
+            .decay_rate(8, false).sustain_rate(2).release_rate(15, 2),
+        FmOperator::new().multiplier(1, 0).total_level(0).attack_rate(31, 1)
+            .decay_rate(6, false).sustain_rate(1).release_rate(10, 2),
+    ]);
+
+    /// A brass section, with a slow attack on the modulators.
+    pub const BRASS: FmPatch = FmPatch::new(4, 5, [
+        FmOperator::new().multiplier(1, 3).total_level(27).attack_rate(16, 1)
+            .decay_rate(6, false).sustain_rate(0).release_rate(7, 2),
+        FmOperator::new().multiplier(1, 5).total_level(25).attack_rate(14, 1)
+            .decay_rate(6, false).sustain_rate(0).release_rate(7, 2),
+        FmOperator::new().multiplier(1, 0).total_level(6).attack_rate(22, 1)
+            .decay_rate(4, false).sustain_rate(0).release_rate(7, 1),
+        FmOperator::new().multiplier(1, 0).total_level(4).attack_rate(20, 1)
+            .decay_rate(4, false).sustain_rate(0).release_rate(7, 1),
+    ]);
+
+    /// A short, noisy drum hit, which sounds like a kick at low pitches and a
+    /// snare at high ones.
+    pub const DRUMS: FmPatch = FmPatch::new(4, 7, [
+        FmOperator::new().multiplier(15, 0).total_level(14).attack_rate(31, 0)
+            .decay_rate(18, false).sustain_rate(12).release_rate(15, 15),
+        FmOperator::new().multiplier(2, 0).total_level(16).attack_rate(31, 0)
+            .decay_rate(22, false).sustain_rate(14).release_rate(15, 15),
+        FmOperator::new().multiplier(1, 0).total_level(0).attack_rate(31, 0)
+            .decay_rate(16, false).sustain_rate(10).release_rate(15, 15),
+        FmOperator::new().multiplier(0, 0).total_level(2).attack_rate(31, 0)
+            .decay_rate(12, false).sustain_rate(8).release_rate(15, 15),
+    ]);
+}
+
+/// A saved copy of the YM2612's registers.
+///
+/// This is taken from the shadow, so it only includes writes made through
+/// `FM`.
+#[derive(Clone)]
+pub struct FmState([[u8; SHADOW_SIZE]; 2]);
+
 /// A driver for the YM2612.
 ///
 /// Whilst normally controlled by the Z80, this driver can be used to operate
 /// the YM2612 from the 68k.
+///
+/// Every register write is also stored in a shadow in RAM, which is used by the
+/// getters. Registers written by the Z80 are not tracked.
 pub struct FM;
 
 impl FM {
@@ -166,7 +391,7 @@ impl FM {
             ch.set_key(false);
 
             for op in ch.operators() {
-                op.set_ssgeg(0);
+                op.set_ssg_eg(0);
             }
         }
 
@@ -175,14 +400,18 @@ impl FM {
 
     fn write_reg_bank(&self, second: bool, addr: u8, value: u8) {
         let reg_offset = if second { 2 } else { 0 };
+        if addr >= SHADOW_BASE && (addr - SHADOW_BASE) < SHADOW_SIZE as u8 {
+            unsafe { SHADOW[second as usize][(addr - SHADOW_BASE) as usize] = value };
+        }
+
         unsafe {
-            let base = FM_BASE.offset(reg_offset);
+            let base = FM_BASE + reg_offset;
 
             // Busy spin until idle.
-            while (read_volatile(FM_BASE) & 0x80) != 0 {}
+            while (mmio::read_u8(FM_BASE) & 0x80) != 0 {}
 
-            write_volatile(base, addr);
-            write_volatile(base.offset(1), value);
+            mmio::write_u8(base, addr);
+            mmio::write_u8(base + 1, value);
         }
     }
 
@@ -190,6 +419,70 @@ impl FM {
         self.write_reg_bank(false, addr, value);
     }
 
+    fn read_reg_bank(&self, second: bool, addr: u8) -> u8 {
+        unsafe { SHADOW[second as usize][(addr - SHADOW_BASE) as usize] }
+    }
+
+    fn read_reg(&self, addr: u8) -> u8 {
+        self.read_reg_bank(false, addr)
+    }
+
+    /// Write a raw register, in the first bank or the second.
+    ///
+    /// This is for playing back register logs. The write is recorded in the
+    /// shadow like any other.
+    pub fn write_register(&self, second: bool, addr: u8, value: u8) {
+        self.write_reg_bank(second, addr, value);
+    }
+
+    /// Read back the last value written to a register from the shadow.
+    pub fn register(&self, second: bool, addr: u8) -> u8 {
+        assert!(addr >= SHADOW_BASE && addr < SHADOW_BASE + SHADOW_SIZE as u8, "register is not shadowed");
+        self.read_reg_bank(second, addr)
+    }
+
+    /// Take a copy of the chip's registers.
+    pub fn save(&self) -> FmState {
+        FmState(unsafe { SHADOW })
+    }
+
+    /// Restore the registers from a saved copy.
+    ///
+    /// This restores the LFO, the DAC enable, the channel 3 mode and every
+    /// channel & operator register. The timers and keys are left alone.
+    pub fn restore(&self, state: &FmState) {
+        for (second, regs) in [false, true].iter().cloned().zip(state.0.iter()) {
+            let reg = |addr: u8| regs[(addr - SHADOW_BASE) as usize];
+            if !second {
+                self.write_reg(FM_LFO, reg(FM_LFO));
+                self.write_reg(FM_DAC_ENABLE, reg(FM_DAC_ENABLE));
+
+                // As in `set_ch3_mode`, the timer bits are kept as they are now.
+                let ctrl = (self.read_reg(FM_TIMER_CTRL) & 0x0f) | (reg(FM_TIMER_CTRL) & 0xc0);
+                self.write_reg(FM_TIMER_CTRL, ctrl);
+            }
+
+            for addr in (FM_MULTIPLY..=0x9e).chain(FM_ALGORITHM..=0xb6) {
+                // There are only 3 channels per bank.
+                if (addr & 3) != 3 {
+                    self.write_reg_bank(second, addr, reg(addr));
+                }
+            }
+
+            // The high byte of each frequency is latched by writing the low byte.
+            for ch in 0..3 {
+                self.write_reg_bank(second, FM_FREQUENCY_HI + ch, reg(FM_FREQUENCY_HI + ch));
+                self.write_reg_bank(second, FM_FREQUENCY_LO + ch, reg(FM_FREQUENCY_LO + ch));
+            }
+            if !second {
+                for (&hi, &lo) in FM_SPECIAL_FREQUENCY_HI.iter().zip(FM_SPECIAL_FREQUENCY_LO.iter()).take(3) {
+                    self.write_reg(hi, reg(hi));
+                    self.write_reg(lo, reg(lo));
+                }
+            }
+        }
+    }
+
     /// Enable or disable the LFO unit.
     ///
     /// This enables and sets the frequency of the low-frequency-oscillator.
@@ -200,12 +493,35 @@ impl FM {
         self.write_reg(FM_LFO, v);
     }
 
+    /// Get the LFO frequency, if it is enabled.
+    pub fn lfo(&self) -> Option<LFORate> {
+        let v = self.read_reg(FM_LFO);
+        if (v & 8) == 0 {
+            return None;
+        }
+        Some(match v & 7 {
+            0b000 => LFORate::F3_82Hz,
+            0b001 => LFORate::F5_33Hz,
+            0b010 => LFORate::F5_77Hz,
+            0b011 => LFORate::F6_11Hz,
+            0b100 => LFORate::F6_60Hz,
+            0b101 => LFORate::F9_23Hz,
+            0b110 => LFORate::F46_11Hz,
+            _ => LFORate::F69_22Hz,
+        })
+    }
+
     /// Enables or disables the DAc, which replaces channel 6.
     pub fn enable_dac(&self, enabled: bool) {
         let v = if enabled { 0x80 } else { 0 };
         self.write_reg(FM_DAC_ENABLE, v);
     }
 
+    /// Returns true if the DAC is enabled.
+    pub fn dac_enabled(&self) -> bool {
+        (self.read_reg(FM_DAC_ENABLE) & 0x80) != 0
+    }
+
     /// Write a single sample to the DAC.
     pub fn dac_write(&self, v: u8) {
         self.write_reg(FM_DAC_DATA, v);
@@ -222,6 +538,11 @@ impl FM {
         self.write_reg(FM_TIMER_A_LO, lo);
     }
 
+    /// Get the frequency of timer A.
+    pub fn timer_a(&self) -> u16 {
+        ((self.read_reg(FM_TIMER_A_HI) as u16) << 2) | (self.read_reg(FM_TIMER_A_LO) & 3) as u16
+    }
+
     /// Configure the frequency of timer B.
     ///
     /// The interval is calculated as `(256 - f) * 288 us`.
@@ -229,14 +550,40 @@ impl FM {
         self.write_reg(FM_TIMER_B, f);
     }
 
+    /// Get the frequency of timer B.
+    pub fn timer_b(&self) -> u8 {
+        self.read_reg(FM_TIMER_B)
+    }
+
     /// Configure the timers.
     pub fn configure_timers(&self, c: TimerConfig) {
         self.write_reg(FM_TIMER_CTRL, c.0)
     }
 
+    /// Set the mode of channel 3, leaving the timers alone.
+    pub fn set_ch3_mode(&self, mode: Ch3Mode) {
+        let bits = match mode {
+            Ch3Mode::Normal => 0,
+            Ch3Mode::Special => 0x40,
+            Ch3Mode::Csm => 0x80,
+        };
+        // Don't write the reset bits again, which would clear the timer flags.
+        let v = (self.read_reg(FM_TIMER_CTRL) & 0x0f) | bits;
+        self.write_reg(FM_TIMER_CTRL, v);
+    }
+
+    /// Get the mode of channel 3.
+    pub fn ch3_mode(&self) -> Ch3Mode {
+        match self.read_reg(FM_TIMER_CTRL) >> 6 {
+            0 => Ch3Mode::Normal,
+            1 => Ch3Mode::Special,
+            _ => Ch3Mode::Csm,
+        }
+    }
+
     /// Check whether the timers have completed.
     pub fn timer_status(&self) -> (bool, bool) {
-        let v = unsafe { read_volatile(FM_BASE) };
+        let v = unsafe { mmio::read_u8(FM_BASE) };
         let a = (v & 1) != 0;
         let b = (v & 2) != 0;
         (a, b)
@@ -244,7 +591,8 @@ impl FM {
 
     /// Fetch a single FM channel.
     pub fn channel(&self, channel: u8) -> Channel {
-        Channel(FM, channel & 7)
+        assert!(channel < NUM_CHANNELS, "invalid FM channel");
+        Channel(FM, channel)
     }
 
     /// Fetch all of the channels of the FM chip.
@@ -259,17 +607,33 @@ impl FM {
 pub struct Channel(FM, u8);
 
 impl Channel {
-    fn write_reg(&self, base: u8, value: u8) {
+    /// Find the bank & address of one of this channel's registers.
+    fn reg_addr(&self, base: u8) -> (bool, u8) {
         let channel = self.1;
-        let (channel, second) = if channel > 3 {
+        let (channel, second) = if channel >= 3 {
             (channel - 3, true)
         } else {
             (channel, false)
         };
-        let addr = base + (channel & 3);
+        (second, base + (channel & 3))
+    }
+
+    fn write_reg(&self, base: u8, value: u8) {
+        let (second, addr) = self.reg_addr(base);
         self.0.write_reg_bank(second, addr, value);
     }
 
+    fn read_reg(&self, base: u8) -> u8 {
+        let (second, addr) = self.reg_addr(base);
+        self.0.read_reg_bank(second, addr)
+    }
+
+    /// Change some bits of a register, keeping the rest.
+    fn modify_reg(&self, base: u8, mask: u8, value: u8) {
+        let v = (self.read_reg(base) & !mask) | (value & mask);
+        self.write_reg(base, v);
+    }
+
     /// Set whether the key is down for a channel.
     ///
     /// This version allows setting key down individually per-operator, however
@@ -300,18 +664,101 @@ impl Channel {
         self.write_reg(FM_FREQUENCY_LO, lo);
     }
 
+    /// Get the frequency & octave of the channel.
+    pub fn frequency(&self) -> (u16, u8) {
+        let hi = self.read_reg(FM_FREQUENCY_HI);
+        let lo = self.read_reg(FM_FREQUENCY_LO);
+        ((((hi & 7) as u16) << 8) | lo as u16, (hi >> 3) & 7)
+    }
+
     /// Set the algorithm and feedback for a bank.
     pub fn set_algorithm(&self, algorithm: u8, feedback: u8) {
         let v = (algorithm & 7) | ((feedback & 7) << 3);
         self.write_reg(FM_ALGORITHM, v);
     }
 
+    /// Set the algorithm, keeping the feedback.
+    ///
+    /// This is named for the CON field in the YM2612's documentation, as
+    /// `set_algorithm` also sets the feedback.
+    pub fn set_connection(&self, algorithm: u8) {
+        self.modify_reg(FM_ALGORITHM, 0x07, algorithm);
+    }
+
+    /// Set the feedback of the first operator, keeping the algorithm.
+    pub fn set_feedback(&self, feedback: u8) {
+        self.modify_reg(FM_ALGORITHM, 0x38, feedback << 3);
+    }
+
+    /// Get the algorithm of the channel.
+    pub fn algorithm(&self) -> u8 {
+        self.read_reg(FM_ALGORITHM) & 7
+    }
+
+    /// Get the feedback of the first operator.
+    pub fn feedback(&self) -> u8 {
+        (self.read_reg(FM_ALGORITHM) >> 3) & 7
+    }
+
     /// Set the panning, frequency modulation and amplituate modulation for a bank.
     pub fn set_panning(&self, panning: Panning, ams: u8, fms: u8) {
         let v = ((panning as u8) << 6) | ((ams & 3) << 4) | (fms & 7);
         self.write_reg(FM_PANNING, v);
     }
 
+    /// Set the panning, keeping the modulation sensitivity.
+    pub fn set_pan(&self, panning: Panning) {
+        self.modify_reg(FM_PANNING, 0xc0, (panning as u8) << 6);
+    }
+
+    /// Set the sensitivity to the LFO's amplitude modulation.
+    pub fn set_ams(&self, ams: u8) {
+        self.modify_reg(FM_PANNING, 0x30, ams << 4);
+    }
+
+    /// Set the sensitivity to the LFO's frequency modulation.
+    pub fn set_fms(&self, fms: u8) {
+        self.modify_reg(FM_PANNING, 0x07, fms);
+    }
+
+    /// Get the panning of the channel.
+    pub fn panning(&self) -> Panning {
+        Panning::from_bits(self.read_reg(FM_PANNING) >> 6)
+    }
+
+    /// Get the channel's sensitivity to the LFO's amplitude modulation.
+    pub fn ams(&self) -> u8 {
+        (self.read_reg(FM_PANNING) >> 4) & 3
+    }
+
+    /// Get the channel's sensitivity to the LFO's frequency modulation.
+    pub fn fms(&self) -> u8 {
+        self.read_reg(FM_PANNING) & 7
+    }
+
+    /// Configure the channel and all of its operators from a patch.
+    ///
+    /// The channel should be keyed off first.
+    pub fn apply_patch(&self, patch: &FmPatch) {
+        self.set_algorithm(patch.algorithm, patch.feedback);
+        self.set_panning(patch.panning, patch.ams, patch.fms);
+        for (op, settings) in self.operators().zip(patch.operators.iter()) {
+            op.apply(settings);
+        }
+    }
+
+    /// Get the current settings of the channel as a patch.
+    pub fn patch(&self) -> FmPatch {
+        let mut operators = [FmOperator::new(); 4];
+        for (settings, op) in operators.iter_mut().zip(self.operators()) {
+            *settings = op.settings();
+        }
+
+        FmPatch::new(self.algorithm(), self.feedback(), operators)
+            .panning(self.panning())
+            .modulation(self.ams(), self.fms())
+    }
+
     /// Get one of this channel's operators.
     pub fn operator(&self, operator: u8) -> Operator {
         Operator(FM, self.1, operator & 3)
@@ -330,33 +777,77 @@ impl Channel {
 pub struct Operator(FM, u8, u8);
 
 impl Operator {
-    fn write_reg(&self, base: u8, value: u8) {
+    /// Find the bank & address of one of this operator's registers.
+    fn reg_addr(&self, base: u8) -> (bool, u8) {
         let channel = self.1;
         let op = self.2;
-        let (channel, second) = if channel > 3 {
+        let (channel, second) = if channel >= 3 {
             (channel - 3, true)
         } else {
             (channel, false)
         };
-        let addr = base | (channel & 3) | ((op & 3) << 2);
+        (second, base | (channel & 3) | ((op & 3) << 2))
+    }
+
+    fn write_reg(&self, base: u8, value: u8) {
+        let (second, addr) = self.reg_addr(base);
         self.0.write_reg_bank(second, addr, value);
     }
 
+    fn read_reg(&self, base: u8) -> u8 {
+        let (second, addr) = self.reg_addr(base);
+        self.0.read_reg_bank(second, addr)
+    }
+
+    /// Change some bits of a register, keeping the rest.
+    fn modify_reg(&self, base: u8, mask: u8, value: u8) {
+        let v = (self.read_reg(base) & !mask) | (value & mask);
+        self.write_reg(base, v);
+    }
+
     /// Set the operator multiplier & detuning.
     ///
     /// The multiply value is as written, except that 0 means 0.5.
+    ///
+    /// The detune is 0-3 to raise the frequency, or 4-7 to lower it by the
+    /// same amounts.
     pub fn set_multiplier(&self, multiply: u8, detune: u8) {
         let v = ((detune & 7) << 4) | (multiply & 0xf);
         self.write_reg(FM_MULTIPLY, v);
     }
 
+    /// Set the multiplier, keeping the detuning.
+    pub fn set_multiply(&self, multiply: u8) {
+        self.modify_reg(FM_MULTIPLY, 0x0f, multiply);
+    }
+
+    /// Set the detuning, keeping the multiplier.
+    pub fn set_detune(&self, detune: u8) {
+        self.modify_reg(FM_MULTIPLY, 0x70, detune << 4);
+    }
+
+    /// Get the multiplier.
+    pub fn multiply(&self) -> u8 {
+        self.read_reg(FM_MULTIPLY) & 0xf
+    }
+
+    /// Get the detuning.
+    pub fn detune(&self) -> u8 {
+        (self.read_reg(FM_MULTIPLY) >> 4) & 7
+    }
+
     /// Set the total level (volume) of a single operator.
     ///
-    /// The maximum value is 127.
+    /// The maximum value is 127, which is silent. Each step is 0.75dB.
     pub fn set_total_level(&self, level: u8) {
         self.write_reg(FM_TOTAL_LEVEL, level & 0x7f);
     }
 
+    /// Get the total level.
+    pub fn total_level(&self) -> u8 {
+        self.read_reg(FM_TOTAL_LEVEL) & 0x7f
+    }
+
     /// Set the attack rate and attack rate scaling for an operator.
     ///
     /// The attack rate defines how quickly the operator reaches maximum amplitude
@@ -365,20 +856,62 @@ impl Operator {
     /// The attack rate scale is used to increase the attack rate at higher frequencies.
     /// 0 indicates no increase, increasing the value increases the attack rate.
     pub fn set_attack_rate(&self, attack_rate: u8, rate_scale: u8) {
-        let v = (attack_rate & 0x1f) | (rate_scale << 6);
+        let v = (attack_rate & 0x1f) | ((rate_scale & 3) << 6);
         self.write_reg(FM_ATTACK_RATE, v);
     }
 
+    /// Set the attack rate, keeping the rate scaling.
+    pub fn set_attack(&self, attack_rate: u8) {
+        self.modify_reg(FM_ATTACK_RATE, 0x1f, attack_rate);
+    }
+
+    /// Set the rate scaling, keeping the attack rate.
+    pub fn set_rate_scale(&self, rate_scale: u8) {
+        self.modify_reg(FM_ATTACK_RATE, 0xc0, rate_scale << 6);
+    }
+
+    /// Get the attack rate.
+    pub fn attack_rate(&self) -> u8 {
+        self.read_reg(FM_ATTACK_RATE) & 0x1f
+    }
+
+    /// Get the rate scaling.
+    pub fn rate_scale(&self) -> u8 {
+        self.read_reg(FM_ATTACK_RATE) >> 6
+    }
+
     /// Set the decay rate and enable amplitude modulation.
     ///
     /// The higher the decay rate, the steeper the decay, with 31 being the maximum.
     ///
-    /// If amon is true, the global amplitude modulation will be applied
+    /// If amon is true, the LFO's amplitude modulation is applied to this
+    /// operator. Its depth is set per channel by the AMS in `set_panning`, and
+    /// the LFO must be enabled with `FM::enable_lfo`.
     pub fn set_decay_rate(&self, decay_rate: u8, amon: bool) {
         let v = ((amon as u8) << 7) | (decay_rate & 0x1f);
         self.write_reg(FM_DECAY_RATE, v);
     }
 
+    /// Set the decay rate, keeping the amplitude modulation.
+    pub fn set_decay(&self, decay_rate: u8) {
+        self.modify_reg(FM_DECAY_RATE, 0x1f, decay_rate);
+    }
+
+    /// Enable amplitude modulation, keeping the decay rate.
+    pub fn set_amplitude_modulation(&self, amon: bool) {
+        self.modify_reg(FM_DECAY_RATE, 0x80, (amon as u8) << 7);
+    }
+
+    /// Get the decay rate.
+    pub fn decay_rate(&self) -> u8 {
+        self.read_reg(FM_DECAY_RATE) & 0x1f
+    }
+
+    /// Returns true if amplitude modulation is enabled.
+    pub fn amplitude_modulation(&self) -> bool {
+        (self.read_reg(FM_DECAY_RATE) & 0x80) != 0
+    }
+
     /// Set the sustain rate of the operator (also known as the second decay rate).
     ///
     /// The maximum value is 31. Higher values mean steeper decay.
@@ -387,12 +920,60 @@ impl Operator {
         self.write_reg(FM_SUSTAIN_RATE, v);
     }
 
+    /// Get the sustain rate.
+    pub fn sustain_rate(&self) -> u8 {
+        self.read_reg(FM_SUSTAIN_RATE) & 0x1f
+    }
+
     /// Set the release rate and sustain level of an operator.
     pub fn set_release_rate(&self, release_rate: u8, sustain_level: u8) {
         let v = (release_rate & 0xf) | ((sustain_level & 0xf) << 4);
         self.write_reg(FM_RELEASE_RATE, v);
     }
 
+    /// Set the release rate, keeping the sustain level.
+    pub fn set_release(&self, release_rate: u8) {
+        self.modify_reg(FM_RELEASE_RATE, 0x0f, release_rate);
+    }
+
+    /// Set the sustain level, keeping the release rate.
+    pub fn set_sustain_level(&self, sustain_level: u8) {
+        self.modify_reg(FM_RELEASE_RATE, 0xf0, sustain_level << 4);
+    }
+
+    /// Get the release rate.
+    pub fn release_rate(&self) -> u8 {
+        self.read_reg(FM_RELEASE_RATE) & 0xf
+    }
+
+    /// Get the sustain level.
+    pub fn sustain_level(&self) -> u8 {
+        self.read_reg(FM_RELEASE_RATE) >> 4
+    }
+
+    /// Configure every setting of the operator at once.
+    pub fn apply(&self, settings: &FmOperator) {
+        self.set_multiplier(settings.multiply, settings.detune);
+        self.set_total_level(settings.total_level);
+        self.set_attack_rate(settings.attack_rate, settings.rate_scale);
+        self.set_decay_rate(settings.decay_rate, settings.amplitude_modulation);
+        self.set_sustain_rate(settings.sustain_rate);
+        self.set_release_rate(settings.release_rate, settings.sustain_level);
+        self.set_ssg_eg(settings.ssg_eg);
+    }
+
+    /// Get every setting of the operator.
+    pub fn settings(&self) -> FmOperator {
+        FmOperator::new()
+            .multiplier(self.multiply(), self.detune())
+            .total_level(self.total_level())
+            .attack_rate(self.attack_rate(), self.rate_scale())
+            .decay_rate(self.decay_rate(), self.amplitude_modulation())
+            .sustain_rate(self.sustain_rate())
+            .release_rate(self.release_rate(), self.sustain_level())
+            .ssg_eg(self.ssg_eg())
+    }
+
     /// Set the frequency for a single operator.
     ///
     /// This is only valid for channel 3 in 'special' mode.
@@ -404,10 +985,24 @@ impl Operator {
         self.0.write_reg(FM_SPECIAL_FREQUENCY_LO[self.2 as usize], lo);
     }
 
-    /// Set the proprietary field.
+    /// Get the frequency & octave of a single operator in special mode.
+    pub fn frequency_special(&self) -> (u16, u8) {
+        let hi = self.0.read_reg(FM_SPECIAL_FREQUENCY_HI[self.2 as usize]);
+        let lo = self.0.read_reg(FM_SPECIAL_FREQUENCY_LO[self.2 as usize]);
+        ((((hi & 7) as u16) << 8) | lo as u16, (hi >> 3) & 7)
+    }
+
+    /// Set the SSG-EG envelope mode.
     ///
-    /// According to the documentation, this should always be set to zero.
-    fn set_ssgeg(&self, value: u8) {
-        self.write_reg(FM_SSGEG, value);
+    /// Bit 3 enables it, and bits 0-2 select the envelope shape. Official
+    /// documentation says this should always be zero, but it works on most
+    /// consoles.
+    pub fn set_ssg_eg(&self, value: u8) {
+        self.write_reg(FM_SSGEG, value & 0xf);
+    }
+
+    /// Get the SSG-EG envelope mode.
+    pub fn ssg_eg(&self) -> u8 {
+        self.read_reg(FM_SSGEG) & 0xf
     }
 }
@@ -0,0 +1,143 @@
+//! Interrupt and trap handler registration.
+//!
+//! The vector table points the VDP, external and TRAP interrupts at small
+//! dispatch routines provided by this module:
+//!
+//! | Vector                     | Symbol          |
+//! |----------------------------|-----------------|
+//! | IRQ level 2 (external)     | `_irq_ext`      |
+//! | IRQ level 4 (horizontal)   | `_irq_hblank`   |
+//! | IRQ level 6 (vertical)     | `_irq_vblank`   |
+//! | TRAP #n                    | `_trap_n`       |
+//!
+//! Each dispatch routine saves the registers the handler may clobber, calls
+//! the handler installed in a table in RAM and returns from the exception.
+//! Handlers default to doing nothing and can be replaced at any time.
+
+/// An interrupt handler.
+pub type Handler = extern "C" fn();
+
+/// The interrupts which can have handlers installed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    /// The VDP's vertical blank interrupt.
+    VBlank,
+    /// The VDP's horizontal blank interrupt.
+    HBlank,
+    /// The external interrupt, raised by the TH pin of an IO port.
+    External,
+    /// One of the 16 `TRAP #n` instructions.
+    Trap(u8),
+}
+
+impl Interrupt {
+    fn index(self) -> usize {
+        match self {
+            Interrupt::VBlank => 0,
+            Interrupt::HBlank => 1,
+            Interrupt::External => 2,
+            Interrupt::Trap(n) => 3 + (n & 0xf) as usize,
+        }
+    }
+}
+
+const NUM_HANDLERS: usize = 19;
+
+extern "C" fn default_handler() {}
+
+#[no_mangle]
+static mut IRQ_HANDLERS: [Handler; NUM_HANDLERS] = [default_handler; NUM_HANDLERS];
+
+/// Install a handler for an interrupt, returning the previous handler.
+///
+/// The new handler can call the returned one to chain the two together.
+pub fn set_handler(interrupt: Interrupt, handler: Handler) -> Handler {
+    without_interrupts(|| unsafe {
+        let slot = &mut IRQ_HANDLERS[interrupt.index()];
+        let previous = *slot;
+        *slot = handler;
+        previous
+    })
+}
+
+/// Fetch the handler currently installed for an interrupt.
+pub fn handler(interrupt: Interrupt) -> Handler {
+    unsafe { IRQ_HANDLERS[interrupt.index()] }
+}
+
+/// Remove the handler for an interrupt, returning the previous handler.
+pub fn reset_handler(interrupt: Interrupt) -> Handler {
+    set_handler(interrupt, default_handler)
+}
+
+/// Run a closure with all maskable interrupts disabled.
+///
+/// The previous interrupt mask is restored afterwards.
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let sr = unsafe { _irq_disable() };
+    let result = f();
+    unsafe { _irq_restore(sr) };
+    result
+}
+
+#[cfg(target_arch = "m68k")]
+extern "C" {
+    fn _irq_disable() -> u32;
+    fn _irq_restore(sr: u32);
+}
+
+#[cfg(not(target_arch = "m68k"))]
+unsafe fn _irq_disable() -> u32 { 0 }
+
+#[cfg(not(target_arch = "m68k"))]
+unsafe fn _irq_restore(_sr: u32) {}
+
+#[cfg(target_arch = "m68k")]
+macro_rules! dispatch {
+    ($name:literal, $offset:literal) => {
+        concat!(
+            ".global ", $name, "\n",
+            $name, ":\n",
+            "  movem.l d0-d1/a0-a1, -(sp)\n",
+            "  move.l (IRQ_HANDLERS+", $offset, "), a0\n",
+            "  jsr (a0)\n",
+            "  movem.l (sp)+, d0-d1/a0-a1\n",
+            "  rte\n",
+        )
+    };
+}
+
+#[cfg(target_arch = "m68k")]
+global_asm!(concat!(
+    ".text\n",
+    ".global _irq_disable\n",
+    "_irq_disable:\n",
+    "  moveq #0, d0\n",
+    "  move.w sr, d0\n",
+    "  ori.w #0x0700, sr\n",
+    "  rts\n",
+    ".global _irq_restore\n",
+    "_irq_restore:\n",
+    "  move.l 4(sp), d0\n",
+    "  move.w d0, sr\n",
+    "  rts\n",
+    dispatch!("_irq_vblank", "0"),
+    dispatch!("_irq_hblank", "4"),
+    dispatch!("_irq_ext", "8"),
+    dispatch!("_trap_0", "12"),
+    dispatch!("_trap_1", "16"),
+    dispatch!("_trap_2", "20"),
+    dispatch!("_trap_3", "24"),
+    dispatch!("_trap_4", "28"),
+    dispatch!("_trap_5", "32"),
+    dispatch!("_trap_6", "36"),
+    dispatch!("_trap_7", "40"),
+    dispatch!("_trap_8", "44"),
+    dispatch!("_trap_9", "48"),
+    dispatch!("_trap_10", "52"),
+    dispatch!("_trap_11", "56"),
+    dispatch!("_trap_12", "60"),
+    dispatch!("_trap_13", "64"),
+    dispatch!("_trap_14", "68"),
+    dispatch!("_trap_15", "72"),
+));
@@ -1,12 +1,21 @@
 #![no_std]
+#![cfg_attr(target_arch = "m68k", feature(global_asm))]
 
-use core::ptr::{read_volatile, write_volatile};
+#[cfg(feature = "mock")]
+extern crate std;
 
+pub mod mmio;
 pub mod z80;
 pub mod vdp;
 pub mod ports;
 pub mod fm;
+pub mod interrupt;
+pub mod mapper;
+pub mod pcm;
+pub mod pitch;
 pub mod psg;
+pub mod raster;
+pub mod sram;
 
 extern "C" {
     static _data_src: *const u32;
@@ -99,23 +108,22 @@ impl Version {
     }
 }
 
-const VERSION_REG: *mut u8 = (0xa10001) as _;
+const VERSION_REG: u32 = 0xa10001;
 
 /// Read the console version information.
 pub fn version() -> Version {
-    let v = unsafe { read_volatile(VERSION_REG) };
+    let v = unsafe { mmio::read_u8(VERSION_REG) };
     Version(v)
 }
 
 // TMSS - copy protection for the Mega Drive.
 const TMSS_CODE: &'static [u8; 4] = b"SEGA";
-const TMSS_REG: *mut u32 = 0xa14000 as _;
+const TMSS_REG: u32 = 0xa14000;
 
 fn init_tmss() {
     if version().hardware_revision() > 0 {
         unsafe {
-            let tmss_code: *const u32 = core::mem::transmute(&TMSS_CODE[0]);
-            write_volatile(TMSS_REG, *tmss_code);
+            mmio::write_u32(TMSS_REG, u32::from_be_bytes(*TMSS_CODE));
         }
     }
 }
@@ -0,0 +1,99 @@
+//! The Sega mapper, as used by Super Street Fighter II.
+//!
+//! The cartridge address space is split into eight 512KiB slots, each of
+//! which can show any 512KiB bank of a ROM up to 32MiB. Slot 0 always shows
+//! bank 0, since it contains the vector table.
+//!
+//! `cargo-megadrive` links the code & most data into banks 0 to 6, which stay
+//! mapped, and the sections `.bank.N` into bank N so that they appear in
+//! slot 7 when mapped. `Banked` provides access to that data:
+//!
+//! ```ignore
+//! #[link_section = ".bank.8"]
+//! static VOICE_DATA: [u8; 0x40000] = *include_bytes!("voice.pcm");
+//! static VOICE: Banked<[u8; 0x40000]> = unsafe { Banked::new(8, &VOICE_DATA) };
+//!
+//! VOICE.with(|data| play(data));
+//! ```
+
+use crate::mmio;
+
+/// The size of a single bank.
+pub const BANK_SIZE: u32 = 0x80000;
+
+/// The number of slots in the address space.
+pub const NUM_SLOTS: u8 = 8;
+
+/// The maximum number of banks the mapper can address.
+pub const MAX_BANKS: u8 = 64;
+
+/// The slot which banked data is mapped into.
+pub const WINDOW_SLOT: u8 = 7;
+
+/// The address at which banked data appears.
+pub const WINDOW_BASE: u32 = WINDOW_SLOT as u32 * BANK_SIZE;
+
+const REG_MAPPER_BASE: u32 = 0xa130f1;
+
+static mut BANKS: [u8; NUM_SLOTS as usize] = [0, 1, 2, 3, 4, 5, 6, 7];
+
+/// Map a bank into a slot.
+///
+/// This returns the bank which was previously mapped.
+pub fn set_bank(slot: u8, bank: u8) -> u8 {
+    assert!(slot > 0 && slot < NUM_SLOTS, "only slots 1 to 7 can be changed");
+    assert!(bank < MAX_BANKS, "only 64 banks");
+
+    unsafe {
+        let previous = BANKS[slot as usize];
+        BANKS[slot as usize] = bank;
+        mmio::write_u8(REG_MAPPER_BASE + ((slot as u32) << 1), bank);
+        previous
+    }
+}
+
+/// Fetch the bank which is mapped into a slot.
+pub fn bank(slot: u8) -> u8 {
+    assert!(slot < NUM_SLOTS, "only 8 slots");
+    unsafe { BANKS[slot as usize] }
+}
+
+/// Restore the power-on mapping, where slot N shows bank N.
+pub fn reset() {
+    for slot in 1..NUM_SLOTS {
+        set_bank(slot, slot);
+    }
+}
+
+/// A reference to data which lives in a bank.
+///
+/// The data can only be accessed inside `with`, whilst its bank is mapped.
+pub struct Banked<T: ?Sized + 'static> {
+    bank: u8,
+    data: &'static T,
+}
+
+impl<T: ?Sized + 'static> Banked<T> {
+    /// Create a reference to banked data.
+    ///
+    /// # Safety
+    /// `data` must have been placed in the `.bank.N` section, where `N` is
+    /// `bank`.
+    pub const unsafe fn new(bank: u8, data: &'static T) -> Banked<T> {
+        Banked { bank, data }
+    }
+
+    /// Fetch the bank which contains the data.
+    pub fn bank(&self) -> u8 { self.bank }
+
+    /// Map the bank in and call `f` with the data.
+    ///
+    /// The previous mapping is restored afterwards, so this can be nested
+    /// and used from interrupt handlers.
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let previous = set_bank(WINDOW_SLOT, self.bank);
+        let result = f(self.data);
+        set_bank(WINDOW_SLOT, previous);
+        result
+    }
+}
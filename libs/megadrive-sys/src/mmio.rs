@@ -0,0 +1,66 @@
+//! Access to the memory-mapped hardware registers.
+//!
+//! All hardware access in this crate goes through these functions. Normally
+//! they perform volatile reads and writes of the given address. When the
+//! `mock` feature is enabled, they are instead routed to the recorder in
+//! `mmio::mock`, which allows the drivers to be tested on a host machine.
+
+#[cfg(feature = "mock")]
+pub mod mock;
+
+#[cfg(not(feature = "mock"))]
+mod backend {
+    use core::ptr::{read_volatile, write_volatile};
+
+    pub unsafe fn read_u8(addr: u32) -> u8 { read_volatile(addr as *const u8) }
+    pub unsafe fn read_u16(addr: u32) -> u16 { read_volatile(addr as *const u16) }
+    pub unsafe fn read_u32(addr: u32) -> u32 { read_volatile(addr as *const u32) }
+    pub unsafe fn write_u8(addr: u32, v: u8) { write_volatile(addr as *mut u8, v) }
+    pub unsafe fn write_u16(addr: u32, v: u16) { write_volatile(addr as *mut u16, v) }
+    pub unsafe fn write_u32(addr: u32, v: u32) { write_volatile(addr as *mut u32, v) }
+}
+
+#[cfg(feature = "mock")]
+use mock as backend;
+
+/// Read a byte from a hardware register.
+///
+/// # Safety
+/// The address must be a valid, readable hardware register.
+#[inline(always)]
+pub unsafe fn read_u8(addr: u32) -> u8 { backend::read_u8(addr) }
+
+/// Read a word from a hardware register.
+///
+/// # Safety
+/// The address must be a valid, readable, word-aligned hardware register.
+#[inline(always)]
+pub unsafe fn read_u16(addr: u32) -> u16 { backend::read_u16(addr) }
+
+/// Read a long word from a hardware register.
+///
+/// # Safety
+/// The address must be a valid, readable, word-aligned hardware register.
+#[inline(always)]
+pub unsafe fn read_u32(addr: u32) -> u32 { backend::read_u32(addr) }
+
+/// Write a byte to a hardware register.
+///
+/// # Safety
+/// The address must be a valid, writable hardware register.
+#[inline(always)]
+pub unsafe fn write_u8(addr: u32, v: u8) { backend::write_u8(addr, v) }
+
+/// Write a word to a hardware register.
+///
+/// # Safety
+/// The address must be a valid, writable, word-aligned hardware register.
+#[inline(always)]
+pub unsafe fn write_u16(addr: u32, v: u16) { backend::write_u16(addr, v) }
+
+/// Write a long word to a hardware register.
+///
+/// # Safety
+/// The address must be a valid, writable, word-aligned hardware register.
+#[inline(always)]
+pub unsafe fn write_u32(addr: u32, v: u32) { backend::write_u32(addr, v) }
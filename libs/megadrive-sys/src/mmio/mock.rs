@@ -0,0 +1,123 @@
+//! A recording MMIO backend for testing on a host machine.
+//!
+//! Every access made by the drivers is appended to a log which can be
+//! inspected with `take_log` or `writes`. Reads return values scripted with
+//! `script_reads`, falling back to the value set with `set_read_value` (or
+//! zero). A zero status means, for example, that the VDP never reports a
+//! DMA in progress and the YM2612 is never busy, so drivers do not spin.
+//!
+//! The state is per-thread, so tests running in parallel do not interfere
+//! with each other.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::vec::Vec;
+
+/// The width of a single bus access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Width {
+    Byte,
+    Word,
+    Long,
+}
+
+/// A single recorded bus access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read { addr: u32, width: Width, value: u32 },
+    Write { addr: u32, width: Width, value: u32 },
+}
+
+#[derive(Default)]
+struct Bus {
+    log: Vec<Access>,
+    scripted: HashMap<u32, VecDeque<u32>>,
+    defaults: HashMap<u32, u32>,
+}
+
+impl Bus {
+    fn read(&mut self, addr: u32, width: Width) -> u32 {
+        let value = self.scripted.get_mut(&addr)
+            .and_then(|q| q.pop_front())
+            .or_else(|| self.defaults.get(&addr).cloned())
+            .unwrap_or(0);
+        self.log.push(Access::Read { addr, width, value });
+        value
+    }
+
+    fn write(&mut self, addr: u32, width: Width, value: u32) {
+        self.log.push(Access::Write { addr, width, value });
+    }
+}
+
+std::thread_local! {
+    static BUS: RefCell<Bus> = RefCell::new(Bus::default());
+}
+
+/// Clear the access log, scripted reads and read values.
+pub fn reset() {
+    BUS.with(|b| *b.borrow_mut() = Bus::default());
+}
+
+/// Queue values to be returned by successive reads of `addr`.
+///
+/// Once the queue is exhausted, reads return the value set by `set_read_value`.
+pub fn script_reads(addr: u32, values: impl IntoIterator<Item=u32>) {
+    BUS.with(|b| b.borrow_mut().scripted.entry(addr).or_default().extend(values));
+}
+
+/// Set the value returned by reads of `addr` when nothing is scripted.
+pub fn set_read_value(addr: u32, value: u32) {
+    BUS.with(|b| { b.borrow_mut().defaults.insert(addr, value); });
+}
+
+/// Remove and return every access recorded so far.
+pub fn take_log() -> Vec<Access> {
+    BUS.with(|b| core::mem::take(&mut b.borrow_mut().log))
+}
+
+/// Remove and return every write recorded so far as `(address, value)` pairs.
+///
+/// Recorded reads are discarded.
+pub fn writes() -> Vec<(u32, u32)> {
+    take_log().into_iter()
+        .filter_map(|a| match a {
+            Access::Write { addr, value, .. } => Some((addr, value)),
+            Access::Read { .. } => None,
+        })
+        .collect()
+}
+
+/// Remove and return the values of every write to `addr` recorded so far.
+///
+/// All other recorded accesses are discarded.
+pub fn writes_to(addr: u32) -> Vec<u32> {
+    writes().into_iter()
+        .filter(|&(a, _)| a == addr)
+        .map(|(_, v)| v)
+        .collect()
+}
+
+pub(super) unsafe fn read_u8(addr: u32) -> u8 {
+    BUS.with(|b| b.borrow_mut().read(addr, Width::Byte) as u8)
+}
+
+pub(super) unsafe fn read_u16(addr: u32) -> u16 {
+    BUS.with(|b| b.borrow_mut().read(addr, Width::Word) as u16)
+}
+
+pub(super) unsafe fn read_u32(addr: u32) -> u32 {
+    BUS.with(|b| b.borrow_mut().read(addr, Width::Long))
+}
+
+pub(super) unsafe fn write_u8(addr: u32, v: u8) {
+    BUS.with(|b| b.borrow_mut().write(addr, Width::Byte, v as u32));
+}
+
+pub(super) unsafe fn write_u16(addr: u32, v: u16) {
+    BUS.with(|b| b.borrow_mut().write(addr, Width::Word, v as u32));
+}
+
+pub(super) unsafe fn write_u32(addr: u32, v: u32) {
+    BUS.with(|b| b.borrow_mut().write(addr, Width::Long, v));
+}
@@ -0,0 +1,205 @@
+//! PCM sample playback using a driver on the Z80.
+//!
+//! The driver streams 8-bit unsigned samples from ROM through the Z80's bank
+//! window, mixes up to `NUM_CHANNELS` of them and writes the result to the
+//! YM2612's DAC, paced by timer A.
+//!
+//! `FM` must not be used at all whilst the driver is running. The YM2612 has a
+//! single address latch for each bank, so a write from the 68k which lands
+//! between the driver's address & data writes sends one of them to the wrong
+//! register. Set up any FM instruments before `Pcm::new`, or stop the driver
+//! with `Pcm::into_z80` first.
+//!
+//! ```ignore
+//! static DRUM: Sample = Sample::new(include_bytes!("drum.pcm"), 8000);
+//!
+//! let mut pcm = Pcm::new(Z80::take().unwrap());
+//! pcm.play(&DRUM, 10);
+//! ```
+
+use crate::z80::{BankAddress, Mailbox, ROM_END, Z80};
+
+include!(concat!(env!("OUT_DIR"), "/pcm_symbols.rs"));
+
+static DRIVER: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/pcm.bin"));
+
+/// The number of samples which can be played at once.
+pub const NUM_CHANNELS: usize = 2;
+
+/// The output rate of the driver in Hz.
+///
+/// This is derived from the YM2612 clock, so it is slightly lower on PAL
+/// consoles.
+pub const MIX_RATE: u32 = 53267 / (1024 - TIMER_A as u32);
+
+const CHANNEL_ADDRS: [u16; NUM_CHANNELS] = [CH0, CH1];
+
+const OP_STOP: u8 = 0;
+const OP_PLAY: u8 = 1;
+
+/// A play command, as the driver expects it.
+///
+/// Multi-byte fields are little-endian.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Command {
+    op: u8,
+    step: [u8; 2],
+    start: [u8; 7],
+    looping: u8,
+    loop_start: [u8; 7],
+}
+
+impl Command {
+    fn stop() -> Command {
+        Command {
+            op: OP_STOP,
+            step: [0; 2],
+            start: [0; 7],
+            looping: 0,
+            loop_start: [0; 7],
+        }
+    }
+
+    /// Encode the bank, window address & length of a block of ROM.
+    fn position(data: &[u8]) -> [u8; 7] {
+        let BankAddress { bank, addr } = BankAddress::new(data.as_ptr() as u32);
+        let len = data.len() as u32;
+        [
+            bank as u8, (bank >> 8) as u8,
+            addr as u8, (addr >> 8) as u8,
+            len as u8, (len >> 8) as u8, (len >> 16) as u8,
+        ]
+    }
+}
+
+/// An 8-bit unsigned PCM sample stored in ROM.
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    data: &'static [u8],
+    step: u16,
+    loop_start: Option<usize>,
+}
+
+impl Sample {
+    /// Create a sample which is played back at `rate` Hz.
+    ///
+    /// Rates above `MIX_RATE` skip samples, so they should be avoided.
+    pub const fn new(data: &'static [u8], rate: u32) -> Sample {
+        Sample {
+            data,
+            step: ((rate << 8) / MIX_RATE) as u16,
+            loop_start: None,
+        }
+    }
+
+    /// Loop the sample from the byte offset `start` once it has finished.
+    pub const fn looping(mut self, start: usize) -> Sample {
+        self.loop_start = Some(start);
+        self
+    }
+
+    /// The sample data.
+    pub fn data(&self) -> &'static [u8] { self.data }
+
+    fn command(&self) -> Command {
+        let mut command = Command::stop();
+        command.op = OP_PLAY;
+        command.step = self.step.to_le_bytes();
+        command.start = Command::position(self.data);
+        if let Some(start) = self.loop_start {
+            command.looping = 1;
+            command.loop_start = Command::position(&self.data[start..]);
+        }
+        command
+    }
+}
+
+/// The PCM driver.
+pub struct Pcm {
+    z80: Z80,
+    mailboxes: [Mailbox<Command, u8>; NUM_CHANNELS],
+    priorities: [u8; NUM_CHANNELS],
+}
+
+impl Pcm {
+    /// Load the driver onto the Z80 and start it.
+    pub fn new(mut z80: Z80) -> Pcm {
+        z80.load_program(DRIVER);
+        let mailboxes = unsafe {
+            [
+                Mailbox::new(CHANNEL_ADDRS[0] + CH_MAILBOX),
+                Mailbox::new(CHANNEL_ADDRS[1] + CH_MAILBOX),
+            ]
+        };
+
+        Pcm {
+            z80,
+            mailboxes,
+            priorities: [0; NUM_CHANNELS],
+        }
+    }
+
+    fn send(&mut self, channel: usize, command: &Command) {
+        while !self.mailboxes[channel].send(&mut self.z80, command) {}
+    }
+
+    /// Play a sample.
+    ///
+    /// A free channel is used if there is one, otherwise the sample replaces
+    /// the one with the lowest priority, as long as that is no higher than
+    /// `priority`. Returns the channel, or `None` if the sample was not
+    /// played.
+    pub fn play(&mut self, sample: &'static Sample, priority: u8) -> Option<usize> {
+        let data = sample.data;
+        assert!(!data.is_empty(), "sample is empty");
+        assert!(data.as_ptr() as u32 + data.len() as u32 <= ROM_END, "sample is not in ROM");
+        assert!(sample.step > 0, "sample rate is too low");
+        if let Some(start) = sample.loop_start {
+            assert!(start < data.len(), "loop start is past the end of the sample");
+        }
+
+        let channel = match (0..NUM_CHANNELS).find(|&ch| !self.is_playing(ch)) {
+            Some(ch) => ch,
+            None => (0..NUM_CHANNELS)
+                .filter(|&ch| self.priorities[ch] <= priority)
+                .min_by_key(|&ch| self.priorities[ch])?,
+        };
+
+        self.send(channel, &sample.command());
+        self.priorities[channel] = priority;
+        Some(channel)
+    }
+
+    /// Stop playback on a channel.
+    pub fn stop(&mut self, channel: usize) {
+        assert!(channel < NUM_CHANNELS, "invalid PCM channel");
+        self.send(channel, &Command::stop());
+    }
+
+    /// Stop playback on every channel.
+    pub fn stop_all(&mut self) {
+        for channel in 0..NUM_CHANNELS {
+            self.stop(channel);
+        }
+    }
+
+    /// Returns true if a sample is playing on a channel.
+    ///
+    /// This includes samples which have been sent to the driver, but not yet
+    /// started.
+    pub fn is_playing(&mut self, channel: usize) -> bool {
+        assert!(channel < NUM_CHANNELS, "invalid PCM channel");
+        let mailbox = &self.mailboxes[channel];
+        if mailbox.is_busy(&mut self.z80) {
+            return true;
+        }
+        self.z80.bus().read_u8(CHANNEL_ADDRS[channel] + CH_STATE) != 0
+    }
+
+    /// Stop the driver and return the Z80.
+    pub fn into_z80(mut self) -> Z80 {
+        self.z80.stop();
+        self.z80
+    }
+}
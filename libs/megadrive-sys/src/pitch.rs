@@ -0,0 +1,135 @@
+//! Conversion of MIDI notes into YM2612 & PSG frequency registers.
+//!
+//! Pitches are given as a MIDI note number, where 60 is middle C (C4), plus a
+//! fine tuning in cents. The sound chips are clocked from the console's master
+//! clock, which differs between NTSC & PAL consoles, so the conversion depends
+//! on a `Clock`.
+//!
+//! ```ignore
+//! let clock = Clock::detect();
+//! let (block, f_number) = clock.fm(60, 0);
+//! fm.channel(0).set_frequency(f_number, block);
+//! ```
+
+/// The console clock, which the sound chips are driven from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Clock {
+    /// 53.693175MHz.
+    Ntsc,
+    /// 53.203424MHz.
+    Pal,
+}
+
+/// YM2612 F-numbers for C4 to B4 in block 4, with 4 fractional bits.
+static FM_NTSC: [u16; 12] = [
+    10300, 10913, 11562, 12249, 12978, 13749, 14567, 15433, 16351, 17323, 18353, 19444,
+];
+static FM_PAL: [u16; 12] = [
+    10395, 11013, 11668, 12362, 13097, 13876, 14701, 15575, 16501, 17482, 18522, 19623,
+];
+
+/// PSG dividers for C-1 to B-1, which are too low to play directly.
+static PSG_NTSC: [u16; 12] = [
+    13682, 12914, 12189, 11505, 10859, 10250, 9675, 9132, 8619, 8135, 7679, 7248,
+];
+static PSG_PAL: [u16; 12] = [
+    13557, 12796, 12078, 11400, 10760, 10156, 9586, 9048, 8540, 8061, 7609, 7182,
+];
+
+/// `2 ^ (cents / 1200)`, scaled by 32768.
+static CENTS_UP: [u16; 100] = [
+    32768, 32787, 32806, 32825, 32844, 32863, 32882, 32901, 32920, 32939,
+    32958, 32977, 32996, 33015, 33034, 33053, 33072, 33091, 33110, 33130,
+    33149, 33168, 33187, 33206, 33225, 33245, 33264, 33283, 33302, 33322,
+    33341, 33360, 33379, 33399, 33418, 33437, 33457, 33476, 33495, 33515,
+    33534, 33553, 33573, 33592, 33611, 33631, 33650, 33670, 33689, 33709,
+    33728, 33748, 33767, 33787, 33806, 33826, 33845, 33865, 33884, 33904,
+    33924, 33943, 33963, 33982, 34002, 34022, 34041, 34061, 34081, 34100,
+    34120, 34140, 34160, 34179, 34199, 34219, 34239, 34258, 34278, 34298,
+    34318, 34338, 34357, 34377, 34397, 34417, 34437, 34457, 34477, 34497,
+    34517, 34536, 34556, 34576, 34596, 34616, 34636, 34656, 34676, 34696,
+];
+
+/// `2 ^ (-cents / 1200)`, scaled by 32768.
+static CENTS_DOWN: [u16; 100] = [
+    32768, 32749, 32730, 32711, 32692, 32673, 32655, 32636, 32617, 32598,
+    32579, 32560, 32542, 32523, 32504, 32485, 32467, 32448, 32429, 32410,
+    32392, 32373, 32354, 32336, 32317, 32298, 32280, 32261, 32242, 32224,
+    32205, 32186, 32168, 32149, 32131, 32112, 32094, 32075, 32057, 32038,
+    32020, 32001, 31983, 31964, 31946, 31927, 31909, 31890, 31872, 31854,
+    31835, 31817, 31798, 31780, 31762, 31743, 31725, 31707, 31688, 31670,
+    31652, 31634, 31615, 31597, 31579, 31561, 31542, 31524, 31506, 31488,
+    31470, 31451, 31433, 31415, 31397, 31379, 31361, 31343, 31324, 31306,
+    31288, 31270, 31252, 31234, 31216, 31198, 31180, 31162, 31144, 31126,
+    31108, 31090, 31072, 31054, 31036, 31018, 31000, 30983, 30965, 30947,
+];
+
+const FM_MAX_BLOCK: i16 = 7;
+const FM_MAX_F_NUMBER: u32 = 0x7ff;
+const PSG_MAX_DIVIDER: u32 = 0x3ff;
+
+/// Split a pitch into a semitone and positive cents.
+fn split(note: u8, cents: i16) -> (usize, usize) {
+    let pitch = (note as i32 * 100 + cents as i32).max(0) as usize;
+    (pitch / 100, pitch % 100)
+}
+
+impl Clock {
+    /// Detect the clock of this console.
+    pub fn detect() -> Clock {
+        if crate::version().is_pal() {
+            Clock::Pal
+        } else {
+            Clock::Ntsc
+        }
+    }
+
+    /// Convert a pitch into a block (octave) & F-number for the YM2612.
+    ///
+    /// Pitches outside of the range of the chip are clamped.
+    pub fn fm(self, note: u8, cents: i16) -> (u8, u16) {
+        let table = match self {
+            Clock::Ntsc => &FM_NTSC,
+            Clock::Pal => &FM_PAL,
+        };
+        let (semitone, cents) = split(note, cents);
+        let scaled = table[semitone % 12] as u32 * CENTS_UP[cents] as u32;
+        let mut f_number = (scaled + (1 << 18)) >> 19;
+
+        // The table is for block 4, which is MIDI octave 4.
+        let mut block = (semitone / 12) as i16 - 1;
+        if block < 0 {
+            f_number >>= -block;
+            block = 0;
+        }
+        while block > FM_MAX_BLOCK {
+            f_number = (f_number << 1).min(FM_MAX_F_NUMBER);
+            block -= 1;
+        }
+        (block as u8, f_number as u16)
+    }
+
+    /// Convert a pitch into a 10-bit PSG tone divider.
+    ///
+    /// Returns `None` if the pitch is out of the range of the PSG, which
+    /// cannot play below about A2.
+    pub fn psg(self, note: u8, cents: i16) -> Option<u16> {
+        let table = match self {
+            Clock::Ntsc => &PSG_NTSC,
+            Clock::Pal => &PSG_PAL,
+        };
+        let (semitone, cents) = split(note, cents);
+        let octave = (semitone / 12) as u32;
+        if octave > 16 {
+            return None;
+        }
+        let shift = 15 + octave;
+        let scaled = table[semitone % 12] as u32 * CENTS_DOWN[cents] as u32;
+        let divider = (scaled + (1 << (shift - 1))) >> shift;
+        if divider > 0 && divider <= PSG_MAX_DIVIDER {
+            Some(divider as u16)
+        } else {
+            None
+        }
+    }
+}
@@ -1,33 +1,33 @@
-use core::ptr::{write_volatile, read_volatile};
+use crate::mmio;
 
 const IO_BASE: u32 = 0xa10000;
-const IO_DATA: *mut u8 = (IO_BASE + 3) as _;
-const IO_CTRL: *mut u8 = (IO_BASE + 9) as _;
-const IO_TX: *mut u8 = (IO_BASE + 0xf) as _;
-const IO_RX: *mut u8 = (IO_BASE + 0x11) as _;
-const IO_SCTRL: *mut u8 = (IO_BASE + 0x13) as _;
+const IO_DATA: u32 = IO_BASE + 3;
+const IO_CTRL: u32 = IO_BASE + 9;
+const IO_TX: u32 = IO_BASE + 0xf;
+const IO_RX: u32 = IO_BASE + 0x11;
+const IO_SCTRL: u32 = IO_BASE + 0x13;
 
-fn read_reg_2(base: *mut u8, idx: u8) -> u8 {
+fn read_reg_2(base: u32, idx: u8) -> u8 {
     unsafe {
-        read_volatile(base.offset((idx * 1) as isize))
+        mmio::read_u8(base + (idx as u32) * 2)
     }
 }
 
-fn write_reg_2(base: *mut u8, idx: u8, v: u8) {
+fn write_reg_2(base: u32, idx: u8, v: u8) {
     unsafe {
-        write_volatile(base.offset((idx * 1) as isize), v);
+        mmio::write_u8(base + (idx as u32) * 2, v);
     }
 }
 
-fn read_reg_6(base: *mut u8, idx: u8) -> u8 {
+fn read_reg_6(base: u32, idx: u8) -> u8 {
     unsafe {
-        read_volatile(base.offset((idx * 6) as isize))
+        mmio::read_u8(base + (idx as u32) * 6)
     }
 }
 
-fn write_reg_6(base: *mut u8, idx: u8, v: u8) {
+fn write_reg_6(base: u32, idx: u8, v: u8) {
     unsafe {
-        write_volatile(base.offset((idx * 6) as isize), v);
+        mmio::write_u8(base + (idx as u32) * 6, v);
     }
 }
 
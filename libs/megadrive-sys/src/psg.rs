@@ -1,6 +1,6 @@
-use core::ptr::write_volatile;
+use crate::mmio;
 
-const PSG_BASE: *mut u8 = 0xc00011 as _;
+const PSG_BASE: u32 = 0xc00011;
 const NUM_CHANNELS: u8 = 4;
 
 /// A frequency for use with the noise generator.
@@ -14,6 +14,8 @@ pub enum NoiseFrequency {
 }
 
 /// A selection of note frequencies to use with the tone generators.
+///
+/// `pitch::Clock::psg` gives exact values for any note.
 #[repr(u16)]
 #[derive(Clone, Copy, Debug)]
 pub enum Note {
@@ -67,7 +69,14 @@ impl PSG {
     }
 
     fn write(&self, v: u8) {
-        unsafe { write_volatile(PSG_BASE, v) };
+        unsafe { mmio::write_u8(PSG_BASE, v) };
+    }
+
+    /// Write a raw command byte to the PSG.
+    ///
+    /// This is for playing back register logs.
+    pub fn write_raw(&self, v: u8) {
+        self.write(v);
     }
 
     /// Set the volume of a channel.
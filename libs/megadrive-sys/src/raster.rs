@@ -0,0 +1,229 @@
+//! Raster effects driven by the horizontal blank interrupt.
+//!
+//! A `RasterTable` lists actions to perform part way down the screen, such as
+//! swapping palette entries for water or splitting the window for a status
+//! bar. `RasterEffects` runs the table: `on_hblank` must be called from the
+//! horizontal interrupt handler and `on_vblank` from the vertical interrupt
+//! handler.
+//!
+//! For the actions to land on the right lines, the horizontal interrupt
+//! needs to fire on every line:
+//!
+//! ```ignore
+//! vdp.set_h_interrupt_interval(0);
+//! vdp.enable_interrupts(true, true, false);
+//! ```
+//!
+//! The `RasterEffects` is shared between the interrupt handlers and the main
+//! program, so every method which changes it does so with interrupts masked.
+//! The main program builds the next frame's table in its own `RasterTable`
+//! and hands over a copy with `submit`; it never has access to the tables the
+//! handlers are using.
+//!
+//! The actions write to the VDP directly. If the main program is part way
+//! through writing to VRAM when one runs, the main program's address will be
+//! lost, so uploads should be done during vertical blank.
+
+use crate::interrupt::without_interrupts;
+use crate::mmio;
+use crate::vdp::{addr_words, registers, AddrKind, Color, HvCounter, WindowDivide, VDP,
+                 REG_VDP_CONTROL16, REG_VDP_DATA16};
+
+/// The maximum number of actions in a single `RasterTable`.
+pub const MAX_RASTER_ACTIONS: usize = 32;
+
+/// A single change to make to the VDP part way through the frame.
+#[derive(Clone, Copy, Debug)]
+pub enum RasterAction {
+    /// Set a single colour: palette, index & colour.
+    SetColor(u8, u8, Color),
+    /// Write a raw VDP register: register & value.
+    SetRegister(u8, u8),
+    /// Set the full-screen horizontal scroll of planes A and B.
+    SetHScroll(i16, i16),
+    /// Set the full-screen vertical scroll of planes A and B.
+    SetVScroll(i16, i16),
+    /// Move the window split.
+    SetWindow(WindowDivide, WindowDivide),
+}
+
+/// A list of actions to perform during a frame, ordered by scanline.
+#[derive(Clone)]
+pub struct RasterTable {
+    entries: [(i16, RasterAction); MAX_RASTER_ACTIONS],
+    len: usize,
+}
+
+impl RasterTable {
+    /// Create an empty table.
+    pub const fn new() -> RasterTable {
+        RasterTable {
+            entries: [(0, RasterAction::SetRegister(0, 0)); MAX_RASTER_ACTIONS],
+            len: 0,
+        }
+    }
+
+    /// Returns the number of actions in the table.
+    pub fn len(&self) -> usize { self.len }
+
+    /// Returns true if there are no actions in the table.
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Remove all actions from the table.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Add an action which takes effect from the given scanline.
+    ///
+    /// Actions on the same line run in the order they were added. Returns
+    /// false if the table is full.
+    pub fn push(&mut self, line: i16, action: RasterAction) -> bool {
+        if self.len >= MAX_RASTER_ACTIONS {
+            return false;
+        }
+
+        let idx = self.entries[..self.len].iter()
+            .position(|&(l, _)| l > line)
+            .unwrap_or(self.len);
+        self.entries.copy_within(idx..self.len, idx + 1);
+        self.entries[idx] = (line, action);
+        self.len += 1;
+        true
+    }
+
+    /// Iterate over the actions in the table, in the order they will run.
+    pub fn iter(&self) -> impl Iterator<Item=&(i16, RasterAction)> {
+        self.entries[..self.len].iter()
+    }
+}
+
+impl Default for RasterTable {
+    fn default() -> Self {
+        RasterTable::new()
+    }
+}
+
+/// A runner for raster effects.
+///
+/// This holds two tables: the one being run this frame and the one submitted
+/// for the next frame. Submitted tables are swapped in at the next vertical
+/// blank, otherwise the current table is run again.
+pub struct RasterEffects {
+    tables: [RasterTable; 2],
+    active: usize,
+    next_action: usize,
+    submitted: bool,
+    h_scroll_base: u16,
+    hv_counter: HvCounter,
+}
+
+impl RasterEffects {
+    /// Create a new raster effect runner for the VDP's current configuration.
+    pub fn new(vdp: &VDP) -> RasterEffects {
+        RasterEffects {
+            tables: [RasterTable::new(), RasterTable::new()],
+            active: 0,
+            next_action: 0,
+            submitted: false,
+            h_scroll_base: vdp.vram_layout().h_scroll,
+            hv_counter: vdp.hv_counter(),
+        }
+    }
+
+    /// Fetch a copy of the table which is currently being run.
+    pub fn current(&self) -> RasterTable {
+        without_interrupts(|| self.tables[self.active].clone())
+    }
+
+    /// Set the table to run from the next vertical blank.
+    ///
+    /// The table is copied with interrupts masked, so any horizontal
+    /// interrupts during the copy will run late. Submitting again before the
+    /// next vertical blank replaces the previous submission.
+    pub fn submit(&mut self, table: &RasterTable) {
+        without_interrupts(|| {
+            let pending = &mut self.tables[self.active ^ 1];
+            pending.entries[..table.len].copy_from_slice(&table.entries[..table.len]);
+            pending.len = table.len;
+            self.submitted = true;
+        });
+    }
+
+    /// Handle the vertical blank interrupt.
+    ///
+    /// This swaps in the submitted table and runs any actions for line 0 or
+    /// above the screen.
+    pub fn on_vblank(&mut self) {
+        without_interrupts(|| {
+            if self.submitted {
+                self.active ^= 1;
+                self.submitted = false;
+            }
+
+            self.next_action = 0;
+            self.run_until(0);
+        });
+    }
+
+    /// Handle the horizontal blank interrupt.
+    ///
+    /// This runs all actions up to the next scanline. Interrupts are masked
+    /// so that the vertical blank handler cannot swap tables part way through.
+    pub fn on_hblank(&mut self) {
+        without_interrupts(|| {
+            let line = self.hv_counter.decode(HvCounter::read_raw(), false).line;
+            self.run_until(line + 1);
+        });
+    }
+
+    fn run_until(&mut self, line: i16) {
+        let table = &self.tables[self.active];
+
+        while self.next_action < table.len {
+            let (action_line, action) = table.entries[self.next_action];
+            if action_line > line {
+                break;
+            }
+
+            self.run(action);
+            self.next_action += 1;
+        }
+    }
+
+    fn run(&self, action: RasterAction) {
+        match action {
+            RasterAction::SetColor(palette, index, color) => {
+                let addr = (((palette & 3) as u16) << 5) | (((index & 0xf) as u16) << 1);
+                write_words(AddrKind::CRAM, addr, &[color.raw()]);
+            },
+            RasterAction::SetRegister(reg, value) => write_register(reg, value),
+            RasterAction::SetHScroll(a, b) => {
+                write_words(AddrKind::VRAM, self.h_scroll_base, &[a as u16, b as u16]);
+            },
+            RasterAction::SetVScroll(a, b) => {
+                write_words(AddrKind::VSRAM, 0, &[a as u16, b as u16]);
+            },
+            RasterAction::SetWindow(x, y) => {
+                write_register(registers::WINX, x.reg_value());
+                write_register(registers::WINY, y.reg_value());
+            },
+        }
+    }
+}
+
+fn write_register(reg: u8, value: u8) {
+    unsafe { mmio::write_u16(REG_VDP_CONTROL16, ((reg as u16) << 8) | (value as u16)) };
+}
+
+// This assumes the auto-increment is 2, which is how `VDP` leaves it.
+fn write_words(kind: AddrKind, addr: u16, values: &[u16]) {
+    let (lo, hi) = addr_words(kind, addr, true, false);
+    unsafe {
+        mmio::write_u16(REG_VDP_CONTROL16, lo);
+        mmio::write_u16(REG_VDP_CONTROL16, hi);
+        for &v in values {
+            mmio::write_u16(REG_VDP_DATA16, v);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Cartridge save RAM.
+//!
+//! Most cartridges with battery-backed SRAM only connect it to one half of
+//! the data bus, so only every other byte is usable. `Sram` hides this, and
+//! addresses the SRAM by byte offsets into the usable storage.
+//!
+//! On cartridges larger than 2MiB the SRAM overlaps the ROM, so it must be
+//! enabled before use and disabled afterwards. Code and data in the upper
+//! half of the ROM cannot be used whilst the SRAM is enabled.
+
+use crate::mmio;
+
+const REG_SRAM_CONTROL: u32 = 0xa130f1;
+const SRAM_ENABLE: u8 = 1;
+const SRAM_WRITE_PROTECT: u8 = 2;
+
+const HEADER_SRAM: u32 = 0x1b0;
+
+/// Which bytes of the data bus the SRAM is connected to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteLane {
+    /// Only odd addresses are connected (the low byte of each word).
+    Odd,
+    /// Only even addresses are connected (the high byte of each word).
+    Even,
+    /// The SRAM is a full 16 bits wide.
+    Both,
+}
+
+/// A handle to the cartridge SRAM.
+#[derive(Clone, Copy, Debug)]
+pub struct Sram {
+    base: u32,
+    size: u32,
+    lane: ByteLane,
+}
+
+impl Sram {
+    /// Create a handle to SRAM at `base` with `size` usable bytes.
+    ///
+    /// For the odd and even byte lanes, `base` is the address of the first
+    /// usable byte, e.g. 0x200001 for odd SRAM.
+    pub const fn new(base: u32, size: u32, lane: ByteLane) -> Sram {
+        Sram { base, size, lane }
+    }
+
+    /// Find the SRAM described by the ROM header.
+    ///
+    /// Returns `None` if the header does not declare any SRAM.
+    pub fn from_header() -> Option<Sram> {
+        let (magic, start, end) = unsafe {
+            (mmio::read_u32(HEADER_SRAM),
+             mmio::read_u32(HEADER_SRAM + 4),
+             mmio::read_u32(HEADER_SRAM + 8))
+        };
+
+        if (magic >> 16) != 0x5241 || end < start {
+            return None;
+        }
+
+        let lane = match (magic >> 11) & 3 {
+            3 => ByteLane::Odd,
+            2 => ByteLane::Even,
+            _ => ByteLane::Both,
+        };
+        let size = match lane {
+            ByteLane::Both => end - start + 1,
+            _ => ((end - start) >> 1) + 1,
+        };
+        Some(Sram::new(start, size, lane))
+    }
+
+    /// Returns the number of usable bytes.
+    pub fn size(&self) -> u32 { self.size }
+
+    /// Returns which bytes of the bus the SRAM is connected to.
+    pub fn lane(&self) -> ByteLane { self.lane }
+
+    /// Map the SRAM into the address space.
+    ///
+    /// If `writable` is false, the SRAM is write-protected.
+    pub fn enable(&self, writable: bool) {
+        let mut v = SRAM_ENABLE;
+        if !writable {
+            v |= SRAM_WRITE_PROTECT;
+        }
+        unsafe { mmio::write_u8(REG_SRAM_CONTROL, v) };
+    }
+
+    /// Unmap the SRAM, restoring access to the ROM underneath it.
+    pub fn disable(&self) {
+        unsafe { mmio::write_u8(REG_SRAM_CONTROL, SRAM_WRITE_PROTECT) };
+    }
+
+    fn address(&self, offset: u32) -> u32 {
+        assert!(offset < self.size, "SRAM offset out of range");
+        match self.lane {
+            ByteLane::Both => self.base + offset,
+            _ => self.base + (offset << 1),
+        }
+    }
+
+    /// Read a single byte.
+    pub fn read_u8(&self, offset: u32) -> u8 {
+        unsafe { mmio::read_u8(self.address(offset)) }
+    }
+
+    /// Write a single byte.
+    pub fn write_u8(&self, offset: u32, v: u8) {
+        unsafe { mmio::write_u8(self.address(offset), v) };
+    }
+
+    /// Read a big-endian word.
+    pub fn read_u16(&self, offset: u32) -> u16 {
+        let mut bytes = [0; 2];
+        self.read(offset, &mut bytes);
+        u16::from_be_bytes(bytes)
+    }
+
+    /// Write a big-endian word.
+    pub fn write_u16(&self, offset: u32, v: u16) {
+        self.write(offset, &v.to_be_bytes());
+    }
+
+    /// Read a big-endian long word.
+    pub fn read_u32(&self, offset: u32) -> u32 {
+        let mut bytes = [0; 4];
+        self.read(offset, &mut bytes);
+        u32::from_be_bytes(bytes)
+    }
+
+    /// Write a big-endian long word.
+    pub fn write_u32(&self, offset: u32, v: u32) {
+        self.write(offset, &v.to_be_bytes());
+    }
+
+    /// Read consecutive bytes into `dst`.
+    pub fn read(&self, offset: u32, dst: &mut [u8]) {
+        for (i, b) in dst.iter_mut().enumerate() {
+            *b = self.read_u8(offset + i as u32);
+        }
+    }
+
+    /// Write consecutive bytes from `src`.
+    pub fn write(&self, offset: u32, src: &[u8]) {
+        for (i, &b) in src.iter().enumerate() {
+            self.write_u8(offset + i as u32, b);
+        }
+    }
+
+    /// Read a value of any type.
+    ///
+    /// # Safety
+    /// The bytes in SRAM must be a valid `T`. This is not the case for
+    /// uninitialised SRAM if `T` contains references, `bool`s or enums.
+    pub unsafe fn read_value<T: Copy>(&self, offset: u32) -> T {
+        let mut value = core::mem::MaybeUninit::<T>::uninit();
+        let ptr = value.as_mut_ptr() as *mut u8;
+        for i in 0..core::mem::size_of::<T>() {
+            ptr.add(i).write(self.read_u8(offset + i as u32));
+        }
+        value.assume_init()
+    }
+
+    /// Write a value of any type.
+    ///
+    /// # Safety
+    /// `T` must not contain any padding bytes.
+    pub unsafe fn write_value<T: Copy>(&self, offset: u32, value: &T) {
+        let bytes = core::slice::from_raw_parts(
+            value as *const T as *const u8, core::mem::size_of::<T>());
+        self.write(offset, bytes);
+    }
+}
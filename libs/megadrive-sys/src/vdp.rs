@@ -1,14 +1,18 @@
 use core::ptr::{read_volatile, write_volatile};
-use core::ops::{Deref, DerefMut};
+use core::ops::{Deref, DerefMut, Range};
+use crate::mmio;
 
-const REG_VDP_BASE: usize = 0xc00000;
-const REG_VDP_DATA16: *mut u16 = REG_VDP_BASE as _;
-const REG_VDP_CONTROL16: *mut u16 = (REG_VDP_BASE + 4) as _;
+const REG_VDP_BASE: u32 = 0xc00000;
+pub(crate) const REG_VDP_DATA16: u32 = REG_VDP_BASE;
+pub(crate) const REG_VDP_CONTROL16: u32 = REG_VDP_BASE + 4;
+const REG_VDP_HV_COUNTER16: u32 = REG_VDP_BASE + 8;
 
-const DEFAULT_PALETTE: [u16; 16] = [
-    0x000, 0xFFF, 0xF00, 0x0F0, 0x00B, 0xFF0, 0xF0F, 0x0FF,
-    0x666, 0xBBB, 0x800, 0x080, 0x008, 0x880, 0x808, 0x088,
-];
+const DEFAULT_PALETTE: RgbPalette = RgbPalette([
+    Color::new(0, 0, 0), Color::new(7, 7, 7), Color::new(0, 0, 7), Color::new(0, 7, 0),
+    Color::new(5, 0, 0), Color::new(0, 7, 7), Color::new(7, 0, 7), Color::new(7, 7, 0),
+    Color::new(3, 3, 3), Color::new(5, 5, 5), Color::new(0, 0, 4), Color::new(0, 4, 0),
+    Color::new(4, 0, 0), Color::new(0, 4, 4), Color::new(4, 0, 4), Color::new(4, 4, 0),
+]);
 
 pub mod registers {
     pub const MODE_1: u8 = 0x80;
@@ -48,14 +52,38 @@ fn dma_len<T>(s: &[T]) -> u16 {
     ((s.len() * core::mem::size_of::<T>()) >> 1) as u16
 }
 
+/// Encode the two control port words which select an address.
+pub(crate) fn addr_words(kind: AddrKind, ptr: u16, write: bool, dma: bool) -> (u16, u16) {
+    let ctrl = match (kind, write) {
+        (AddrKind::VRAM, true) => 0b00001,
+        (AddrKind::CRAM, true) => 0b00011,
+        (AddrKind::VSRAM, true) => 0b00101,
+        (AddrKind::VRAM, false) => 0b00000,
+        (AddrKind::CRAM, false) => 0b01000,
+        (AddrKind::VSRAM, false) => 0b00100,
+    };
+    let dma_flag = if dma { 0x80 } else { 0 };
+    let hi = ((ptr >> 14) & 3) | ((ctrl >> 2) << 4) | dma_flag;
+    let lo = (ptr & 0x3fff) | (ctrl << 14);
+    (lo, hi)
+}
+
 /// A struct representing the various segments of VRAM available on the VDP.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AddrKind {
     VRAM,
     CRAM,
     VSRAM,
 }
 
+/// An enumeration of the tile planes drawn by the VDP.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Plane {
+    A,
+    B,
+    Window,
+}
+
 /// A struct representing where the window is drawn instead of plane A for an axis.
 ///
 /// For example x: After(10), would make the window render to the right of tile 10 onwards.
@@ -66,7 +94,7 @@ pub enum WindowDivide {
 }
 
 impl WindowDivide {
-    fn reg_value(self) -> u8 {
+    pub(crate) fn reg_value(self) -> u8 {
         match self {
             WindowDivide::Before(v) => v & 0x1f,
             WindowDivide::After(v) => 0x80 | (v & 0x1f),
@@ -109,6 +137,371 @@ pub enum ScrollSize {
     Cell128 = 0b11,
 }
 
+impl ScrollSize {
+    /// Get the number of cells along this axis.
+    pub fn cells(self) -> u16 {
+        match self {
+            ScrollSize::Cell32 => 32,
+            ScrollSize::Cell64 => 64,
+            ScrollSize::Cell128 => 128,
+        }
+    }
+}
+
+/// An enumeration of the tables the VDP reads from VRAM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VramTable {
+    PlaneA,
+    PlaneB,
+    Window,
+    Sprites,
+    HScroll,
+}
+
+static ALL_VRAM_TABLES: [VramTable; 5] = [
+    VramTable::PlaneA,
+    VramTable::PlaneB,
+    VramTable::Window,
+    VramTable::Sprites,
+    VramTable::HScroll,
+];
+
+/// An error found when validating a `VdpConfig`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutError {
+    /// The plane size is larger than the VDP supports (4096 cells).
+    InvalidPlaneSize,
+    /// The table's address is not aligned as the VDP requires.
+    Misaligned(VramTable),
+    /// The table extends past the end of VRAM.
+    OutOfRange(VramTable),
+    /// The two tables share some VRAM.
+    Overlap(VramTable, VramTable),
+}
+
+/// The addresses of the tables the VDP reads from VRAM.
+#[derive(Clone, Copy, Debug)]
+pub struct VramLayout {
+    pub plane_a: u16,
+    pub plane_b: u16,
+    pub window: u16,
+    pub sprites: u16,
+    pub h_scroll: u16,
+}
+
+impl VramLayout {
+    /// Fetch the address of a single table.
+    pub fn address(&self, table: VramTable) -> u16 {
+        match table {
+            VramTable::PlaneA => self.plane_a,
+            VramTable::PlaneB => self.plane_b,
+            VramTable::Window => self.window,
+            VramTable::Sprites => self.sprites,
+            VramTable::HScroll => self.h_scroll,
+        }
+    }
+}
+
+impl Default for VramLayout {
+    fn default() -> Self {
+        VramLayout {
+            plane_a: 0xc000,
+            plane_b: 0xe000,
+            window: 0xd000,
+            sprites: 0xf000,
+            h_scroll: 0xf400,
+        }
+    }
+}
+
+/// The initial configuration of the VDP.
+///
+/// This selects the resolution, plane size and where each of the tables are
+/// placed in VRAM. `validate` checks the tables are correctly aligned and do
+/// not overlap for the chosen resolution and plane size.
+#[derive(Clone, Copy, Debug)]
+pub struct VdpConfig {
+    layout: VramLayout,
+    plane_width: ScrollSize,
+    plane_height: ScrollSize,
+    h40: bool,
+    v30: bool,
+    clear_vram: bool,
+}
+
+impl VdpConfig {
+    /// Create the default configuration.
+    ///
+    /// This is 40x28 cells, with 32x32 cell planes and the default VRAM layout.
+    pub fn new() -> VdpConfig {
+        VdpConfig {
+            layout: VramLayout::default(),
+            plane_width: ScrollSize::Cell32,
+            plane_height: ScrollSize::Cell32,
+            h40: true,
+            v30: false,
+            clear_vram: true,
+        }
+    }
+
+    /// Set the addresses of all of the tables.
+    pub fn layout(mut self, layout: VramLayout) -> VdpConfig {
+        self.layout = layout;
+        self
+    }
+
+    /// Set the size of the tile planes.
+    pub fn plane_size(mut self, width: ScrollSize, height: ScrollSize) -> VdpConfig {
+        self.plane_width = width;
+        self.plane_height = height;
+        self
+    }
+
+    /// Select the 40 cell wide (`h`) and 30 cell high (`v`) modes.
+    ///
+    /// Vertical 30-cell mode is only available on PAL systems.
+    pub fn resolution(mut self, h: bool, v: bool) -> VdpConfig {
+        self.h40 = h;
+        self.v30 = v;
+        self
+    }
+
+    /// Configure whether all of VRAM is cleared on initialisation.
+    pub fn clear_vram(mut self, clear: bool) -> VdpConfig {
+        self.clear_vram = clear;
+        self
+    }
+
+    /// Fetch the VRAM layout.
+    pub fn vram_layout(&self) -> &VramLayout { &self.layout }
+
+    /// Fetch the number of bytes of VRAM used by a table.
+    pub fn table_size(&self, table: VramTable) -> u16 {
+        match table {
+            VramTable::PlaneA | VramTable::PlaneB =>
+                self.plane_width.cells() * self.plane_height.cells() * 2,
+            VramTable::Window => if self.h40 { 64 * 32 * 2 } else { 32 * 32 * 2 },
+            VramTable::Sprites => if self.h40 { 80 * 8 } else { 64 * 8 },
+            // Enough for per-line scrolling.
+            VramTable::HScroll => if self.v30 { 240 * 4 } else { 224 * 4 },
+        }
+    }
+
+    /// Fetch the alignment required for the address of a table.
+    pub fn table_alignment(&self, table: VramTable) -> u16 {
+        match table {
+            VramTable::PlaneA | VramTable::PlaneB => 0x2000,
+            VramTable::Window => if self.h40 { 0x1000 } else { 0x800 },
+            VramTable::Sprites => if self.h40 { 0x400 } else { 0x200 },
+            VramTable::HScroll => 0x400,
+        }
+    }
+
+    fn table_range(&self, table: VramTable) -> Range<u32> {
+        let start = self.layout.address(table) as u32;
+        start..(start + self.table_size(table) as u32)
+    }
+
+    /// Check that the configuration is valid.
+    pub fn validate(&self) -> Result<(), LayoutError> {
+        if self.plane_width.cells() * self.plane_height.cells() > 4096 {
+            return Err(LayoutError::InvalidPlaneSize);
+        }
+
+        for (idx, &table) in ALL_VRAM_TABLES.iter().enumerate() {
+            if self.layout.address(table) & (self.table_alignment(table) - 1) != 0 {
+                return Err(LayoutError::Misaligned(table));
+            }
+
+            let range = self.table_range(table);
+            if range.end > registers::VRAM_SIZE {
+                return Err(LayoutError::OutOfRange(table));
+            }
+
+            for &other in &ALL_VRAM_TABLES[idx + 1..] {
+                let other_range = self.table_range(other);
+                if range.start < other_range.end && other_range.start < range.end {
+                    return Err(LayoutError::Overlap(table, other));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fetch the range of tile indices below the lowest table.
+    ///
+    /// These tiles can be used freely without overwriting any of the tables.
+    pub fn free_tiles(&self) -> Range<u16> {
+        let lowest = ALL_VRAM_TABLES.iter()
+            .map(|&t| self.layout.address(t))
+            .min()
+            .unwrap_or(0);
+        0..(lowest >> 5)
+    }
+}
+
+impl Default for VdpConfig {
+    fn default() -> Self {
+        VdpConfig::new()
+    }
+}
+
+// Approximate output levels of the VDP's DAC for each of the 8 intensities.
+static SHADOW_LEVELS: [u8; 8] = [0, 29, 52, 70, 87, 101, 116, 130];
+static NORMAL_LEVELS: [u8; 8] = [0, 52, 87, 116, 144, 172, 206, 255];
+static HIGHLIGHT_LEVELS: [u8; 8] = [130, 144, 158, 172, 187, 206, 228, 255];
+
+/// The brightness a pixel is drawn at in shadow / highlight mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Intensity {
+    Shadow,
+    Normal,
+    Highlight,
+}
+
+/// A single colour in the VDP's 9-bit format.
+///
+/// Each channel has 3 bits. In CRAM these are stored as `0000BBB0GGG0RRR0`.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Color(u16);
+
+impl Color {
+    /// Create a colour from 3-bit red, green and blue channels.
+    pub const fn new(r: u8, g: u8, b: u8) -> Color {
+        Color((((b & 7) as u16) << 9) | (((g & 7) as u16) << 5) | (((r & 7) as u16) << 1))
+    }
+
+    /// Create a colour from 8-bit red, green and blue channels.
+    ///
+    /// The lowest 5 bits of each channel are discarded.
+    pub const fn from_rgb888(r: u8, g: u8, b: u8) -> Color {
+        Color::new(r >> 5, g >> 5, b >> 5)
+    }
+
+    /// Create a colour from a hex value of the form `0xRRGGBB`.
+    pub const fn from_hex(hex: u32) -> Color {
+        Color::from_rgb888((hex >> 16) as u8, (hex >> 8) as u8, hex as u8)
+    }
+
+    /// Create a colour from the raw CRAM format.
+    pub const fn from_raw(raw: u16) -> Color {
+        Color(raw & 0xeee)
+    }
+
+    /// Fetch the colour in the raw CRAM format.
+    pub const fn raw(self) -> u16 { self.0 }
+
+    /// Get the 3-bit red channel.
+    pub const fn r(self) -> u8 { ((self.0 >> 1) & 7) as u8 }
+
+    /// Get the 3-bit green channel.
+    pub const fn g(self) -> u8 { ((self.0 >> 5) & 7) as u8 }
+
+    /// Get the 3-bit blue channel.
+    pub const fn b(self) -> u8 { ((self.0 >> 9) & 7) as u8 }
+
+    /// Convert this colour to approximate 8-bit red, green and blue channels.
+    pub fn to_rgb888(self) -> (u8, u8, u8) {
+        self.to_rgb888_at(Intensity::Normal)
+    }
+
+    /// Convert this colour to 8-bit channels, as displayed at the given intensity.
+    ///
+    /// Shadowed pixels are drawn at half brightness, and highlighted pixels
+    /// at half brightness plus a half.
+    pub fn to_rgb888_at(self, intensity: Intensity) -> (u8, u8, u8) {
+        let levels = match intensity {
+            Intensity::Shadow => &SHADOW_LEVELS,
+            Intensity::Normal => &NORMAL_LEVELS,
+            Intensity::Highlight => &HIGHLIGHT_LEVELS,
+        };
+        (levels[self.r() as usize], levels[self.g() as usize], levels[self.b() as usize])
+    }
+
+    /// Reduce the brightness of every channel by `steps`, saturating at black.
+    pub const fn darken(self, steps: u8) -> Color {
+        Color::new(self.r().saturating_sub(steps),
+                   self.g().saturating_sub(steps),
+                   self.b().saturating_sub(steps))
+    }
+
+    /// Increase the brightness of every channel by `steps`, saturating at white.
+    pub const fn lighten(self, steps: u8) -> Color {
+        const fn add(v: u8, steps: u8) -> u8 {
+            let v = v.saturating_add(steps);
+            if v > 7 { 7 } else { v }
+        }
+        Color::new(add(self.r(), steps), add(self.g(), steps), add(self.b(), steps))
+    }
+
+    /// Find the colour which most closely matches this one at another intensity.
+    ///
+    /// For example, `c.match_intensity(Intensity::Highlight, Intensity::Normal)`
+    /// returns the colour which looks like `c` when highlighted, but drawn
+    /// without shadow or highlight.
+    pub fn match_intensity(self, from: Intensity, to: Intensity) -> Color {
+        let (r, g, b) = self.to_rgb888_at(from);
+        let levels = match to {
+            Intensity::Shadow => &SHADOW_LEVELS,
+            Intensity::Normal => &NORMAL_LEVELS,
+            Intensity::Highlight => &HIGHLIGHT_LEVELS,
+        };
+        let nearest = |v: u8| {
+            (0..8u8)
+                .min_by_key(|&i| (levels[i as usize] as i16 - v as i16).abs())
+                .unwrap_or(0)
+        };
+        Color::new(nearest(r), nearest(g), nearest(b))
+    }
+}
+
+impl From<Color> for u16 {
+    fn from(c: Color) -> Self {
+        c.0
+    }
+}
+
+/// A full palette of 16 colours.
+///
+/// Colour 0 is transparent when used by tiles and sprites.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct RgbPalette(pub [Color; 16]);
+
+impl RgbPalette {
+    /// Create a palette from 16 hex values of the form `0xRRGGBB`.
+    pub const fn from_hex(hex: [u32; 16]) -> RgbPalette {
+        let mut colors = [Color(0); 16];
+        let mut i = 0;
+        while i < 16 {
+            colors[i] = Color::from_hex(hex[i]);
+            i += 1;
+        }
+        RgbPalette(colors)
+    }
+
+    /// Fetch the palette in the raw CRAM format.
+    pub fn raw(&self) -> &[u16; 16] {
+        unsafe { &*(self as *const RgbPalette as *const [u16; 16]) }
+    }
+}
+
+impl Deref for RgbPalette {
+    type Target = [Color; 16];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for RgbPalette {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
 const TILE_FLAG_PRIORITY: u16 = 0x8000;
 const TILE_FLAG_FLIP_H: u16 = 0x800;
 const TILE_FLAG_FLIP_V: u16 = 0x1000;
@@ -289,26 +682,43 @@ pub struct VDP {
     plane_b_base: u16,
     scroll_h_base: u16,
     window_base: u16,
+    plane_width: ScrollSize,
+    plane_height: ScrollSize,
     increment: u8,
 }
 
 impl VDP {
     /// Initialise and return the VDP.
     pub fn new() -> VDP {
+        VDP::from_config(&VdpConfig::new())
+    }
+
+    /// Initialise and return the VDP with the given configuration.
+    ///
+    /// The configuration is validated before the VDP is touched.
+    pub fn with_config(config: &VdpConfig) -> Result<VDP, LayoutError> {
+        config.validate()?;
+        Ok(VDP::from_config(config))
+    }
+
+    fn from_config(config: &VdpConfig) -> VDP {
+        let layout = config.vram_layout();
         let mut vdp = VDP {
-            mode: 0x81000404,
-            sprites_base: 0xf000,
-            plane_a_base: 0xc000,
-            plane_b_base: 0xe000,
-            scroll_h_base: 0xf400,
-            window_base: 0xd000,
+            mode: 0x404 | flag_32(0x81000000, config.h40) | flag_32(0x800, config.v30),
+            sprites_base: layout.sprites,
+            plane_a_base: layout.plane_a,
+            plane_b_base: layout.plane_b,
+            scroll_h_base: layout.h_scroll,
+            window_base: layout.window,
+            plane_width: config.plane_width,
+            plane_height: config.plane_height,
             increment: 0,
         };
-        vdp.init();
+        vdp.init(config);
         vdp
     }
 
-    fn init(&mut self) {
+    fn init(&mut self, config: &VdpConfig) {
         self.read_state_raw();
 
         // Initialise mode.
@@ -320,35 +730,68 @@ impl VDP {
         self.set_scroll_base(self.scroll_h_base);
 
         self.set_increment(2);
-        self.set_plane_size(ScrollSize::Cell32, ScrollSize::Cell32);
+        self.set_plane_size(config.plane_width, config.plane_height);
         self.set_window(WindowDivide::Before(0), WindowDivide::Before(0));
         self.set_background(0, 0);
         self.set_h_interrupt_interval(0xff);
 
         // Wipe RAM. This should not be strictly necessary since we should
         // write it as we use it and does have a slight performance penalty.
-        self.dma_set(AddrKind::VRAM, 0, 0, 0xffff);//registers::VRAM_SIZE as u16);
+        if config.clear_vram {
+            self.dma_set(AddrKind::VRAM, 0, 0, 0xffff);//registers::VRAM_SIZE as u16);
+        }
         self.dma_set(AddrKind::CRAM, 0, 0, registers::CRAM_SIZE);
         self.dma_set(AddrKind::VSRAM, 0, 0, registers::VSRAM_SIZE);
 
         // Default the palette
-        self.set_palette(0, &DEFAULT_PALETTE);
+        self.set_palette(0, DEFAULT_PALETTE.raw());
     }
 
     /// Read the VDP status register.
     pub fn read_state_raw(&self) -> u16 {
         unsafe {
-            read_volatile(REG_VDP_CONTROL16)
+            mmio::read_u16(REG_VDP_CONTROL16)
         }
     }
 
+    /// Read the VDP status register.
+    pub fn status(&self) -> VdpStatus {
+        VdpStatus(self.read_state_raw())
+    }
+
+    /// Wait until the vertical blanking period starts.
+    ///
+    /// If this is called during vertical blank, it waits for the next one.
+    pub fn wait_for_vblank_start(&self) {
+        while self.status().in_vblank() {}
+        while !self.status().in_vblank() {}
+    }
+
+    /// Wait until the vertical blanking period ends.
+    ///
+    /// This returns immediately if called outside of vertical blank.
+    pub fn wait_for_vblank_end(&self) {
+        while self.status().in_vblank() {}
+    }
+
+    /// Wait until the next horizontal blanking period starts.
+    pub fn wait_for_hblank_start(&self) {
+        while self.status().in_hblank() {}
+        while !self.status().in_hblank() {}
+    }
+
+    /// Wait until all pending writes have left the FIFO.
+    pub fn wait_for_fifo_empty(&self) {
+        while !self.status().fifo_empty() {}
+    }
+
     /// Set a single VDP register.
     ///
     /// This can cause the VDP to become out of sync with our state caching.
     /// Where possible it is best to use the specific methods in `VDP`.
     pub fn set_register(&mut self, reg: u8, value: u8) {
         let v = ((reg as u16) << 8) | (value as u16);
-        unsafe { write_volatile(REG_VDP_CONTROL16, v) };
+        unsafe { mmio::write_u16(REG_VDP_CONTROL16, v) };
     }
 
     /// Set the address increment on write.
@@ -364,29 +807,22 @@ impl VDP {
 
     /// Write data to VRAM at the current write address.
     pub fn write_data(&mut self, data: u16) {
-        unsafe { write_volatile(REG_VDP_DATA16, data) };
+        unsafe { mmio::write_u16(REG_VDP_DATA16, data) };
     }
 
-    fn set_addr_raw(&mut self, kind: AddrKind, ptr: u16, dma: bool) {
-        let ctrl = match kind {
-            AddrKind::VRAM => 0b00001,
-            AddrKind::CRAM => 0b00011,
-            AddrKind::VSRAM => 0b00101,
-        };
-        let dma_flag = if dma { 0x80 } else { 0 };
-        let hi = ((ptr >> 14) & 3) | ((ctrl >> 2) << 4) | dma_flag;
-        let lo = (ptr & 0x3fff) | (ctrl << 14);
+    fn set_addr_raw(&mut self, kind: AddrKind, ptr: u16, write: bool, dma: bool) {
+        let (lo, hi) = addr_words(kind, ptr, write, dma);
 
         unsafe {
             if dma {
                 static mut SCRATCH: [u16; 2] = [0, 0];
                 write_volatile(&mut SCRATCH[0], lo);
                 write_volatile(&mut SCRATCH[1], hi);
-                write_volatile(REG_VDP_CONTROL16, read_volatile(&SCRATCH[0]));
-                write_volatile(REG_VDP_CONTROL16, read_volatile(&SCRATCH[1]));
+                mmio::write_u16(REG_VDP_CONTROL16, read_volatile(&SCRATCH[0]));
+                mmio::write_u16(REG_VDP_CONTROL16, read_volatile(&SCRATCH[1]));
             } else {
-                write_volatile(REG_VDP_CONTROL16, lo);
-                write_volatile(REG_VDP_CONTROL16, hi);
+                mmio::write_u16(REG_VDP_CONTROL16, lo);
+                mmio::write_u16(REG_VDP_CONTROL16, hi);
             }
         }
     }
@@ -395,15 +831,60 @@ impl VDP {
     ///
     /// This will be incremented after every write via `write_data`.
     pub fn set_address(&mut self, kind: AddrKind, ptr: u16) {
-        self.set_addr_raw(kind, ptr, false);
+        self.set_addr_raw(kind, ptr, true, false);
     }
 
-    fn wait_for_dma(&self) {
-        unsafe {
-            while read_volatile(REG_VDP_CONTROL16) & 2 != 0 {}
+    /// Set the VRAM read address.
+    ///
+    /// This will be incremented after every read via `read_data`.
+    pub fn set_read_address(&mut self, kind: AddrKind, ptr: u16) {
+        self.wait_for_dma();
+        self.set_addr_raw(kind, ptr, false, false);
+    }
+
+    /// Read data from VRAM at the current read address.
+    pub fn read_data(&mut self) -> u16 {
+        unsafe { mmio::read_u16(REG_VDP_DATA16) }
+    }
+
+    fn read_words(&mut self, kind: AddrKind, addr: u16, dst: &mut [u16], mask: u16) {
+        self.set_increment(2);
+        self.set_read_address(kind, addr);
+
+        for v in dst.iter_mut() {
+            *v = self.read_data() & mask;
         }
     }
 
+    /// Read words from VRAM, starting at the given byte address.
+    pub fn read_vram(&mut self, addr: u16, dst: &mut [u16]) {
+        self.read_words(AddrKind::VRAM, addr, dst, 0xffff);
+    }
+
+    /// Read colours from CRAM, starting at the given byte address.
+    ///
+    /// Only the colour bits are returned, the rest of each word is cleared.
+    pub fn read_cram(&mut self, addr: u16, dst: &mut [u16]) {
+        self.read_words(AddrKind::CRAM, addr, dst, 0xeee);
+    }
+
+    /// Read scroll values from VSRAM, starting at the given byte address.
+    ///
+    /// Only the 11 scroll bits are returned, the rest of each word is cleared.
+    pub fn read_vsram(&mut self, addr: u16, dst: &mut [u16]) {
+        self.read_words(AddrKind::VSRAM, addr, dst, 0x7ff);
+    }
+
+    /// Read back one of the 4 configurable palettes.
+    pub fn read_palette(&mut self, index: u16, palette: &mut [u16; 16]) {
+        assert!(index < 4, "only 4 palettes");
+        self.read_cram(index << 5, palette);
+    }
+
+    fn wait_for_dma(&self) {
+        while self.status().dma_busy() {}
+    }
+
     /// Upload memory from ROM or RAM to VRAM.
     pub fn dma_upload(&mut self, kind: AddrKind, dst_addr: u16, src_addr: *const (), length: u16) {
         let mut length = length as u32;
@@ -412,17 +893,19 @@ impl VDP {
 
         self.enable_dma(true);
         while length > 0 {
-            let this_block = (0x20000 - (0x1ffff & src_addr)).min(length);
+            // The source address is in words and the VDP wraps it at each
+            // 128KiB boundary, so split the transfer there.
+            let this_block = (0x10000 - (0xffff & src_addr)).min(length);
 
             self.set_register(registers::DMA_LEN_L, this_block as u8);
             self.set_register(registers::DMA_LEN_H, (this_block >> 8) as u8);
             self.set_register(registers::DMA_SRC_L, src_addr as u8);
             self.set_register(registers::DMA_SRC_M, (src_addr >> 8) as u8);
             self.set_register(registers::DMA_SRC_H, (src_addr >> 16) as u8);
-            self.set_addr_raw(kind, dst_addr as u16, true);
+            self.set_addr_raw(kind, dst_addr as u16, true, true);
             self.wait_for_dma();
 
-            dst_addr += this_block;
+            dst_addr += this_block << 1;
             src_addr += this_block;
             length -= this_block;
         }
@@ -444,7 +927,7 @@ impl VDP {
         self.set_register(registers::DMA_LEN_L, length as u8);
         self.set_register(registers::DMA_LEN_H, (length >> 8) as u8);
         self.set_register(registers::DMA_SRC_H, 0x80);
-        self.set_addr_raw(kind, dst_addr, true);
+        self.set_addr_raw(kind, dst_addr, true, true);
         self.write_data(fill as u16);
         self.wait_for_dma();
         self.enable_dma(false);
@@ -459,7 +942,7 @@ impl VDP {
         self.set_register(registers::DMA_SRC_L, src_addr as u8);
         self.set_register(registers::DMA_SRC_M, (src_addr >> 8) as u8);
         self.set_register(registers::DMA_SRC_H, 0xc0);
-        self.set_addr_raw(kind, dst_addr, true);
+        self.set_addr_raw(kind, dst_addr, true, true);
         self.wait_for_dma();
         self.enable_dma(false);
     }
@@ -537,10 +1020,44 @@ impl VDP {
     }
 
     /// Stop the HV counter.
+    ///
+    /// Whilst stopped, the HV counter holds the position latched by the last
+    /// external interrupt (such as a light gun trigger).
     pub fn stop_hv_counter(&mut self, stopped: bool) {
         self.modify_mode(2, flag_32(2, stopped));
     }
 
+    /// Fetch the current addresses of the tables in VRAM.
+    pub fn vram_layout(&self) -> VramLayout {
+        VramLayout {
+            plane_a: self.plane_a_base,
+            plane_b: self.plane_b_base,
+            window: self.window_base,
+            sprites: self.sprites_base,
+            h_scroll: self.scroll_h_base,
+        }
+    }
+
+    /// Fetch a reader for the HV counter matching the current display mode.
+    ///
+    /// This needs to be fetched again if the resolution or interlace mode
+    /// is changed.
+    pub fn hv_counter(&self) -> HvCounter {
+        let interlace = match (self.mode >> 25) & 3 {
+            0b01 => InterlaceMode::Interlace,
+            0b11 => InterlaceMode::DoubleRes,
+            _ => InterlaceMode::None,
+        };
+
+        HvCounter {
+            h40: (self.mode & 0x1000000) != 0,
+            v30: (self.mode & 0x800) != 0,
+            pal: super::version().is_pal(),
+            interlace,
+            latched: (self.mode & 2) != 0,
+        }
+    }
+
     /// Enable the increased resolution 40x30-cell mode.
     ///
     /// Vertical 30-cell mode is only available on PAL systems.
@@ -576,6 +1093,8 @@ impl VDP {
 
     /// Set the size of the tile planes (plane A, plane B and the window plane).
     pub fn set_plane_size(&mut self, x: ScrollSize, y: ScrollSize) {
+        self.plane_width = x;
+        self.plane_height = y;
         let v = (x as u8) | ((y as u8) << 4);
         self.set_register(registers::SIZE, v);
     }
@@ -603,9 +1122,11 @@ impl VDP {
     }
 
     /// Set the base address for the window plane.
+    ///
+    /// This must be aligned to 2KB, or 4KB in 40-cell mode.
     pub fn set_window_base(&mut self, address: u16) {
         self.window_base = address;
-        self.set_register(registers::WINDOW, ((self.window_base >> 10) & 0x38) as u8);
+        self.set_register(registers::WINDOW, ((self.window_base >> 10) & 0x3e) as u8);
     }
 
     /// Set the base address for the scrolling matrix.
@@ -631,6 +1152,19 @@ impl VDP {
         self.dma_upload_word_slice(AddrKind::CRAM, index << 5, palette);
     }
 
+    /// Set all 4 palettes (all 64 colours) at once.
+    pub fn set_palettes(&mut self, palettes: &[RgbPalette; 4]) {
+        self.dma_upload_word_slice(AddrKind::CRAM, 0, palettes);
+    }
+
+    /// Set a single colour in one of the 4 palettes.
+    pub fn set_color(&mut self, palette: u16, index: u16, color: Color) {
+        assert!(palette < 4, "only 4 palettes");
+        assert!(index < 16, "only 16 colours per palette");
+        self.set_address(AddrKind::CRAM, (palette << 5) | (index << 1));
+        self.write_data(color.0);
+    }
+
     /// Set the contents of some tiles in VRAM.
     pub fn set_tiles_iter<T>(&mut self, start_index: u16, tiles: impl Iterator<Item=T>)
         where T: Deref<Target=Tile>
@@ -641,7 +1175,7 @@ impl VDP {
             unsafe {
                 let ptr: *const u16 = core::mem::transmute(tile.deref());
                 for i in 0..16isize {
-                    write_volatile(REG_VDP_DATA16, *ptr.offset(i));
+                    mmio::write_u16(REG_VDP_DATA16, *ptr.offset(i));
                 }
             }
         }
@@ -667,7 +1201,7 @@ impl VDP {
             unsafe {
                 let src: *const u16 = core::mem::transmute(sprite.deref());
                 for i in 0..4isize {
-                    write_volatile(REG_VDP_DATA16, *src.offset(i));
+                    mmio::write_u16(REG_VDP_DATA16, *src.offset(i));
                 }
             }
         }
@@ -718,4 +1252,497 @@ impl VDP {
                         (first_index << 1) + self.window_base,
                         values);
     }
+
+    fn plane_base(&self, plane: Plane) -> u16 {
+        match plane {
+            Plane::A => self.plane_a_base,
+            Plane::B => self.plane_b_base,
+            Plane::Window => self.window_base,
+        }
+    }
+
+    /// Fetch the size of one of the planes in cells.
+    ///
+    /// Planes A and B use the size configured by `set_plane_size`. The window
+    /// plane is always 32 cells high, and 32 or 64 cells wide depending on
+    /// the horizontal resolution.
+    pub fn plane_size(&self, plane: Plane) -> (u16, u16) {
+        match plane {
+            Plane::Window => (if (self.mode & 0x1000000) != 0 { 64 } else { 32 }, 32),
+            _ => (self.plane_width.cells(), self.plane_height.cells()),
+        }
+    }
+
+    fn cell_address(&self, plane: Plane, x: u16, y: u16) -> u16 {
+        let (w, h) = self.plane_size(plane);
+        let index = (y & (h - 1)) * w + (x & (w - 1));
+        (index << 1) + self.plane_base(plane)
+    }
+
+    /// Set the tile flags of a single cell in one of the planes.
+    ///
+    /// The coordinates wrap around the edges of the plane.
+    pub fn set_cell(&mut self, plane: Plane, x: u16, y: u16, flags: TileFlags) {
+        let addr = self.cell_address(plane, x, y);
+        self.set_address(AddrKind::VRAM, addr);
+        self.write_data(flags.0);
+    }
+
+    /// Set a row of cells in one of the planes, starting at (x, y).
+    ///
+    /// The row wraps around to the start of the same line.
+    pub fn set_row(&mut self, plane: Plane, x: u16, y: u16, values: &[TileFlags]) {
+        let (w, _) = self.plane_size(plane);
+        let mut x = x & (w - 1);
+        let mut values = values;
+
+        while !values.is_empty() {
+            let count = ((w - x) as usize).min(values.len());
+            let addr = self.cell_address(plane, x, y);
+            self.dma_upload_word_slice(AddrKind::VRAM, addr, &values[..count]);
+            values = &values[count..];
+            x = 0;
+        }
+    }
+
+    /// Set a column of cells in one of the planes, starting at (x, y).
+    ///
+    /// The column wraps around to the top of the plane.
+    pub fn set_column(&mut self, plane: Plane, x: u16, y: u16, values: &[TileFlags]) {
+        let (w, h) = self.plane_size(plane);
+        let stride = w << 1;
+        let mut y = y & (h - 1);
+        let mut values = values;
+
+        if stride > 0xff {
+            // The row stride does not fit in the increment register.
+            for v in values {
+                self.set_cell(plane, x, y, *v);
+                y = (y + 1) & (h - 1);
+            }
+            return;
+        }
+
+        self.set_increment(stride as u8);
+        while !values.is_empty() {
+            let count = ((h - y) as usize).min(values.len());
+            let addr = self.cell_address(plane, x, y);
+            self.dma_upload(AddrKind::VRAM, addr, values.as_ptr() as _, dma_len(&values[..count]));
+            values = &values[count..];
+            y = 0;
+        }
+        self.set_increment(2);
+    }
+
+    /// Set a rectangle of cells in one of the planes.
+    ///
+    /// `values` is in row-major order and must contain at least `w * h`
+    /// entries. The rectangle wraps around the edges of the plane.
+    pub fn blit_rect(&mut self, plane: Plane, x: u16, y: u16, w: u16, h: u16, values: &[TileFlags]) {
+        let w = w as usize;
+        assert!(values.len() >= w * (h as usize), "not enough tiles for rectangle");
+        if w == 0 {
+            return;
+        }
+
+        for (row, line) in values.chunks(w).take(h as usize).enumerate() {
+            self.set_row(plane, x, y.wrapping_add(row as u16), line);
+        }
+    }
+
+    /// Read the tile flags of a single cell from one of the planes.
+    pub fn read_plane_tile(&mut self, plane: Plane, index: u16) -> TileFlags {
+        let addr = (index << 1) + self.plane_base(plane);
+        self.set_read_address(AddrKind::VRAM, addr);
+        TileFlags(self.read_data())
+    }
+
+    /// Modify the tile flags of a single cell in one of the planes.
+    ///
+    /// The current flags are read back from VRAM, passed to `f` and the result
+    /// is written back.
+    pub fn modify_plane_tile(&mut self, plane: Plane, index: u16, f: impl FnOnce(TileFlags) -> TileFlags) {
+        let addr = (index << 1) + self.plane_base(plane);
+        let flags = self.read_plane_tile(plane, index);
+        self.set_address(AddrKind::VRAM, addr);
+        self.write_data(f(flags).0);
+    }
+
+    /// Queue one of the 4 configurable palettes for upload.
+    ///
+    /// This is the deferred equivalent of `set_palette`.
+    pub fn queue_palette(&self, queue: &mut DmaQueue, index: u16, palette: &'static [u16; 16]) -> bool {
+        assert!(index < 4, "only 4 palettes");
+        queue.push(AddrKind::CRAM, index << 5, palette)
+    }
+
+    /// Queue tiles for upload.
+    ///
+    /// This is the deferred equivalent of `set_tiles`.
+    pub fn queue_tiles(&self, queue: &mut DmaQueue, start_index: u16, tiles: &'static [Tile]) -> bool {
+        queue.push(AddrKind::VRAM, start_index << 5, tiles)
+    }
+
+    /// Queue sprites for upload into the sprite table.
+    ///
+    /// This is the deferred equivalent of `set_sprites`.
+    pub fn queue_sprites(&self, queue: &mut DmaQueue, first_index: u16, sprites: &'static [Sprite]) -> bool {
+        queue.push(AddrKind::VRAM, (first_index << 3) + self.sprites_base, sprites)
+    }
+
+    /// Queue the horizontal scroll for planes A and B.
+    ///
+    /// This is the deferred equivalent of `set_h_scroll`.
+    pub fn queue_h_scroll(&self, queue: &mut DmaQueue, first_index: u16, values: &'static [i16]) -> bool {
+        queue.push(AddrKind::VRAM, (first_index << 1) + self.scroll_h_base, values)
+    }
+
+    /// Queue the vertical scroll for planes A and B.
+    ///
+    /// This is the deferred equivalent of `set_v_scroll`.
+    pub fn queue_v_scroll(&self, queue: &mut DmaQueue, first_index: u16, values: &'static [i16]) -> bool {
+        queue.push(AddrKind::VSRAM, first_index << 1, values)
+    }
+
+    /// Queue tile flags for plane A.
+    ///
+    /// This is the deferred equivalent of `set_plane_a_tiles`.
+    pub fn queue_plane_a_tiles(&self, queue: &mut DmaQueue, first_index: u16, values: &'static [TileFlags]) -> bool {
+        queue.push(AddrKind::VRAM, (first_index << 1) + self.plane_a_base, values)
+    }
+
+    /// Queue tile flags for plane B.
+    ///
+    /// This is the deferred equivalent of `set_plane_b_tiles`.
+    pub fn queue_plane_b_tiles(&self, queue: &mut DmaQueue, first_index: u16, values: &'static [TileFlags]) -> bool {
+        queue.push(AddrKind::VRAM, (first_index << 1) + self.plane_b_base, values)
+    }
+
+    /// Queue tile flags for the window plane.
+    ///
+    /// This is the deferred equivalent of `set_window_tiles`.
+    pub fn queue_window_tiles(&self, queue: &mut DmaQueue, first_index: u16, values: &'static [TileFlags]) -> bool {
+        queue.push(AddrKind::VRAM, (first_index << 1) + self.window_base, values)
+    }
+}
+
+/// The status register of the VDP.
+#[derive(Clone, Copy, Debug)]
+pub struct VdpStatus(u16);
+
+impl VdpStatus {
+    /// Fetch the raw value of the status register.
+    pub fn raw(self) -> u16 { self.0 }
+
+    /// Returns true if the write FIFO is empty.
+    pub fn fifo_empty(self) -> bool { (self.0 & 0x200) != 0 }
+
+    /// Returns true if the write FIFO is full.
+    pub fn fifo_full(self) -> bool { (self.0 & 0x100) != 0 }
+
+    /// Returns true if a vertical interrupt has occurred and not yet been acknowledged.
+    pub fn v_interrupt_pending(self) -> bool { (self.0 & 0x80) != 0 }
+
+    /// Returns true if too many sprites were drawn on a single line.
+    ///
+    /// This is cleared when the status register is read.
+    pub fn sprite_overflow(self) -> bool { (self.0 & 0x40) != 0 }
+
+    /// Returns true if two sprites have non-transparent pixels which overlap.
+    ///
+    /// This is cleared when the status register is read.
+    pub fn sprite_collision(self) -> bool { (self.0 & 0x20) != 0 }
+
+    /// Returns true if the odd frame is being drawn in interlaced mode.
+    pub fn odd_frame(self) -> bool { (self.0 & 0x10) != 0 }
+
+    /// Returns true during the vertical blanking period.
+    pub fn in_vblank(self) -> bool { (self.0 & 8) != 0 }
+
+    /// Returns true during the horizontal blanking period.
+    pub fn in_hblank(self) -> bool { (self.0 & 4) != 0 }
+
+    /// Returns true whilst a DMA transfer is in progress.
+    pub fn dma_busy(self) -> bool { (self.0 & 2) != 0 }
+
+    /// Returns true if this is a PAL system.
+    pub fn is_pal(self) -> bool { (self.0 & 1) != 0 }
+}
+
+/// A position decoded from the HV counter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HvPosition {
+    /// The horizontal position in pixels.
+    ///
+    /// Positions in the horizontal blank after the counter jumps are negative.
+    pub x: i16,
+
+    /// The current scanline.
+    ///
+    /// Lines in the vertical blank after the counter jumps (those just before
+    /// the first visible line) are negative.
+    pub line: i16,
+}
+
+/// A reader for the VDP's HV counter.
+///
+/// The raw counter is not linear: it jumps backwards during the horizontal
+/// and vertical blanking periods, and the points at which it does depend on
+/// the resolution, the interlace mode and whether the console is PAL. This
+/// decodes the raw value into a pixel position and scanline.
+///
+/// Some vertical blank lines repeat raw values which are also used before the
+/// jump. These are reported as the earlier line, so positions inside vertical
+/// blank are approximate.
+#[derive(Clone, Copy, Debug)]
+pub struct HvCounter {
+    h40: bool,
+    v30: bool,
+    pal: bool,
+    interlace: InterlaceMode,
+    latched: bool,
+}
+
+impl HvCounter {
+    /// Read the raw HV counter.
+    ///
+    /// The V counter is in the high byte and the H counter in the low byte.
+    pub fn read_raw() -> u16 {
+        unsafe { mmio::read_u16(REG_VDP_HV_COUNTER16) }
+    }
+
+    /// Returns true if the counter was stopped when this reader was fetched.
+    ///
+    /// In this case, reads return the latched position rather than the
+    /// current one.
+    pub fn is_latched(&self) -> bool { self.latched }
+
+    /// Read and decode the current position.
+    pub fn read(&self) -> HvPosition {
+        let status = VdpStatus(unsafe { mmio::read_u16(REG_VDP_CONTROL16) });
+        self.decode(HvCounter::read_raw(), status.in_vblank())
+    }
+
+    /// Read the current scanline.
+    pub fn line(&self) -> i16 {
+        self.read().line
+    }
+
+    /// Decode a raw HV counter value.
+    ///
+    /// `in_vblank` is used to tell apart the lines which share raw values on
+    /// PAL systems, it should be read from the status register alongside the
+    /// counter.
+    pub fn decode(&self, raw: u16, in_vblank: bool) -> HvPosition {
+        let h = raw & 0xff;
+        let h_last = if self.h40 { 0xb6 } else { 0x93 };
+        let x = if h > h_last { h as i16 - 0x100 } else { h as i16 };
+
+        let mut v = (raw >> 8) as u8;
+        if let InterlaceMode::DoubleRes = self.interlace {
+            // In double resolution mode, the counter is shifted left and
+            // the top bit is moved into bit 0.
+            v = v.rotate_right(1);
+        }
+
+        // (lines past 0xff before the jump, first value after the jump, last value before the jump)
+        let (wrap, jump_to, jump_from) = match (self.pal, self.v30) {
+            (false, false) => (0, 0xe5, 0xea),
+            (false, true) => (6, 0x100, 0xff),
+            (true, false) => (3, 0xca, 0xff),
+            (true, true) => (11, 0xd2, 0xff),
+        };
+        let visible = if self.v30 { 240 } else { 224 };
+        let v = v as u16;
+
+        let line = if in_vblank && v < wrap {
+            v as i16 + 0x100
+        } else if v >= jump_to && (v > jump_from || (in_vblank && v < visible)) {
+            v as i16 - 0x100
+        } else {
+            v as i16
+        };
+
+        HvPosition {
+            x: x << 1,
+            line,
+        }
+    }
+}
+
+/// The maximum number of separate transfers a `DmaQueue` can hold.
+///
+/// Adjacent transfers are merged, so this is only reached by scattered updates.
+pub const MAX_DMA_TRANSFERS: usize = 32;
+
+/// The default number of bytes a `DmaQueue` will transfer per flush.
+///
+/// This is a conservative figure which fits in an NTSC vertical blank in
+/// both H32 and H40 modes.
+pub const DEFAULT_DMA_BUDGET: u32 = 6144;
+
+#[derive(Clone, Copy, Debug)]
+struct DmaTransfer {
+    kind: AddrKind,
+    dst_addr: u16,
+    src_addr: u32,
+    length: u16,
+}
+
+impl DmaTransfer {
+    const EMPTY: DmaTransfer = DmaTransfer {
+        kind: AddrKind::VRAM,
+        dst_addr: 0,
+        src_addr: 0,
+        length: 0,
+    };
+
+    fn try_merge(&mut self, other: &DmaTransfer) -> bool {
+        let bytes = (self.length as u32) << 1;
+        let total = self.length as u32 + other.length as u32;
+        let adjacent = self.kind == other.kind
+            && self.dst_addr as u32 + bytes == other.dst_addr as u32
+            && self.src_addr + bytes == other.src_addr;
+
+        if adjacent && total <= 0xffff {
+            self.length = total as u16;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A queue of DMA transfers to be performed during vertical blank.
+///
+/// Uploading to the VDP whilst the display is active is slow and can cause
+/// visible artifacts (such as CRAM dots). Instead, transfers can be recorded
+/// here during game logic and performed by calling `flush` from the vblank
+/// handler.
+///
+/// Each flush is limited to a budget of bytes, any transfers which do not
+/// fit are carried over to the next flush.
+pub struct DmaQueue {
+    transfers: [DmaTransfer; MAX_DMA_TRANSFERS],
+    num_transfers: usize,
+    budget: u32,
+}
+
+impl DmaQueue {
+    /// Create a new, empty queue with the default budget.
+    pub const fn new() -> DmaQueue {
+        DmaQueue::with_budget(DEFAULT_DMA_BUDGET)
+    }
+
+    /// Create a new, empty queue which transfers at most `budget` bytes per flush.
+    pub const fn with_budget(budget: u32) -> DmaQueue {
+        DmaQueue {
+            transfers: [DmaTransfer::EMPTY; MAX_DMA_TRANSFERS],
+            num_transfers: 0,
+            budget,
+        }
+    }
+
+    /// Fetch the number of bytes transferred per flush.
+    pub fn budget(&self) -> u32 { self.budget }
+
+    /// Set the number of bytes transferred per flush.
+    pub fn set_budget(&mut self, budget: u32) {
+        self.budget = budget;
+    }
+
+    /// Returns the number of pending transfers.
+    pub fn len(&self) -> usize { self.num_transfers }
+
+    /// Returns true if there are no pending transfers.
+    pub fn is_empty(&self) -> bool { self.num_transfers == 0 }
+
+    /// Returns the number of bytes still waiting to be transferred.
+    pub fn pending_bytes(&self) -> u32 {
+        self.transfers[..self.num_transfers].iter()
+            .map(|t| (t.length as u32) << 1)
+            .sum()
+    }
+
+    /// Discard all pending transfers.
+    pub fn clear(&mut self) {
+        self.num_transfers = 0;
+    }
+
+    /// Queue a transfer from ROM or RAM to one of the VDP memories.
+    ///
+    /// The length is specified in words. Returns false if the queue is full.
+    ///
+    /// # Safety
+    /// The source memory is only read when the queue is flushed. It must
+    /// remain valid until then.
+    pub unsafe fn push_raw(&mut self, kind: AddrKind, dst_addr: u16, src_addr: *const (), length: u16) -> bool {
+        if length == 0 {
+            return true;
+        }
+
+        let transfer = DmaTransfer {
+            kind,
+            dst_addr,
+            src_addr: src_addr as u32,
+            length,
+        };
+
+        if self.num_transfers > 0 && self.transfers[self.num_transfers - 1].try_merge(&transfer) {
+            return true;
+        }
+
+        if self.num_transfers >= MAX_DMA_TRANSFERS {
+            return false;
+        }
+
+        self.transfers[self.num_transfers] = transfer;
+        self.num_transfers += 1;
+        true
+    }
+
+    /// Queue a slice to be transferred to one of the VDP memories.
+    ///
+    /// Returns false if the queue is full.
+    pub fn push<T>(&mut self, kind: AddrKind, dst_addr: u16, src: &'static [T]) -> bool {
+        unsafe { self.push_raw(kind, dst_addr, src.as_ptr() as _, dma_len(src)) }
+    }
+
+    /// Perform queued transfers, up to the budget.
+    ///
+    /// This should be called during vertical blank. Returns the number of
+    /// bytes transferred.
+    pub fn flush(&mut self, vdp: &mut VDP) -> u32 {
+        let mut remaining = self.budget;
+        let mut done = 0;
+
+        vdp.set_increment(2);
+        while done < self.num_transfers && remaining >= 2 {
+            let transfer = &mut self.transfers[done];
+            let length = (transfer.length as u32).min(remaining >> 1) as u16;
+            let bytes = (length as u32) << 1;
+
+            vdp.dma_upload(transfer.kind, transfer.dst_addr, transfer.src_addr as _, length);
+            remaining -= bytes;
+
+            if length < transfer.length {
+                transfer.length -= length;
+                transfer.dst_addr = transfer.dst_addr.wrapping_add(bytes as u16);
+                transfer.src_addr += bytes;
+                break;
+            }
+
+            done += 1;
+        }
+
+        self.transfers.copy_within(done..self.num_transfers, 0);
+        self.num_transfers -= done;
+        self.budget - remaining
+    }
+}
+
+impl Default for DmaQueue {
+    fn default() -> Self {
+        DmaQueue::new()
+    }
 }
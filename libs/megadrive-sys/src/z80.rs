@@ -1,10 +1,21 @@
-use core::ptr::write_volatile;
+use core::marker::PhantomData;
+use core::mem::{size_of, MaybeUninit};
+
+use crate::mmio;
 
 const Z80_RAM_BASE: u32 = 0xa00000;
 const Z80_RAM_SIZE: u32 = 0x2000;
 const Z80_CTRL_BASE: u32 = 0xa11100;
-const Z80_BUS_REQ: *mut u16 = Z80_CTRL_BASE as _;
-const Z80_RESET: *mut u16 = (Z80_CTRL_BASE + 0x100) as _;
+const Z80_BUS_REQ: u32 = Z80_CTRL_BASE;
+const Z80_RESET: u32 = Z80_CTRL_BASE + 0x100;
+const Z80_BANK_REG: u32 = 0xa06000;
+
+/// The size of the 68k memory visible through the Z80's bank window.
+pub const BANK_SIZE: u32 = 0x8000;
+/// The Z80 address at which the bank window starts.
+pub const BANK_WINDOW: u16 = 0x8000;
+/// The end of the cartridge ROM in the 68k address space.
+pub(crate) const ROM_END: u32 = 0x400000;
 
 /// Request the bus from the Z80.
 ///
@@ -12,9 +23,9 @@ const Z80_RESET: *mut u16 = (Z80_CTRL_BASE + 0x100) as _;
 pub fn request_bus(r: bool) {
     unsafe {
         if r {
-            write_volatile(Z80_BUS_REQ, 0x100);
+            mmio::write_u16(Z80_BUS_REQ, 0x100);
         } else {
-            write_volatile(Z80_BUS_REQ, 0);
+            mmio::write_u16(Z80_BUS_REQ, 0);
         }
     }
 }
@@ -26,9 +37,9 @@ pub fn request_bus(r: bool) {
 pub fn halt(r: bool) {
     unsafe {
         if r {
-            write_volatile(Z80_RESET, 0);
+            mmio::write_u16(Z80_RESET, 0);
         } else {
-            write_volatile(Z80_RESET, 0x100);
+            mmio::write_u16(Z80_RESET, 0x100);
         }
     }
 }
@@ -42,10 +53,254 @@ pub fn reset() {
     request_bus(false);
 }
 
+/// Returns true if the Z80 has released the bus after a request.
+pub fn bus_granted() -> bool {
+    unsafe { (mmio::read_u16(Z80_BUS_REQ) & 0x100) == 0 }
+}
+
 /// Access the Z80's RAM.
 ///
 /// The bus must be requested for accesses into this slice to be safe,
 /// though it is safe to call this function beforehand.
+#[deprecated(note = "use `Z80::bus` instead")]
 pub unsafe fn ram() -> &'static mut [u8] {
     core::slice::from_raw_parts_mut(Z80_RAM_BASE as _, Z80_RAM_SIZE as usize)
 }
+
+static mut Z80_TAKEN: bool = false;
+
+/// The owner of the Z80.
+///
+/// Only one of these can exist. At startup the Z80 is stopped with its bus
+/// requested, which allows the 68k to use the YM2612 directly. Once a program
+/// has been loaded, the Z80 runs and the bus is only held by a `BusGuard`.
+pub struct Z80 {
+    bus_held: bool,
+}
+
+impl Z80 {
+    /// Take ownership of the Z80.
+    ///
+    /// Returns `None` if it has already been taken.
+    pub fn take() -> Option<Z80> {
+        unsafe {
+            if Z80_TAKEN {
+                None
+            } else {
+                Z80_TAKEN = true;
+                Some(Z80 { bus_held: true })
+            }
+        }
+    }
+
+    /// Returns true if the Z80 is running a program.
+    pub fn is_running(&self) -> bool { !self.bus_held }
+
+    /// Stop the Z80 and take its bus until the guard is dropped.
+    ///
+    /// This waits for the Z80 to release the bus.
+    pub fn bus(&mut self) -> BusGuard<'_> {
+        if !self.bus_held {
+            request_bus(true);
+            while !bus_granted() {}
+        }
+        BusGuard { z80: self }
+    }
+
+    /// Load a program into the Z80's RAM and start running it from address 0.
+    pub fn load_program(&mut self, program: &[u8]) {
+        assert!(program.len() <= Z80_RAM_SIZE as usize, "Z80 program too large");
+
+        // The Z80 must be out of reset for its RAM to be accessible.
+        request_bus(true);
+        halt(false);
+        while !bus_granted() {}
+
+        for (i, &b) in program.iter().enumerate() {
+            unsafe { mmio::write_u8(Z80_RAM_BASE + i as u32, b) };
+        }
+
+        halt(true);
+        request_bus(false);
+        halt(false);
+        self.bus_held = false;
+    }
+
+    /// Stop the Z80 and keep its bus requested.
+    ///
+    /// This returns the Z80 to the state it is in at startup.
+    pub fn stop(&mut self) {
+        request_bus(true);
+        halt(true);
+        halt(false);
+        while !bus_granted() {}
+        self.bus_held = true;
+    }
+}
+
+/// Exclusive access to the Z80's RAM whilst its bus is requested.
+///
+/// The bus is released when this is dropped, unless the Z80 is stopped.
+pub struct BusGuard<'a> {
+    z80: &'a mut Z80,
+}
+
+impl<'a> BusGuard<'a> {
+    fn address(addr: u16, len: usize) -> u32 {
+        assert!(addr as usize + len <= Z80_RAM_SIZE as usize, "Z80 RAM access out of range");
+        Z80_RAM_BASE + addr as u32
+    }
+
+    /// Read a byte from Z80 RAM.
+    pub fn read_u8(&self, addr: u16) -> u8 {
+        unsafe { mmio::read_u8(Self::address(addr, 1)) }
+    }
+
+    /// Write a byte to Z80 RAM.
+    pub fn write_u8(&mut self, addr: u16, v: u8) {
+        unsafe { mmio::write_u8(Self::address(addr, 1), v) };
+    }
+
+    /// Read consecutive bytes from Z80 RAM into `dst`.
+    pub fn read(&self, addr: u16, dst: &mut [u8]) {
+        let base = Self::address(addr, dst.len());
+        for (i, b) in dst.iter_mut().enumerate() {
+            *b = unsafe { mmio::read_u8(base + i as u32) };
+        }
+    }
+
+    /// Map the bank containing the 68k address `addr` into the Z80's window.
+    ///
+    /// Any driver running on the Z80 which keeps track of its current bank
+    /// will not notice this change.
+    pub fn set_bank(&mut self, addr: u32) {
+        let bank = BankAddress::new(addr).bank;
+        for i in 0..9 {
+            unsafe { mmio::write_u8(Z80_BANK_REG, (bank >> i) as u8 & 1) };
+        }
+    }
+
+    /// Write consecutive bytes from `src` to Z80 RAM.
+    pub fn write(&mut self, addr: u16, src: &[u8]) {
+        let base = Self::address(addr, src.len());
+        for (i, &b) in src.iter().enumerate() {
+            unsafe { mmio::write_u8(base + i as u32, b) };
+        }
+    }
+}
+
+impl<'a> Drop for BusGuard<'a> {
+    fn drop(&mut self) {
+        if !self.z80.bus_held {
+            request_bus(false);
+        }
+    }
+}
+
+/// A 68k address, as seen through the Z80's bank window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BankAddress {
+    /// The value for the bank register.
+    pub bank: u16,
+    /// The Z80 address within the window.
+    pub addr: u16,
+}
+
+impl BankAddress {
+    /// Find the bank & window address of a 68k address.
+    pub const fn new(addr: u32) -> BankAddress {
+        BankAddress {
+            bank: ((addr >> 15) & 0x1ff) as u16,
+            addr: BANK_WINDOW | (addr as u16 & 0x7fff),
+        }
+    }
+
+    /// Find the bank & window address of some data in ROM.
+    ///
+    /// This panics if the data is not in ROM, or would not fit in the window
+    /// at once.
+    pub fn of(data: &'static [u8]) -> BankAddress {
+        let start = data.as_ptr() as u32;
+        let len = data.len() as u32;
+        assert!(start + len <= ROM_END, "data is not in ROM");
+        if len > 0 {
+            assert!((start >> 15) == ((start + len - 1) >> 15), "data straddles Z80 banks");
+        }
+        BankAddress::new(start)
+    }
+}
+
+/// The mailbox is empty and the 68k may send a command.
+pub const MAILBOX_IDLE: u8 = 0;
+/// The 68k has sent a command which the Z80 has not yet answered.
+pub const MAILBOX_COMMAND: u8 = 1;
+/// The Z80 has answered the last command.
+pub const MAILBOX_RESPONSE: u8 = 2;
+
+/// A command & response channel in Z80 RAM.
+///
+/// The mailbox is laid out as a status byte, followed by the command `C`,
+/// followed by the response `R`. The status is one of `MAILBOX_IDLE`,
+/// `MAILBOX_COMMAND` or `MAILBOX_RESPONSE`: the 68k writes a command and
+/// sets the status to `MAILBOX_COMMAND`, then the Z80 writes a response and
+/// sets the status to `MAILBOX_RESPONSE`.
+///
+/// Multi-byte values are copied as they are laid out on the 68k, so they
+/// are big-endian to the Z80.
+pub struct Mailbox<C: Copy, R: Copy> {
+    addr: u16,
+    _marker: PhantomData<(C, R)>,
+}
+
+impl<C: Copy, R: Copy> Mailbox<C, R> {
+    /// Create a mailbox at `addr` in Z80 RAM.
+    ///
+    /// # Safety
+    /// `C` & `R` must not contain any padding and every bit pattern must be
+    /// a valid `R`.
+    pub const unsafe fn new(addr: u16) -> Mailbox<C, R> {
+        Mailbox { addr, _marker: PhantomData }
+    }
+
+    fn command_addr(&self) -> u16 { self.addr + 1 }
+    fn response_addr(&self) -> u16 { self.addr + 1 + size_of::<C>() as u16 }
+
+    /// Returns true if the Z80 is still processing a command.
+    pub fn is_busy(&self, z80: &mut Z80) -> bool {
+        z80.bus().read_u8(self.addr) == MAILBOX_COMMAND
+    }
+
+    /// Send a command to the Z80.
+    ///
+    /// Returns false if the previous command has not been answered yet. Any
+    /// unread response is discarded.
+    pub fn send(&self, z80: &mut Z80, command: &C) -> bool {
+        let mut bus = z80.bus();
+        if bus.read_u8(self.addr) == MAILBOX_COMMAND {
+            return false;
+        }
+
+        let bytes = unsafe {
+            core::slice::from_raw_parts(command as *const C as *const u8, size_of::<C>())
+        };
+        bus.write(self.command_addr(), bytes);
+        bus.write_u8(self.addr, MAILBOX_COMMAND);
+        true
+    }
+
+    /// Fetch the response to the last command, if the Z80 has answered it.
+    pub fn poll(&self, z80: &mut Z80) -> Option<R> {
+        let mut bus = z80.bus();
+        if bus.read_u8(self.addr) != MAILBOX_RESPONSE {
+            return None;
+        }
+
+        let mut response = MaybeUninit::<R>::zeroed();
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(response.as_mut_ptr() as *mut u8, size_of::<R>())
+        };
+        bus.read(self.response_addr(), bytes);
+        bus.write_u8(self.addr, MAILBOX_IDLE);
+        Some(unsafe { response.assume_init() })
+    }
+}
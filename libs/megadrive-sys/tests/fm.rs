@@ -0,0 +1,154 @@
+#![cfg(feature = "mock")]
+
+use std::sync::{Mutex, MutexGuard};
+
+use megadrive_sys::fm::{Ch3Mode, FmOperator, FmPatch, Note, Panning, TimerConfig, FM};
+use megadrive_sys::mmio::mock;
+
+const PORT_1: u32 = 0xa04000;
+const PORT_2: u32 = 0xa04002;
+
+/// The register shadow is shared by every thread, so tests which use it must
+/// not run at the same time.
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn fm() -> (FM, MutexGuard<'static, ()>) {
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let fm = FM::new();
+    mock::reset();
+    (fm, guard)
+}
+
+/// Pair up the address & data writes to each of the YM2612's ports.
+fn registers() -> Vec<(bool, u8, u8)> {
+    let writes = mock::writes();
+    writes.chunks(2)
+        .map(|w| {
+            assert!(w[0].0 == PORT_1 || w[0].0 == PORT_2, "unexpected write {:x?}", w[0]);
+            assert_eq!(w[1].0, w[0].0 + 1);
+            (w[0].0 == PORT_2, w[0].1 as u8, w[1].1 as u8)
+        })
+        .collect()
+}
+
+#[test]
+fn set_frequency() {
+    let (fm, _guard) = fm();
+    fm.channel(1).set_frequency(Note::A, 4);
+    fm.channel(5).set_frequency(0x7ffu16, 7);
+    assert_eq!(registers(), [
+        (false, 0xa5, 0x24), (false, 0xa1, 0x39),
+        (true, 0xa6, 0x3f), (true, 0xa2, 0xff),
+    ]);
+}
+
+#[test]
+fn set_key() {
+    let (fm, _guard) = fm();
+    fm.channel(2).set_key(true);
+    fm.channel(3).set_key(false);
+    assert_eq!(registers(), [(false, 0x28, 0xf2), (false, 0x28, 0x04)]);
+}
+
+#[test]
+fn waits_until_idle() {
+    let (fm, _guard) = fm();
+    mock::script_reads(PORT_1, vec![0x80, 0x80]);
+    fm.set_timer_b(0x40);
+    let reads = mock::take_log().into_iter()
+        .filter(|a| matches!(a, mock::Access::Read { .. }))
+        .count();
+    assert_eq!(reads, 3);
+}
+
+#[test]
+fn shadow() {
+    let (fm, _guard) = fm();
+    fm.channel(4).set_frequency(0x123u16, 5);
+    fm.channel(4).set_algorithm(3, 6);
+    fm.channel(4).operator(2).set_total_level(0x55);
+    assert_eq!(fm.channel(4).frequency(), (0x123, 5));
+    assert_eq!(fm.channel(4).algorithm(), 3);
+    assert_eq!(fm.channel(4).feedback(), 6);
+    assert_eq!(fm.channel(4).operator(2).total_level(), 0x55);
+    assert_eq!(fm.register(true, 0xa5), 0x29);
+    assert_eq!(fm.register(true, 0xa1), 0x23);
+    assert_eq!(fm.register(true, 0x49), 0x55);
+    assert_eq!(fm.register(false, 0x49), 0);
+}
+
+#[test]
+fn single_field_setters() {
+    let (fm, _guard) = fm();
+    let ch = fm.channel(0);
+    let op = ch.operator(1);
+    op.set_multiplier(5, 3);
+    op.set_attack_rate(20, 2);
+    op.set_decay_rate(10, true);
+    op.set_release_rate(7, 9);
+    ch.set_algorithm(4, 2);
+    ch.set_panning(Panning::Left, 1, 3);
+    mock::reset();
+
+    op.set_multiply(9);
+    op.set_attack(31);
+    op.set_decay(4);
+    op.set_release(15);
+    ch.set_connection(7);
+    ch.set_feedback(5);
+    ch.set_pan(Panning::Right);
+    ch.set_ams(3);
+    ch.set_fms(6);
+    assert_eq!(registers(), [
+        (false, 0x34, 0x39),
+        (false, 0x54, 0x9f),
+        (false, 0x64, 0x84),
+        (false, 0x84, 0x9f),
+        (false, 0xb0, 0x17),
+        (false, 0xb0, 0x2f),
+        (false, 0xb4, 0x53),
+        (false, 0xb4, 0x73),
+        (false, 0xb4, 0x76),
+    ]);
+
+    op.set_detune(1);
+    op.set_rate_scale(1);
+    op.set_amplitude_modulation(false);
+    op.set_sustain_level(2);
+    assert_eq!(op.settings(), FmOperator::new()
+        .multiplier(9, 1).attack_rate(31, 1).decay_rate(4, false).release_rate(15, 2));
+}
+
+#[test]
+fn save_restore() {
+    let (fm, _guard) = fm();
+    fm.channel(3).apply_patch(&FmPatch::BRASS);
+    fm.set_ch3_mode(Ch3Mode::Special);
+    fm.channel(2).operator(1).set_frequency_special(0x234u16, 3);
+    let state = fm.save();
+
+    let _ = FM::new();
+    assert_eq!(fm.ch3_mode(), Ch3Mode::Normal);
+    fm.set_timer_a(0x200);
+    fm.configure_timers(TimerConfig::new().enable_timer_a(true, true));
+    fm.restore(&state);
+
+    assert_eq!(fm.ch3_mode(), Ch3Mode::Special);
+    assert_eq!(fm.register(false, 0x27) & 0x0f, 0x05);
+    assert_eq!(fm.channel(2).operator(1).frequency_special(), (0x234, 3));
+    assert_eq!(fm.channel(3).patch().operators, FmPatch::BRASS.operators);
+    assert_eq!(fm.channel(3).algorithm(), FmPatch::BRASS.algorithm);
+}
+
+#[test]
+fn timer_config() {
+    let (fm, _guard) = fm();
+    let config = TimerConfig::new()
+        .enable_timer_a(true, true)
+        .reset_timer_a(true)
+        .reset_timer_b(true);
+    fm.configure_timers(config);
+    fm.configure_timers(config.reset_timer_a(false));
+    fm.configure_timers(config.reset_timer_b(false).ch3_special_mode(true));
+    assert_eq!(registers(), [(false, 0x27, 0x35), (false, 0x27, 0x25), (false, 0x27, 0x55)]);
+}
@@ -0,0 +1,81 @@
+use megadrive_sys::vdp::{LayoutError, ScrollSize, VdpConfig, VramLayout, VramTable};
+
+fn with_layout(layout: VramLayout) -> VdpConfig {
+    VdpConfig::new().layout(layout)
+}
+
+#[test]
+fn default_layout() {
+    let config = VdpConfig::new();
+    assert_eq!(config.validate(), Ok(()));
+    assert_eq!(config.free_tiles(), 0..0x600);
+
+    let config = config.resolution(false, true);
+    assert_eq!(config.validate(), Ok(()));
+}
+
+#[test]
+fn table_sizes() {
+    let config = VdpConfig::new().plane_size(ScrollSize::Cell64, ScrollSize::Cell32);
+    assert_eq!(config.table_size(VramTable::PlaneA), 0x1000);
+    assert_eq!(config.table_size(VramTable::Window), 0x1000);
+    assert_eq!(config.table_size(VramTable::Sprites), 640);
+    assert_eq!(config.table_size(VramTable::HScroll), 896);
+
+    let config = config.resolution(false, true);
+    assert_eq!(config.table_size(VramTable::Window), 0x800);
+    assert_eq!(config.table_size(VramTable::Sprites), 512);
+    assert_eq!(config.table_size(VramTable::HScroll), 960);
+}
+
+#[test]
+fn plane_size() {
+    let config = VdpConfig::new().plane_size(ScrollSize::Cell128, ScrollSize::Cell64);
+    assert_eq!(config.validate(), Err(LayoutError::InvalidPlaneSize));
+}
+
+#[test]
+fn plane_alignment() {
+    let config = with_layout(VramLayout { plane_a: 0xc800, ..VramLayout::default() });
+    assert_eq!(config.validate(), Err(LayoutError::Misaligned(VramTable::PlaneA)));
+}
+
+#[test]
+fn window_alignment() {
+    let layout = VramLayout { window: 0xa800, ..VramLayout::default() };
+
+    // The window must be aligned to 0x1000 in H40, but only 0x800 in H32.
+    assert_eq!(with_layout(layout).validate(), Err(LayoutError::Misaligned(VramTable::Window)));
+    assert_eq!(with_layout(layout).resolution(false, false).validate(), Ok(()));
+}
+
+#[test]
+fn sprite_alignment() {
+    let layout = VramLayout { sprites: 0xf200, ..VramLayout::default() };
+
+    // The sprite table must be aligned to 0x400 in H40, but only 0x200 in H32.
+    assert_eq!(with_layout(layout).validate(), Err(LayoutError::Misaligned(VramTable::Sprites)));
+    assert_eq!(with_layout(layout).resolution(false, false).validate(), Ok(()));
+}
+
+#[test]
+fn overlap() {
+    let layout = VramLayout { window: 0xc000, ..VramLayout::default() };
+    assert_eq!(with_layout(layout).validate(), Err(LayoutError::Overlap(VramTable::PlaneA, VramTable::Window)));
+
+    let layout = VramLayout { h_scroll: 0xf000, ..VramLayout::default() };
+    assert_eq!(with_layout(layout).validate(), Err(LayoutError::Overlap(VramTable::Sprites, VramTable::HScroll)));
+}
+
+#[test]
+fn large_planes() {
+    // 64x64 planes are 8KiB each, so plane B runs into the default sprite table.
+    let layout = VramLayout { plane_a: 0xa000, window: 0xc000, ..VramLayout::default() };
+    let config = with_layout(layout).plane_size(ScrollSize::Cell64, ScrollSize::Cell64);
+    assert_eq!(config.validate(), Err(LayoutError::Overlap(VramTable::PlaneB, VramTable::Sprites)));
+
+    let layout = VramLayout { plane_b: 0x8000, ..layout };
+    let config = with_layout(layout).plane_size(ScrollSize::Cell64, ScrollSize::Cell64);
+    assert_eq!(config.validate(), Ok(()));
+    assert_eq!(config.free_tiles(), 0..0x400);
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "mock")]
+
+use megadrive_sys::pitch::Clock;
+
+const A4: u8 = 69;
+
+#[test]
+fn fm_a4() {
+    assert_eq!(Clock::Ntsc.fm(A4, 0), (4, 1083));
+    assert_eq!(Clock::Pal.fm(A4, 0), (4, 1093));
+}
+
+#[test]
+fn fm_octaves() {
+    let (_, f_number) = Clock::Ntsc.fm(A4, 0);
+    assert_eq!(Clock::Ntsc.fm(A4 + 12, 0), (5, f_number));
+    assert_eq!(Clock::Ntsc.fm(A4 - 12, 0), (3, f_number));
+    assert_eq!(Clock::Ntsc.fm(A4, 1200), (5, f_number));
+    assert_eq!(Clock::Ntsc.fm(A4, -100), Clock::Ntsc.fm(A4 - 1, 0));
+}
+
+#[test]
+fn fm_clamps() {
+    assert_eq!(Clock::Ntsc.fm(0, -1000), (0, Clock::Ntsc.fm(0, 0).1));
+    assert_eq!(Clock::Ntsc.fm(127, 0).0, 7);
+    assert_eq!(Clock::Ntsc.fm(255, i16::MAX), (7, 0x7ff));
+}
+
+#[test]
+fn psg_a4() {
+    assert_eq!(Clock::Ntsc.psg(A4, 0), Some(254));
+    assert_eq!(Clock::Pal.psg(A4, 0), Some(252));
+}
+
+#[test]
+fn psg_range() {
+    // A2 (110Hz) is the lowest A the PSG can play.
+    assert_eq!(Clock::Ntsc.psg(45, 0), Some(1017));
+    assert_eq!(Clock::Ntsc.psg(44, 0), None);
+    assert_eq!(Clock::Pal.psg(45, 0), Some(1008));
+    assert_eq!(Clock::Ntsc.psg(127, 0), Some(9));
+    assert_eq!(Clock::Ntsc.psg(255, i16::MAX), None);
+    assert_eq!(Clock::Ntsc.psg(0, i16::MIN), None);
+}
@@ -0,0 +1,41 @@
+#![cfg(feature = "mock")]
+
+use megadrive_sys::mmio::mock;
+use megadrive_sys::psg::{NoiseFrequency, Note, PSG};
+
+const PSG_PORT: u32 = 0xc00011;
+
+fn psg() -> PSG {
+    let psg = PSG::new();
+    mock::reset();
+    psg
+}
+
+#[test]
+fn new_silences_every_channel() {
+    mock::reset();
+    let _psg = PSG::new();
+    assert_eq!(mock::writes_to(PSG_PORT), [0x9f, 0xbf, 0xdf, 0xff]);
+}
+
+#[test]
+fn set_volume() {
+    let psg = psg();
+    psg.set_volume(1, 15);
+    psg.set_volume(2, 8);
+    assert_eq!(mock::writes_to(PSG_PORT), [0xb0, 0xd7]);
+}
+
+#[test]
+fn set_pitch() {
+    let psg = psg();
+    psg.set_pitch(2, Note::A3);
+    assert_eq!(mock::writes_to(PSG_PORT), [0xc0 | (506 & 0xf), 506 >> 4]);
+}
+
+#[test]
+fn set_noise() {
+    let psg = psg();
+    psg.set_noise(true, NoiseFrequency::Channel2);
+    assert_eq!(mock::writes_to(PSG_PORT), [0xe7]);
+}
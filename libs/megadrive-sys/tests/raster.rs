@@ -0,0 +1,76 @@
+#![cfg(feature = "mock")]
+
+use megadrive_sys::mmio::mock;
+use megadrive_sys::raster::{RasterAction, RasterEffects, RasterTable};
+use megadrive_sys::vdp::{Color, VDP};
+
+const DATA: u32 = 0xc00000;
+const CONTROL: u32 = 0xc00004;
+const HV_COUNTER: u32 = 0xc00008;
+
+fn effects() -> RasterEffects {
+    let vdp = VDP::new();
+    let effects = RasterEffects::new(&vdp);
+    mock::reset();
+    effects
+}
+
+fn table() -> RasterTable {
+    let mut table = RasterTable::new();
+    table.push(10, RasterAction::SetColor(0, 1, Color::new(7, 0, 0)));
+    table.push(0, RasterAction::SetRegister(0x87, 0x25));
+    table
+}
+
+#[test]
+fn table_order() {
+    let mut table = table();
+    table.push(10, RasterAction::SetRegister(0x81, 2));
+    let lines: Vec<_> = table.iter().map(|&(line, _)| line).collect();
+    assert_eq!(lines, [0, 10, 10]);
+    assert!(matches!(table.iter().last(), Some((_, RasterAction::SetRegister(0x81, 2)))));
+}
+
+#[test]
+fn submit() {
+    let mut effects = effects();
+    effects.submit(&table());
+    assert!(effects.current().is_empty());
+
+    // Line 0 actions run at the vertical blank.
+    effects.on_vblank();
+    assert_eq!(effects.current().len(), 2);
+    assert_eq!(mock::writes_to(CONTROL), [0x8725]);
+
+    // Later actions run on the line before they take effect.
+    mock::reset();
+    mock::set_read_value(HV_COUNTER, 0x0800);
+    effects.on_hblank();
+    assert!(mock::writes().is_empty());
+
+    mock::set_read_value(HV_COUNTER, 0x0900);
+    effects.on_hblank();
+    assert_eq!(mock::writes(), [(CONTROL, 0xc002), (CONTROL, 0x0000), (DATA, 0x000e)]);
+}
+
+#[test]
+fn repeat_and_replace() {
+    let mut effects = effects();
+    effects.submit(&table());
+    effects.on_vblank();
+
+    // Without a new submission, the same table runs again.
+    mock::reset();
+    effects.on_vblank();
+    assert_eq!(mock::writes_to(CONTROL), [0x8725]);
+
+    // Only the last submission before the vertical blank is used.
+    let mut other = RasterTable::new();
+    other.push(0, RasterAction::SetRegister(0x87, 0x01));
+    effects.submit(&table());
+    effects.submit(&other);
+    mock::reset();
+    effects.on_vblank();
+    assert_eq!(effects.current().len(), 1);
+    assert_eq!(mock::writes_to(CONTROL), [0x8701]);
+}
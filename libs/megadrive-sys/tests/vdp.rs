@@ -0,0 +1,288 @@
+#![cfg(feature = "mock")]
+
+use megadrive_sys::mmio::mock;
+use megadrive_sys::vdp::{AddrKind, DmaQueue, HvCounter, InterlaceMode, Plane, TileFlags, MAX_DMA_TRANSFERS, VDP};
+
+const DATA: u32 = 0xc00000;
+const CONTROL: u32 = 0xc00004;
+const VERSION: u32 = 0xa10001;
+
+fn vdp() -> VDP {
+    let vdp = VDP::new();
+    mock::reset();
+    vdp
+}
+
+#[test]
+fn set_address() {
+    let mut vdp = vdp();
+    vdp.set_address(AddrKind::VRAM, 0xc000);
+    vdp.set_address(AddrKind::CRAM, 0x0020);
+    vdp.set_address(AddrKind::VSRAM, 0x0002);
+    assert_eq!(mock::writes_to(CONTROL), [0x4000, 0x0003, 0xc020, 0x0000, 0x4002, 0x0010]);
+}
+
+#[test]
+fn set_read_address() {
+    let mut vdp = vdp();
+    vdp.set_read_address(AddrKind::VRAM, 0x1234);
+    vdp.set_read_address(AddrKind::CRAM, 0x0000);
+    assert_eq!(mock::writes_to(CONTROL), [0x1234, 0x0000, 0x0000, 0x0020]);
+}
+
+#[test]
+fn set_register() {
+    let mut vdp = vdp();
+    vdp.set_background(2, 5);
+    assert_eq!(mock::writes_to(CONTROL), [0x8725]);
+}
+
+#[test]
+fn tile_flags() {
+    let flags = TileFlags::for_tile(0x123, 2).set_priority(true).set_flip_h(true);
+    assert_eq!(flags.tile_index(), 0x123);
+    assert_eq!(flags.palette(), 2);
+    assert!(flags.priority());
+    assert!(flags.flip_h());
+    assert!(!flags.flip_v());
+
+    let mut vdp = vdp();
+    vdp.set_cell(Plane::A, 0, 0, flags);
+    assert_eq!(mock::writes_to(DATA), [0xc123 | 0x0800]);
+
+    let flags = flags.set_tile_index(0xfff).set_palette(1).set_priority(false);
+    assert_eq!(flags.tile_index(), 0x7ff);
+    assert_eq!(flags.palette(), 1);
+    assert!(!flags.priority());
+    assert!(flags.flip_h());
+}
+
+#[test]
+fn set_cell_address() {
+    let mut vdp = vdp();
+    let base = vdp.vram_layout().plane_a;
+    let (w, _) = vdp.plane_size(Plane::A);
+    vdp.set_cell(Plane::A, 3, 2, TileFlags::for_tile(1, 0));
+    let addr = (base + (2 * w + 3) * 2) as u32;
+    assert_eq!(mock::writes_to(CONTROL), [0x4000 | (addr & 0x3fff), addr >> 14]);
+}
+
+/// A DMA transfer as seen on the control port: source word address, length
+/// in words, the two address words of the destination.
+type Dma = (u32, u32, u32, u32);
+
+/// Decode the DMA transfers from the recorded control port writes.
+fn dma_transfers() -> Vec<Dma> {
+    let mut regs = [0u32; 5];
+    let mut transfers = Vec::new();
+    let mut writes = mock::writes_to(CONTROL).into_iter();
+
+    while let Some(v) = writes.next() {
+        match v >> 8 {
+            0x93..=0x97 => regs[(v >> 8) as usize - 0x93] = v & 0xff,
+            0x80..=0x9f => {},
+            _ => {
+                let hi = writes.next().unwrap();
+                if (hi & 0x80) != 0 {
+                    let length = regs[0] | (regs[1] << 8);
+                    let src = regs[2] | (regs[3] << 8) | ((regs[4] & 0x7f) << 16);
+                    transfers.push((src, length, v, hi));
+                }
+            },
+        }
+    }
+
+    transfers
+}
+
+/// Queue a transfer from a fake source address, which the mock never reads.
+fn push(queue: &mut DmaQueue, kind: AddrKind, dst_addr: u16, src_addr: u32, length: u16) -> bool {
+    unsafe { queue.push_raw(kind, dst_addr, src_addr as usize as *const (), length) }
+}
+
+#[test]
+fn dma_queue_merge() {
+    let mut vdp = vdp();
+    let mut queue = DmaQueue::new();
+    assert!(push(&mut queue, AddrKind::VRAM, 0x0000, 0x1000, 4));
+    assert!(push(&mut queue, AddrKind::VRAM, 0x0008, 0x1008, 4));
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue.pending_bytes(), 16);
+
+    assert_eq!(queue.flush(&mut vdp), 16);
+    assert!(queue.is_empty());
+    assert_eq!(dma_transfers(), [(0x800, 8, 0x4000, 0x0080)]);
+}
+
+#[test]
+fn dma_queue_no_merge() {
+    let mut queue = DmaQueue::new();
+    push(&mut queue, AddrKind::VRAM, 0x0000, 0x1000, 4);
+    // Different memory.
+    push(&mut queue, AddrKind::CRAM, 0x0008, 0x1008, 4);
+    // Source not contiguous.
+    push(&mut queue, AddrKind::CRAM, 0x0010, 0x1012, 4);
+    // Destination not contiguous.
+    push(&mut queue, AddrKind::CRAM, 0x001a, 0x101a, 4);
+    assert_eq!(queue.len(), 4);
+    assert_eq!(queue.pending_bytes(), 32);
+
+    let mut vdp = vdp();
+    queue.flush(&mut vdp);
+    assert_eq!(dma_transfers(), [
+        (0x800, 4, 0x4000, 0x0080),
+        (0x804, 4, 0xc008, 0x0080),
+        (0x809, 4, 0xc010, 0x0080),
+        (0x80d, 4, 0xc01a, 0x0080),
+    ]);
+}
+
+#[test]
+fn dma_queue_budget() {
+    let mut vdp = vdp();
+    let mut queue = DmaQueue::with_budget(16);
+    push(&mut queue, AddrKind::VRAM, 0x0100, 0x2000, 20);
+    push(&mut queue, AddrKind::CRAM, 0x0000, 0x3000, 2);
+
+    assert_eq!(queue.flush(&mut vdp), 16);
+    assert_eq!(queue.pending_bytes(), 28);
+    assert_eq!(dma_transfers(), [(0x1000, 8, 0x4100, 0x0080)]);
+
+    // The remainder carries on from where the last flush stopped.
+    assert_eq!(queue.flush(&mut vdp), 16);
+    assert_eq!(dma_transfers(), [(0x1008, 8, 0x4110, 0x0080)]);
+
+    assert_eq!(queue.flush(&mut vdp), 12);
+    assert_eq!(dma_transfers(), [(0x1010, 4, 0x4120, 0x0080), (0x1800, 2, 0xc000, 0x0080)]);
+    assert!(queue.is_empty());
+    assert_eq!(queue.flush(&mut vdp), 0);
+}
+
+#[test]
+fn dma_queue_full() {
+    let mut queue = DmaQueue::new();
+    for i in 0..MAX_DMA_TRANSFERS as u32 {
+        assert!(push(&mut queue, AddrKind::VRAM, (i * 0x100) as u16, 0x1000 + i * 0x100, 2));
+    }
+    assert!(!push(&mut queue, AddrKind::VRAM, 0x8000, 0x8000, 2));
+    assert_eq!(queue.len(), MAX_DMA_TRANSFERS);
+
+    // Transfers which merge with the last one still fit.
+    let last = (MAX_DMA_TRANSFERS as u32 - 1) * 0x100 + 4;
+    assert!(push(&mut queue, AddrKind::VRAM, last as u16, 0x1000 + last, 2));
+    assert_eq!(queue.len(), MAX_DMA_TRANSFERS);
+}
+
+#[test]
+fn dma_128k_boundary() {
+    let mut vdp = vdp();
+    let mut queue = DmaQueue::new();
+    push(&mut queue, AddrKind::VRAM, 0x0000, 0x1fff0, 8);
+    push(&mut queue, AddrKind::VRAM, 0x0010, 0x20000, 8);
+    assert_eq!(queue.len(), 1);
+
+    // The VDP wraps the source address at each 128KiB boundary, so the
+    // merged transfer has to be split there.
+    queue.flush(&mut vdp);
+    assert_eq!(dma_transfers(), [(0xfff8, 8, 0x4000, 0x0080), (0x10000, 8, 0x4010, 0x0080)]);
+}
+
+fn hv_counter(pal: bool, h40: bool, v30: bool, interlace: InterlaceMode) -> HvCounter {
+    mock::reset();
+    mock::set_read_value(VERSION, if pal { 0xe0 } else { 0xa0 });
+    let mut vdp = VDP::new();
+    vdp.set_resolution(h40, v30);
+    vdp.set_interlace(interlace);
+    vdp.hv_counter()
+}
+
+fn line(hv: &HvCounter, v: u8, in_vblank: bool) -> i16 {
+    hv.decode((v as u16) << 8, in_vblank).line
+}
+
+#[test]
+fn hv_counter_h32() {
+    let hv = hv_counter(false, false, false, InterlaceMode::None);
+    assert_eq!(hv.decode(0x0000, false).x, 0);
+    assert_eq!(hv.decode(0x0093, false).x, 0x126);
+    // The counter jumps from 0x93 to 0xe9.
+    assert_eq!(hv.decode(0x00e9, false).x, -46);
+    assert_eq!(hv.decode(0x00ff, false).x, -2);
+}
+
+#[test]
+fn hv_counter_h40() {
+    let hv = hv_counter(false, true, false, InterlaceMode::None);
+    assert_eq!(hv.decode(0x0093, false).x, 0x126);
+    assert_eq!(hv.decode(0x00b6, false).x, 0x16c);
+    // The counter jumps from 0xb6 to 0xe4.
+    assert_eq!(hv.decode(0x00e4, false).x, -56);
+    assert_eq!(hv.decode(0x00ff, false).x, -2);
+}
+
+#[test]
+fn hv_counter_ntsc_v28() {
+    let hv = hv_counter(false, false, false, InterlaceMode::None);
+    assert_eq!(line(&hv, 0x00, false), 0);
+    assert_eq!(line(&hv, 0xdf, false), 223);
+    assert_eq!(line(&hv, 0xe0, true), 224);
+    // The counter jumps from 0xea to 0xe5, the repeated values are reported
+    // as the earlier lines.
+    assert_eq!(line(&hv, 0xea, true), 234);
+    assert_eq!(line(&hv, 0xe5, true), 229);
+    assert_eq!(line(&hv, 0xeb, true), -21);
+    assert_eq!(line(&hv, 0xff, true), -1);
+}
+
+#[test]
+fn hv_counter_pal_v28() {
+    let hv = hv_counter(true, false, false, InterlaceMode::None);
+    assert_eq!(line(&hv, 0x01, false), 1);
+    assert_eq!(line(&hv, 0xca, false), 202);
+    assert_eq!(line(&hv, 0xff, true), 255);
+    // The counter wraps past 0xff to 0x02, then jumps to 0xca.
+    assert_eq!(line(&hv, 0x00, true), 256);
+    assert_eq!(line(&hv, 0x02, true), 258);
+    assert_eq!(line(&hv, 0xca, true), -54);
+    assert_eq!(line(&hv, 0xdf, true), -33);
+    assert_eq!(line(&hv, 0xe0, true), 224);
+}
+
+#[test]
+fn hv_counter_pal_v30() {
+    let hv = hv_counter(true, false, true, InterlaceMode::None);
+    assert_eq!(line(&hv, 0x0a, false), 10);
+    assert_eq!(line(&hv, 0xef, false), 239);
+    // The counter wraps past 0xff to 0x0a, then jumps to 0xd2.
+    assert_eq!(line(&hv, 0x0a, true), 266);
+    assert_eq!(line(&hv, 0x0b, true), 11);
+    assert_eq!(line(&hv, 0xd2, true), -46);
+    assert_eq!(line(&hv, 0xef, true), -17);
+    assert_eq!(line(&hv, 0xf0, true), 240);
+}
+
+#[test]
+fn hv_counter_ntsc_v30() {
+    let hv = hv_counter(false, false, true, InterlaceMode::None);
+    assert_eq!(line(&hv, 0x05, false), 5);
+    assert_eq!(line(&hv, 0xff, true), 255);
+    assert_eq!(line(&hv, 0x05, true), 261);
+    assert_eq!(line(&hv, 0x06, true), 6);
+}
+
+#[test]
+fn hv_counter_interlace() {
+    let hv = hv_counter(false, false, false, InterlaceMode::Interlace);
+    assert_eq!(line(&hv, 0x81, false), 0x81);
+
+    // In double resolution mode, bit 0 holds the top bit of the line.
+    let hv = hv_counter(false, false, false, InterlaceMode::DoubleRes);
+    assert_eq!(line(&hv, 0x02, false), 1);
+    assert_eq!(line(&hv, 0x01, false), 128);
+    assert_eq!(line(&hv, 0xbe, false), 95);
+    assert_eq!(line(&hv, 0xbf, false), 223);
+    assert_eq!(line(&hv, 0xd5, true), 234);
+    assert_eq!(line(&hv, 0xd7, true), -21);
+    assert_eq!(line(&hv, 0xfe, true), 127);
+    assert_eq!(line(&hv, 0xff, true), -1);
+}
@@ -0,0 +1,468 @@
+; Two channel PCM sample driver.
+;
+; Samples are 8-bit unsigned PCM, streamed from 68k ROM through the bank
+; window into a 256 byte ring buffer per channel. The YM2612's timer A paces
+; the output: every time it overflows, one sample from each ring is mixed and
+; written to the DAC.
+;
+; The 68k controls each channel through a mailbox, see `pcm.rs`.
+;
+; Register usage:
+;   bc' - channel 0 8.8 read position in its ring
+;   de' - channel 1 8.8 read position in its ring
+;   af', hl' - scratch for `tick`
+; The rest of the driver must only touch the shadow registers through the
+; helpers below.
+
+YM_STATUS       equ 4000h
+YM_ADDR0        equ 4000h
+YM_DATA0        equ 4001h
+YM_ADDR1        equ 4002h
+YM_DATA1        equ 4003h
+BANK_REG        equ 6000h
+
+; Output rate is 53267 / (1024 - TIMER_A) Hz, ~7.6kHz.
+TIMER_A         equ 1017
+
+; Bytes copied into a ring buffer at once, and between checks of the timer.
+CHUNK           equ 64
+PIECE           equ 4
+SILENCE         equ 80h
+
+MAILBOX_IDLE    equ 0
+MAILBOX_COMMAND equ 1
+MAILBOX_RESPONSE equ 2
+
+STATE_STOPPED   equ 0
+STATE_PLAYING   equ 1
+STATE_DRAINING  equ 2
+
+; Channel layout. The parameters mirror the mailbox command, so that they
+; can be copied in one go.
+CH_STEP         equ 0           ; 8.8 ring positions per output sample
+CH_BANK         equ 2           ; bank of the next source byte
+CH_ADDR         equ 4           ; window address of the next source byte
+CH_LEFT         equ 6           ; bytes left to copy, 24 bits
+CH_LOOP         equ 9           ; nonzero to loop
+CH_LOOP_BANK    equ 10
+CH_LOOP_ADDR    equ 12
+CH_LOOP_LEFT    equ 14
+PARAMS_SIZE     equ 17
+CH_STATE        equ 17
+CH_WRITE        equ 18          ; ring write index
+CH_PAD          equ 19          ; silence written since the data ran out
+CH_RING         equ 20          ; high byte of the ring address
+CH_MAILBOX      equ 21          ; status, op, params, response
+CH_RESPONSE     equ CH_MAILBOX + PARAMS_SIZE + 2
+CH_SIZE         equ CH_RESPONSE + 1
+
+RING0           equ 1000h
+RING1           equ 1100h
+STACK_TOP       equ 2000h
+
+        org 0
+        di
+        ld sp,STACK_TOP
+        jp init
+
+; Mix one sample from each channel and write it to the DAC.
+;
+; This must be called soon after timer A overflows, which every long running
+; piece of code checks for with `ld a,(YM_STATUS) / rrca / call c,tick`. It
+; only clobbers af & the shadow registers.
+tick:
+        exx
+        ld hl,YM_ADDR0
+        ld (hl),27h
+        inc l
+        ld (hl),15h             ; reload & reset timer A
+
+        ld hl,(ch0 + CH_STEP)
+        ld a,b
+        add hl,bc
+        ld b,h
+        ld c,l
+        ld h,RING0 >> 8
+        ld l,a
+        ld a,(hl)
+        ex af,af'
+        ld hl,(ch1 + CH_STEP)
+        ld a,d
+        add hl,de
+        ex de,hl
+        ld h,RING1 >> 8
+        ld l,a
+        ld l,(hl)
+        ex af,af'
+
+        ; Both samples are biased by 80h, so remove one bias and clamp.
+        add a,l
+        jr c,.high
+        sub SILENCE
+        jr nc,.write
+        xor a
+        jr .write
+.high:  add a,SILENCE
+        jr nc,.write
+        ld a,0ffh
+
+.write: ld hl,YM_ADDR0
+        ld (hl),2ah
+        inc l
+        ld (hl),a
+        exx
+        ret
+
+main:
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+        ld ix,ch0
+        call service
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+        ld ix,ch1
+        call service
+        jr main
+
+; Handle any command for the channel in ix, then top up its ring.
+service:
+        ld a,(ix + CH_MAILBOX)
+        cp MAILBOX_COMMAND
+        jp nz,refill
+
+        ; Stop the channel whilst it is changed.
+        call stop_channel
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+        ld a,(ix + CH_MAILBOX + 1)
+        or a
+        jr z,.done
+
+        ; Copy everything but the step, which is set once the ring has data.
+        push ix
+        pop de
+        inc de
+        inc de
+        ld hl,CH_MAILBOX + 2
+        add hl,de
+        ld bc,PARAMS_SIZE - 2
+        ldir
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+
+        ld (ix + CH_STATE),STATE_PLAYING
+        ld (ix + CH_WRITE),0
+        ld (ix + CH_PAD),0
+        call reset_position
+        call refill
+        ld a,(ix + CH_MAILBOX + 2)
+        ld (ix + CH_STEP),a
+        ld a,(ix + CH_MAILBOX + 3)
+        ld (ix + CH_STEP + 1),a
+
+.done:  ld (ix + CH_RESPONSE),0
+        ld (ix + CH_MAILBOX),MAILBOX_RESPONSE
+        ret
+
+; Stop the channel in ix, leaving silence in the ring.
+stop_channel:
+        ld (ix + CH_STATE),STATE_STOPPED
+        ld (ix + CH_STEP),0
+        ld (ix + CH_STEP + 1),0
+        call read_position
+        ld h,(ix + CH_RING)
+        ld l,a
+        ld (hl),SILENCE
+        ret
+
+; Fetch the ring read index of the channel in ix into a.
+read_position:
+        ld a,(ix + CH_RING)
+        rrca
+        exx
+        ld a,b
+        jr nc,.done
+        ld a,d
+.done:  exx
+        ret
+
+; Move the channel in ix back to the start of its ring, and silence it.
+reset_position:
+        ld h,(ix + CH_RING)
+        ld l,0
+        ld (hl),SILENCE
+        ld a,h
+        rrca
+        exx
+        jr c,.ch1
+        ld bc,0
+        exx
+        ret
+.ch1:   ld de,0
+        exx
+        ret
+
+; Copy the next chunk into the ring for the channel in ix, if there is room.
+refill:
+        ld a,(ix + CH_STATE)
+        or a
+        ret z
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+        call read_position
+        sub (ix + CH_WRITE)
+        dec a
+        cp CHUNK
+        ret c
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+
+        ; Never wrap around the end of the ring in one copy.
+        ld a,(ix + CH_WRITE)
+        neg
+        jr z,.full
+        cp CHUNK
+        jr c,.limit
+.full:  ld a,CHUNK
+.limit: ld c,a
+        ld b,0
+        ld d,(ix + CH_RING)
+        ld e,(ix + CH_WRITE)
+        ld a,(ix + CH_STATE)
+        cp STATE_DRAINING
+        jp z,.pad
+
+        ; Don't copy past the end of the sample...
+        ld a,(ix + CH_LEFT + 2)
+        or (ix + CH_LEFT + 1)
+        jr nz,.window
+        ld a,(ix + CH_LEFT)
+        cp c
+        jr nc,.window
+        ld c,a
+
+        ; ...or the end of the bank window.
+.window:
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+        ld l,(ix + CH_ADDR)
+        ld h,(ix + CH_ADDR + 1)
+        ld a,h
+        inc a
+        jr nz,.map
+        ld a,l
+        neg
+        jr z,.map
+        cp c
+        jr nc,.map
+        ld c,a
+
+.map:   ld a,(YM_STATUS)
+        rrca
+        call c,tick
+        push hl
+        push de
+        ld e,(ix + CH_BANK)
+        ld d,(ix + CH_BANK + 1)
+        call map_bank
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+
+        ld a,(ix + CH_WRITE)
+        add a,c
+        ld (ix + CH_WRITE),a
+        ld a,(ix + CH_LEFT)
+        sub c
+        ld (ix + CH_LEFT),a
+        ld a,(ix + CH_LEFT + 1)
+        sbc a,0
+        ld (ix + CH_LEFT + 1),a
+        ld a,(ix + CH_LEFT + 2)
+        sbc a,0
+        ld (ix + CH_LEFT + 2),a
+        pop de
+        pop hl
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+
+        ; Copy in pieces, so that `tick` keeps up.
+.piece: ld a,c
+        cp PIECE + 1
+        jr c,.tail
+        ldi
+        ldi
+        ldi
+        ldi
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+        jr .piece
+.tail:  call copy
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+
+        ; Step into the next bank when the end of the window is reached.
+        ld a,h
+        or l
+        jr nz,.store
+        ld h,80h
+        inc (ix + CH_BANK)
+        jr nz,.store
+        inc (ix + CH_BANK + 1)
+.store: ld (ix + CH_ADDR),l
+        ld (ix + CH_ADDR + 1),h
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+
+        ld a,(ix + CH_LEFT)
+        or (ix + CH_LEFT + 1)
+        or (ix + CH_LEFT + 2)
+        ret nz
+        ld a,(ix + CH_LOOP)
+        or a
+        jr z,.drain
+
+        ; Restart from the loop point.
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+        push ix
+        pop de
+        inc de
+        inc de
+        ld hl,CH_LOOP_BANK - CH_BANK
+        add hl,de
+        ld bc,CH_LOOP - CH_BANK
+        ldir
+        ret
+
+.drain: ld (ix + CH_STATE),STATE_DRAINING
+        ret
+
+        ; Once the data has run out, fill the ring with silence. The channel
+        ; stops once a whole ring of it has been written, as by then every
+        ; sample has been played.
+.pad:   ld a,c
+        cp PIECE
+        jr c,.silence
+        ld c,PIECE
+.silence:
+        ld a,(ix + CH_WRITE)
+        add a,c
+        ld (ix + CH_WRITE),a
+        ld a,(ix + CH_PAD)
+        add a,c
+        ld (ix + CH_PAD),a
+        push af
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+        ld hl,silence
+        call copy
+        ld a,(YM_STATUS)
+        rrca
+        call c,tick
+        pop af
+        ret nc
+        jp stop_channel
+
+; Copy c (0 to PIECE) bytes from hl to de.
+copy:
+        push hl
+        ld hl,.end
+        or a
+        sbc hl,bc
+        sbc hl,bc
+        ex (sp),hl
+        ret
+        ldi
+        ldi
+        ldi
+        ldi
+.end:   ret
+
+; Map the 68k bank in de into the window at 8000h.
+map_bank:
+        ld hl,(current_bank)
+        or a
+        sbc hl,de
+        ret z
+        ld (current_bank),de
+        ld hl,BANK_REG
+        ld a,e
+        ld (hl),a
+        rrca
+        ld (hl),a
+        rrca
+        ld (hl),a
+        rrca
+        ld (hl),a
+        rrca
+        ld (hl),a
+        rrca
+        ld (hl),a
+        rrca
+        ld (hl),a
+        rrca
+        ld (hl),a
+        ld (hl),d
+        ret
+
+init:
+        ld a,SILENCE
+        ld (RING0),a
+        ld (RING1),a
+
+        ; Enable the DAC on both speakers.
+        ld hl,YM_ADDR0
+        ld (hl),2bh
+        inc l
+        ld (hl),80h
+        ld hl,YM_ADDR1
+        ld (hl),0b6h
+        inc l
+        ld (hl),0c0h
+
+        ; Start timer A.
+        ld hl,YM_ADDR0
+        ld (hl),24h
+        inc l
+        ld (hl),TIMER_A >> 2
+        dec l
+        ld (hl),25h
+        inc l
+        ld (hl),TIMER_A & 3
+        dec l
+        ld (hl),27h
+        inc l
+        ld (hl),15h
+
+        exx
+        ld bc,0
+        ld de,0
+        exx
+        jp main
+
+current_bank:
+        dw 0ffffh
+
+silence:
+        ds PIECE,SILENCE
+
+ch0:    ds CH_RING
+        db RING0 >> 8
+        ds CH_SIZE - CH_RING - 1
+ch1:    ds CH_RING
+        db RING1 >> 8
+        ds CH_SIZE - CH_RING - 1
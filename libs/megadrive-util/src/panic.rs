@@ -1,6 +1,7 @@
 use core::panic::PanicInfo;
-use core::ptr::read_volatile;
+use core::ptr::{read_volatile, write_volatile};
 
+use megadrive_sys::interrupt::{self, Interrupt};
 use megadrive_sys::vdp::VDP;
 use megadrive_graphics::Renderer;
 use megadrive_graphics::default_ascii::DEFAULT_FONT_1X1;
@@ -15,6 +16,8 @@ fn panic(_info: &PanicInfo) -> ! {
     let mut renderer = Renderer::new();
     let mut vdp = VDP::new();
 
+    // Take over vblank, since the application's handler may be what panicked.
+    interrupt::set_handler(Interrupt::VBlank, vblank);
     vdp.enable_interrupts(false, true, false);
     vdp.enable_display(true);
 
@@ -55,3 +58,7 @@ fn wait_for_vblank() {
         NEW_FRAME = 0;
     }
 }
+
+extern "C" fn vblank() {
+    unsafe { write_volatile(&mut NEW_FRAME, 1) };
+}
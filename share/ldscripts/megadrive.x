@@ -4,6 +4,11 @@ MEMORY
     RAM (rwx) : ORIGIN = 0xFF0000, LENGTH = 0x10000
 }
 
+ENTRY(_start)
+
+/* Provided by megadrive-rt's entry! macro, when it is used. */
+EXTERN(_rom_header)
+
 SECTIONS
 {
     _stack_top = 0x1000000;
@@ -11,6 +16,9 @@ SECTIONS
 
     .text :
     {
+        /* When megadrive-rt is used, the vector table and header come first. */
+        KEEP(*(.vectors));
+        KEEP(*(.header));
         *(.text .text.*);
     } > ROM
 
@@ -24,7 +32,7 @@ SECTIONS
     {
         ALIGN(4);
         _data_start = .;
-        *(.data);
+        *(.data .data.*);
         ALIGN(4);
         _data_end = .;
     } > RAM AT > ROM
@@ -33,11 +41,29 @@ SECTIONS
     {
         ALIGN(4);
         _bss_start = .;
-        *(.bss);
+        *(.bss .bss.*);
         ALIGN(4);
         _bss_end = .;
     } > RAM AT > ROM
 
     _heap_start = .;
     _heap_end = _stack_bottom;
+
+    /*
+     * Cartridge SRAM. cargo-megadrive defines these symbols when SRAM is
+     * declared in the package metadata. Statics placed in .sram only reserve
+     * space: SRAM must be accessed through megadrive_sys::sram. _sram_size
+     * is the number of usable bytes, which is half of the address range for
+     * SRAM on only the odd or even bytes.
+     */
+    PROVIDE(_sram_start = 0x200001);
+    PROVIDE(_sram_end = 0x200000);
+    PROVIDE(_sram_size = 0);
+
+    .sram _sram_start (NOLOAD) :
+    {
+        *(.sram .sram.*);
+    }
+
+    ASSERT(SIZEOF(.sram) <= _sram_size, "SRAM data does not fit in the declared SRAM")
 }
@@ -0,0 +1,39 @@
+use std::fmt::Write;
+use anyhow::bail;
+
+/// The size of a single mapper bank.
+pub const BANK_SIZE: u32 = 0x80000;
+
+/// The slot banked data is mapped into, which must match `megadrive_sys::mapper`.
+const WINDOW_SLOT: u32 = 7;
+
+/// The maximum number of banks the mapper can address.
+const MAX_BANKS: u32 = 64;
+
+/// Generate a linker script which places each `.bank.N` section into bank N.
+///
+/// Every bank is linked to run from the window slot, and the rest of the ROM
+/// is limited to the slots before it.
+pub fn linker_script(num_banks: u32) -> anyhow::Result<String> {
+    if num_banks <= WINDOW_SLOT + 1 || num_banks > MAX_BANKS {
+        bail!("banks must be between {} and {}, not {}", WINDOW_SLOT + 2, MAX_BANKS, num_banks);
+    }
+
+    let window = WINDOW_SLOT * BANK_SIZE;
+    let mut script = String::new();
+    writeln!(script, "/* Generated by cargo-megadrive for the Sega mapper. */")?;
+    writeln!(script, "SECTIONS\n{{")?;
+    for bank in WINDOW_SLOT..num_banks {
+        writeln!(script, "    .bank.{} {:#x} : AT({:#x}) {{ *(.bank.{} .bank.{}.*); }}",
+            bank, window, bank * BANK_SIZE, bank, bank)?;
+    }
+    writeln!(script, "}}\nINSERT AFTER .bss;\n")?;
+    writeln!(script, "ASSERT(LOADADDR(.data) + SIZEOF(.data) <= {:#x}, \
+        \"code and data must fit before the mapper window\")", window)?;
+    for bank in WINDOW_SLOT..num_banks {
+        writeln!(script, "ASSERT(SIZEOF(.bank.{}) <= {:#x}, \"bank {} is too large\")",
+            bank, BANK_SIZE, bank)?;
+    }
+
+    Ok(script)
+}
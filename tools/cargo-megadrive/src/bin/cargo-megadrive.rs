@@ -16,6 +16,7 @@ struct Opts {
 #[derive(Clap)]
 enum Commands {
     Build(BuildOpts),
+    ImportPatch(ImportPatchOpts),
 }
 
 #[derive(Clap)]
@@ -24,6 +25,16 @@ struct BuildOpts {
     manifest_path: Option<PathBuf>,
 }
 
+/// Convert TFI, VGI & DMP instruments into Rust `FmPatch` constants.
+#[derive(Clap)]
+struct ImportPatchOpts {
+    /// Write to this file instead of stdout.
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+
+    files: Vec<PathBuf>,
+}
+
 fn main() -> anyhow::Result<()> {
     let opts = Opts::parse();
 
@@ -33,6 +44,13 @@ fn main() -> anyhow::Result<()> {
                 .verbose(opts.verbose)
                 .build()?;
         },
+        Commands::ImportPatch(c) => {
+            let source = fmpatch::convert(&c.files)?;
+            match c.output {
+                Some(path) => std::fs::write(path, source)?,
+                None => print!("{}", source),
+            }
+        },
     }
 
     Ok(())
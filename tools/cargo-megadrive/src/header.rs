@@ -0,0 +1,269 @@
+use anyhow::bail;
+use crate::metadata::{HeaderMetadata, SramMetadata, SramWidth};
+
+/// The offset of the first byte after the header.
+const HEADER_END: usize = 0x200;
+
+const SYSTEM_TYPE: (usize, usize) = (0x100, 16);
+const COPYRIGHT: (usize, usize) = (0x110, 16);
+const DOMESTIC_NAME: (usize, usize) = (0x120, 48);
+const OVERSEAS_NAME: (usize, usize) = (0x150, 48);
+const SERIAL: (usize, usize) = (0x180, 14);
+const IO_SUPPORT: (usize, usize) = (0x190, 16);
+const ROM_START: usize = 0x1a0;
+const ROM_END: usize = 0x1a4;
+const RAM_START: usize = 0x1a8;
+const RAM_END: usize = 0x1ac;
+const SRAM: usize = 0x1b0;
+const MEMO: (usize, usize) = (0x1c8, 40);
+const REGION: (usize, usize) = (0x1f0, 16);
+
+/// A set of validated writes to the ROM header.
+pub struct HeaderPatch {
+    writes: Vec<(usize, Vec<u8>)>,
+}
+
+impl HeaderPatch {
+    /// Validate the header metadata and convert it to a patch.
+    pub fn from_metadata(header: &HeaderMetadata) -> anyhow::Result<HeaderPatch> {
+        let mut patch = HeaderPatch { writes: Vec::new() };
+
+        if let Some(v) = header.system_type.as_ref() {
+            if !v.starts_with("SEGA") {
+                bail!("header field `system_type` must start with \"SEGA\"");
+            }
+            patch.text("system_type", SYSTEM_TYPE, v)?;
+        }
+        if let Some(v) = header.copyright.as_ref() {
+            patch.text("copyright", COPYRIGHT, v)?;
+        }
+        if let Some(v) = header.name.as_ref() {
+            patch.text("name", DOMESTIC_NAME, v)?;
+            patch.text("name", OVERSEAS_NAME, v)?;
+        }
+        if let Some(v) = header.domestic_name.as_ref() {
+            patch.text("domestic_name", DOMESTIC_NAME, v)?;
+        }
+        if let Some(v) = header.overseas_name.as_ref() {
+            patch.text("overseas_name", OVERSEAS_NAME, v)?;
+        }
+        if let Some(v) = header.serial.as_ref() {
+            patch.text("serial", SERIAL, v)?;
+        }
+        if let Some(v) = header.io_support.as_ref() {
+            patch.text("io_support", IO_SUPPORT, v)?;
+        }
+        if let Some(v) = header.rom_start {
+            patch.long(ROM_START, v);
+        }
+        if let (Some(start), Some(end)) = (header.ram_start, header.ram_end) {
+            if start > end {
+                bail!("header RAM start {:#x} is after RAM end {:#x}", start, end);
+            }
+        }
+        if let Some(v) = header.ram_start {
+            patch.long(RAM_START, v);
+        }
+        if let Some(v) = header.ram_end {
+            patch.long(RAM_END, v);
+        }
+        if let Some(sram) = header.sram.as_ref() {
+            patch.writes.push((SRAM, sram_descriptor(sram)?));
+        }
+        if let Some(v) = header.memo.as_ref() {
+            patch.text("memo", MEMO, v)?;
+        }
+        if let Some(v) = header.region.as_ref() {
+            validate_region(v)?;
+            patch.text("region", REGION, v)?;
+        }
+
+        Ok(patch)
+    }
+
+    /// Write the header into a linked ROM image.
+    ///
+    /// The ROM end address is always set to the last byte of the image.
+    pub fn apply(&self, rom: &mut [u8]) -> anyhow::Result<()> {
+        if rom.len() < HEADER_END {
+            bail!("ROM image is too small to contain a header ({} bytes)", rom.len());
+        }
+
+        for (offset, bytes) in self.writes.iter() {
+            rom[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        }
+
+        let rom_end = (rom.len() - 1) as u32;
+        rom[ROM_END..ROM_END + 4].copy_from_slice(&rom_end.to_be_bytes());
+        Ok(())
+    }
+
+    fn text(&mut self, name: &str, (offset, len): (usize, usize), value: &str) -> anyhow::Result<()> {
+        if let Some(c) = value.chars().find(|c| !(' '..='~').contains(c)) {
+            bail!("header field `{}` contains {:?}, only printable ASCII is allowed", name, c);
+        }
+        if value.len() > len {
+            bail!("header field `{}` is {} characters long, the maximum is {}",
+                name, value.len(), len);
+        }
+
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.resize(len, b' ');
+        self.writes.push((offset, bytes));
+        Ok(())
+    }
+
+    fn long(&mut self, offset: usize, value: u32) {
+        self.writes.push((offset, value.to_be_bytes().to_vec()));
+    }
+}
+
+fn validate_region(region: &str) -> anyhow::Result<()> {
+    if region.is_empty() {
+        bail!("header field `region` must list at least one region");
+    }
+
+    // Later games use a single hex digit, with a bit for each of Japan,
+    // Japan PAL, the Americas and Europe.
+    if region.len() == 1 && region.chars().all(|c| c.is_ascii_digit() || ('A'..='F').contains(&c)) {
+        return Ok(());
+    }
+
+    for (idx, c) in region.char_indices() {
+        if !"JUE".contains(c) {
+            bail!("header field `region` contains {:?}, only J, U and E or a single hex digit are allowed", c);
+        }
+        if region[..idx].contains(c) {
+            bail!("header field `region` contains {:?} more than once", c);
+        }
+    }
+
+    Ok(())
+}
+
+fn sram_descriptor(sram: &SramMetadata) -> anyhow::Result<Vec<u8>> {
+    if sram.start > sram.end {
+        bail!("SRAM start {:#x} is after SRAM end {:#x}", sram.start, sram.end);
+    }
+    if sram.end >= 0x400000 {
+        bail!("SRAM end {:#x} is outside of the cartridge area", sram.end);
+    }
+
+    let mut kind = 0xa0;
+    if sram.battery {
+        kind |= 0x40;
+    }
+    kind |= match sram.width.unwrap_or(SramWidth::Odd) {
+        SramWidth::Both => 0x00,
+        SramWidth::Even => 0x10,
+        SramWidth::Odd => 0x18,
+    };
+
+    let mut bytes = vec![b'R', b'A', kind, 0x20];
+    bytes.extend_from_slice(&sram.start.to_be_bytes());
+    bytes.extend_from_slice(&sram.end.to_be_bytes());
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(header: HeaderMetadata) -> anyhow::Result<Vec<u8>> {
+        let mut rom = vec![0; 0x400];
+        HeaderPatch::from_metadata(&header)?.apply(&mut rom)?;
+        Ok(rom)
+    }
+
+    fn region(region: &str) -> anyhow::Result<Vec<u8>> {
+        patch(HeaderMetadata { region: Some(region.into()), ..HeaderMetadata::default() })
+    }
+
+    fn sram(start: u32, end: u32, battery: bool, width: Option<SramWidth>) -> anyhow::Result<Vec<u8>> {
+        let sram = SramMetadata { start, end, battery, width };
+        patch(HeaderMetadata { sram: Some(sram), ..HeaderMetadata::default() })
+    }
+
+    #[test]
+    fn regions() {
+        for ok in ["J", "JUE", "EU", "0", "4", "F"].iter() {
+            let rom = region(ok).unwrap();
+            assert_eq!(&rom[REGION.0..REGION.0 + ok.len()], ok.as_bytes());
+            assert!(rom[REGION.0 + ok.len()..REGION.0 + REGION.1].iter().all(|&b| b == b' '));
+        }
+
+        for bad in ["", "X", "JJ", "JUEJ", "f", "G", "4J", "12"].iter() {
+            assert!(region(bad).is_err(), "{:?} was accepted", bad);
+        }
+    }
+
+    #[test]
+    fn text_fields() {
+        let name = "A".repeat(48);
+        let rom = patch(HeaderMetadata {
+            name: Some(name.clone()),
+            serial: Some("GM 00000000-00".into()),
+            ..HeaderMetadata::default()
+        }).unwrap();
+        assert_eq!(&rom[DOMESTIC_NAME.0..DOMESTIC_NAME.0 + 48], name.as_bytes());
+        assert_eq!(&rom[OVERSEAS_NAME.0..OVERSEAS_NAME.0 + 48], name.as_bytes());
+        assert_eq!(&rom[SERIAL.0..SERIAL.0 + 14], b"GM 00000000-00");
+        // Fields which are not set are left alone.
+        assert!(rom[COPYRIGHT.0..COPYRIGHT.0 + COPYRIGHT.1].iter().all(|&b| b == 0));
+
+        let rom = patch(HeaderMetadata { copyright: Some("(C)SEGA".into()), ..HeaderMetadata::default() }).unwrap();
+        assert_eq!(&rom[COPYRIGHT.0..COPYRIGHT.0 + COPYRIGHT.1], b"(C)SEGA         ");
+
+        let long = HeaderMetadata { name: Some("A".repeat(49)), ..HeaderMetadata::default() };
+        assert!(patch(long).is_err());
+        let unicode = HeaderMetadata { memo: Some("caf\u{e9}".into()), ..HeaderMetadata::default() };
+        assert!(patch(unicode).is_err());
+        let control = HeaderMetadata { memo: Some("a\tb".into()), ..HeaderMetadata::default() };
+        assert!(patch(control).is_err());
+        let system = HeaderMetadata { system_type: Some("GENESIS".into()), ..HeaderMetadata::default() };
+        assert!(patch(system).is_err());
+    }
+
+    #[test]
+    fn addresses() {
+        let rom = patch(HeaderMetadata {
+            rom_start: Some(0),
+            ram_start: Some(0xff0000),
+            ram_end: Some(0xffffff),
+            ..HeaderMetadata::default()
+        }).unwrap();
+        assert_eq!(rom[ROM_START..ROM_START + 4], [0, 0, 0, 0]);
+        assert_eq!(rom[RAM_START..RAM_START + 4], [0x00, 0xff, 0x00, 0x00]);
+        assert_eq!(rom[RAM_END..RAM_END + 4], [0x00, 0xff, 0xff, 0xff]);
+
+        let backwards = HeaderMetadata { ram_start: Some(0xffffff), ram_end: Some(0xff0000), ..HeaderMetadata::default() };
+        assert!(patch(backwards).is_err());
+    }
+
+    #[test]
+    fn sram_descriptors() {
+        let rom = sram(0x200001, 0x203fff, true, None).unwrap();
+        assert_eq!(rom[SRAM..SRAM + 12], [b'R', b'A', 0xf8, 0x20, 0x00, 0x20, 0x00, 0x01, 0x00, 0x20, 0x3f, 0xff]);
+
+        let rom = sram(0x200000, 0x203ffe, true, Some(SramWidth::Even)).unwrap();
+        assert_eq!(rom[SRAM + 2], 0xf0);
+
+        let rom = sram(0x200000, 0x201fff, false, Some(SramWidth::Both)).unwrap();
+        assert_eq!(rom[SRAM + 2], 0xa0);
+
+        assert!(sram(0x203fff, 0x200001, true, None).is_err());
+        assert!(sram(0x3ff001, 0x400fff, true, None).is_err());
+    }
+
+    #[test]
+    fn rom_end() {
+        let patch = HeaderPatch::from_metadata(&HeaderMetadata::default()).unwrap();
+
+        let mut rom = vec![0; 0x20001];
+        patch.apply(&mut rom).unwrap();
+        assert_eq!(rom[ROM_END..ROM_END + 4], [0x00, 0x02, 0x00, 0x00]);
+
+        let mut rom = vec![0; HEADER_END - 1];
+        assert!(patch.apply(&mut rom).is_err());
+    }
+}
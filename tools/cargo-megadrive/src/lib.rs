@@ -6,9 +6,13 @@ use duct::cmd;
 use cargo_metadata::MetadataCommand;
 use anyhow::anyhow;
 use crate::metadata::{Metadata};
+use crate::header::HeaderPatch;
 
+mod banks;
+mod header;
 mod llvm_config;
 mod metadata;
+mod rom;
 
 pub struct Builder {
     cargo_metadata: cargo_metadata::Metadata,
@@ -18,6 +22,12 @@ pub struct Builder {
     profile: String,
     linker_script: PathBuf,
     entry: Option<PathBuf>,
+    header: HeaderPatch,
+    pad_to: usize,
+    smd: bool,
+    sram: Option<(u32, u32, u32)>,
+    banks: Option<(u32, String)>,
+    z80_sources: Vec<PathBuf>,
     verbose: bool,
 }
 
@@ -50,7 +60,7 @@ impl Builder {
         let package_dir = root_package.manifest_path.parent()
             .unwrap_or(Path::new(""));
 
-        let metadata = if let Some(c) = root_package.metadata.get("megadrive") {
+        let mut metadata: Metadata = if let Some(c) = root_package.metadata.get("megadrive") {
             serde_json::from_value(c.clone())?
         } else {
             Metadata::default()
@@ -62,6 +72,28 @@ impl Builder {
             if has_entry { Some(default_entry_path.to_owned()) } else { None }
         });
 
+        let banks = match metadata.banks {
+            Some(n) => Some((n, banks::linker_script(n)?)),
+            None => None,
+        };
+        if banks.is_some() && metadata.header.system_type.is_none() {
+            // This is how emulators detect the mapper.
+            metadata.header.system_type = Some("SEGA SSF".into());
+        }
+
+        let header = HeaderPatch::from_metadata(&metadata.header)?;
+        let mut pad_to = metadata.pad_to.unwrap_or(2);
+        if metadata.smd {
+            pad_to = pad_to.max(rom::SMD_BLOCK_SIZE);
+        }
+        if !pad_to.is_power_of_two() {
+            return Err(anyhow!("pad_to must be a power of two, not {}", pad_to));
+        }
+
+        let z80_sources = metadata.z80_sources.iter()
+            .map(|p| package_dir.join(p))
+            .collect();
+
         let linker_script = metadata.linker_script
             .unwrap_or_else(|| sdk_home.join("ldscripts/megadrive.x"));
 
@@ -83,6 +115,12 @@ impl Builder {
             target_triple,
             linker_script,
             entry,
+            header,
+            pad_to,
+            smd: metadata.smd,
+            sram: metadata.header.sram.as_ref().map(|s| (s.start, s.end, s.size())),
+            banks,
+            z80_sources,
             output: None,
             profile: "release".into(),
             verbose: false,
@@ -127,10 +165,21 @@ impl Builder {
                 path
             });
 
+        // Assemble the Z80 code first, so that it can be included in the build.
+        let z80_dir = out_dir.join("z80");
+        for source in self.z80_sources.iter() {
+            let stem = source.file_stem()
+                .and_then(|s| s.to_str())
+                .ok_or_else(|| anyhow!("invalid Z80 source path {}", source.display()))?;
+            let program = z80asm::Assembler::new().assemble_file(source)?;
+            program.write(&z80_dir, stem)?;
+        }
+
         let verbose_flag = OsString::from(if self.verbose { "-v" } else { "" });
         cmd!("cargo", "build", "--manifest-path", &root.manifest_path,
             "-Z", "unstable-options", "-Z", "build-std=core,alloc",
             "--profile", &self.profile, "--target", &self.target, verbose_flag)
+            .env("MEGADRIVE_Z80_DIR", &z80_dir)
             .run()?;
 
         let mut objects = vec![];
@@ -146,12 +195,46 @@ impl Builder {
 
         let mut link_args: Vec<OsString> = vec![
             "--gc-sections".into(),
-            "-o".into(), OsString::from(&elf), "-T".into(), self.linker_script.into()];
+            "-o".into(), OsString::from(&elf)];
+        // These must come before the linker script to override its defaults.
+        if let Some((start, end, size)) = self.sram {
+            link_args.push(format!("--defsym=_sram_start={:#x}", start).into());
+            link_args.push(format!("--defsym=_sram_end={:#x}", end).into());
+            link_args.push(format!("--defsym=_sram_size={:#x}", size).into());
+        }
+        link_args.push("-T".into());
+        link_args.push(self.linker_script.into());
+        if let Some((_, script)) = self.banks.as_ref() {
+            let path = out_dir.join("banks.x");
+            fs::write(&path, script)?;
+            // Every bank is linked at the same address.
+            link_args.push("--no-check-sections".into());
+            link_args.push("-T".into());
+            link_args.push(path.into());
+        }
         link_args.extend(objects.into_iter().map(|o| o.into_os_string()));
         link_args.push(OsString::from(staticlib));
         cmd(llvm_config.ld_lld()?, &link_args).run()?;
 
         cmd!(llvm_config.objcopy()?, "-O", "binary", &elf, &output).run()?;
+
+        // Fix up the image: the header depends on the padded size and the
+        // checksum covers everything else.
+        let mut rom = fs::read(&output)?;
+        if let Some((num_banks, _)) = self.banks {
+            rom::extend(&mut rom, (num_banks * banks::BANK_SIZE) as usize);
+        }
+        rom::pad(&mut rom, self.pad_to);
+        self.header.apply(&mut rom)?;
+        rom::patch_checksum(&mut rom);
+        fs::write(&output, &rom)?;
+
+        if self.smd {
+            let mut path = output.clone();
+            path.set_extension("smd");
+            fs::write(path, rom::to_smd(&rom)?)?;
+        }
+
         Ok(())
     }
 }
@@ -6,6 +6,15 @@ use serde::{Serialize, Deserialize};
 pub struct Metadata {
     pub linker_script: Option<PathBuf>,
     pub entry_assembly: Option<PathBuf>,
+    pub header: HeaderMetadata,
+    /// Pad the ROM to a multiple of this many bytes.
+    pub pad_to: Option<usize>,
+    /// Also write an interleaved `.smd` image.
+    pub smd: bool,
+    /// The number of 512KiB banks in the ROM, when using the Sega mapper.
+    pub banks: Option<u32>,
+    /// Z80 sources to assemble before building.
+    pub z80_sources: Vec<PathBuf>,
 }
 
 impl Default for Metadata {
@@ -13,6 +22,68 @@ impl Default for Metadata {
         Metadata {
             linker_script: None,
             entry_assembly: None,
+            header: HeaderMetadata::default(),
+            pad_to: None,
+            smd: false,
+            banks: None,
+            z80_sources: Vec::new(),
         }
     }
 }
+
+/// Overrides for the ROM header, from `[package.metadata.megadrive.header]`.
+///
+/// Fields which are not set are left as they are in the linked image.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct HeaderMetadata {
+    pub system_type: Option<String>,
+    pub copyright: Option<String>,
+    /// Sets both the domestic & overseas names.
+    pub name: Option<String>,
+    pub domestic_name: Option<String>,
+    pub overseas_name: Option<String>,
+    pub serial: Option<String>,
+    pub io_support: Option<String>,
+    pub rom_start: Option<u32>,
+    pub ram_start: Option<u32>,
+    pub ram_end: Option<u32>,
+    pub sram: Option<SramMetadata>,
+    pub memo: Option<String>,
+    pub region: Option<String>,
+}
+
+/// The SRAM descriptor in the ROM header.
+#[derive(Serialize, Deserialize)]
+pub struct SramMetadata {
+    pub start: u32,
+    pub end: u32,
+    #[serde(default = "default_battery")]
+    pub battery: bool,
+    /// Defaults to the odd bytes, which is how most cartridges are wired.
+    #[serde(default)]
+    pub width: Option<SramWidth>,
+}
+
+fn default_battery() -> bool { true }
+
+impl SramMetadata {
+    /// The number of usable bytes, which is half of the address range unless
+    /// the SRAM is connected to both bytes of the bus.
+    pub fn size(&self) -> u32 {
+        let span = self.end.saturating_sub(self.start);
+        match self.width.unwrap_or(SramWidth::Odd) {
+            SramWidth::Both => span + 1,
+            _ => (span >> 1) + 1,
+        }
+    }
+}
+
+/// Which bytes of the bus the SRAM is connected to.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SramWidth {
+    Odd,
+    Even,
+    Both,
+}
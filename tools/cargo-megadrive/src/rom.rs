@@ -0,0 +1,147 @@
+use anyhow::bail;
+
+/// The offset of the checksum in the ROM header.
+const CHECKSUM: usize = 0x18e;
+/// The first byte covered by the checksum, just after the header.
+const CHECKSUM_START: usize = 0x200;
+
+/// The size of a block in an interleaved `.smd` image.
+pub const SMD_BLOCK_SIZE: usize = 0x4000;
+const SMD_HEADER_SIZE: usize = 0x200;
+
+/// The value unused ROM is filled with.
+const PAD_BYTE: u8 = 0xff;
+
+/// Pad the image up to a multiple of `boundary` bytes.
+///
+/// The boundary must be a power of two.
+pub fn pad(rom: &mut Vec<u8>, boundary: usize) {
+    let len = (rom.len() + boundary - 1) & !(boundary - 1);
+    rom.resize(len, PAD_BYTE);
+}
+
+/// Pad the image up to at least `size` bytes.
+pub fn extend(rom: &mut Vec<u8>, size: usize) {
+    if rom.len() < size {
+        rom.resize(size, PAD_BYTE);
+    }
+}
+
+/// Compute the standard checksum: the wrapping sum of every big-endian word
+/// after the header.
+pub fn checksum(rom: &[u8]) -> u16 {
+    rom.get(CHECKSUM_START..)
+        .unwrap_or(&[])
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], c.get(1).cloned().unwrap_or(0)]))
+        .fold(0u16, |a, b| a.wrapping_add(b))
+}
+
+/// Compute the checksum and write it into the header.
+pub fn patch_checksum(rom: &mut [u8]) {
+    let sum = checksum(rom);
+    rom[CHECKSUM..CHECKSUM + 2].copy_from_slice(&sum.to_be_bytes());
+}
+
+/// Convert a ROM image to the interleaved `.smd` format.
+///
+/// Each 16KiB block is stored with all of the odd bytes first, followed by
+/// the even bytes. The image is padded to a whole number of blocks.
+pub fn to_smd(rom: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let num_blocks = rom.chunks(SMD_BLOCK_SIZE).len();
+    if num_blocks > 0xff {
+        bail!("ROM is too large to convert to SMD ({} bytes)", rom.len());
+    }
+
+    let mut smd = vec![0u8; SMD_HEADER_SIZE];
+    smd[0] = num_blocks as u8;
+    smd[1] = 3;
+    smd[8] = 0xaa;
+    smd[9] = 0xbb;
+    smd[10] = 6;
+
+    let half = SMD_BLOCK_SIZE / 2;
+    for block in rom.chunks(SMD_BLOCK_SIZE) {
+        let mut out = vec![PAD_BYTE; SMD_BLOCK_SIZE];
+        for (i, &b) in block.iter().enumerate() {
+            let idx = if (i & 1) != 0 { i / 2 } else { half + i / 2 };
+            out[idx] = b;
+        }
+        smd.extend_from_slice(&out);
+    }
+
+    Ok(smd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_words() {
+        let mut rom = vec![0xaa; CHECKSUM_START];
+        assert_eq!(checksum(&rom), 0);
+        assert_eq!(checksum(&rom[..0x100]), 0);
+
+        rom.extend_from_slice(&[0x12, 0x34, 0xff, 0xff, 0x00, 0x02]);
+        assert_eq!(checksum(&rom), 0x1235);
+
+        // An odd byte at the end is the high byte of a final word.
+        rom.push(0x56);
+        assert_eq!(checksum(&rom), 0x6835);
+    }
+
+    #[test]
+    fn patch() {
+        let mut rom = vec![0; CHECKSUM_START];
+        rom.extend_from_slice(&[0xbe, 0xef]);
+        patch_checksum(&mut rom);
+        assert_eq!(rom[CHECKSUM..CHECKSUM + 2], [0xbe, 0xef]);
+    }
+
+    #[test]
+    fn padding() {
+        let mut rom = vec![0; 0x1234];
+        pad(&mut rom, 0x1000);
+        assert_eq!(rom.len(), 0x2000);
+        assert!(rom[0x1234..].iter().all(|&b| b == PAD_BYTE));
+
+        pad(&mut rom, 0x1000);
+        assert_eq!(rom.len(), 0x2000);
+
+        extend(&mut rom, 0x1000);
+        assert_eq!(rom.len(), 0x2000);
+        extend(&mut rom, 0x2001);
+        assert_eq!(rom.len(), 0x2001);
+    }
+
+    #[test]
+    fn smd() {
+        let rom: Vec<u8> = (0..SMD_BLOCK_SIZE + 3).map(|i| i as u8).collect();
+        let smd = to_smd(&rom).unwrap();
+        assert_eq!(smd.len(), SMD_HEADER_SIZE + 2 * SMD_BLOCK_SIZE);
+        assert_eq!(smd[..11], [2, 3, 0, 0, 0, 0, 0, 0, 0xaa, 0xbb, 6]);
+        assert!(smd[11..SMD_HEADER_SIZE].iter().all(|&b| b == 0));
+
+        // The odd bytes of each block come first, then the even bytes.
+        let half = SMD_BLOCK_SIZE / 2;
+        let first = &smd[SMD_HEADER_SIZE..SMD_HEADER_SIZE + SMD_BLOCK_SIZE];
+        assert_eq!(first[..3], [1, 3, 5]);
+        assert_eq!(first[half - 1], 0xff);
+        assert_eq!(first[half..half + 3], [0, 2, 4]);
+        assert_eq!(first[SMD_BLOCK_SIZE - 1], 0xfe);
+
+        // The last block is padded.
+        let last = &smd[SMD_HEADER_SIZE + SMD_BLOCK_SIZE..];
+        assert_eq!(last[0], 1);
+        assert!(last[1..half].iter().all(|&b| b == PAD_BYTE));
+        assert_eq!(last[half..half + 2], [0, 2]);
+        assert!(last[half + 2..].iter().all(|&b| b == PAD_BYTE));
+    }
+
+    #[test]
+    fn smd_too_large() {
+        assert!(to_smd(&vec![0; 0xff * SMD_BLOCK_SIZE]).is_ok());
+        assert!(to_smd(&vec![0; 0xff * SMD_BLOCK_SIZE + 1]).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{Instrument, Operator};
+
+/// The DMP system byte for the Genesis, with and without channel 3 special mode.
+const DMP_SYSTEM_GENESIS: &[u8] = &[0x02, 0x42];
+const DMP_VERSION: u8 = 11;
+const DMP_MODE_FM: u8 = 1;
+
+/// Reads fields from an instrument file, checking their ranges.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    operator: Option<usize>,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], len: usize, format: &str) -> Result<Reader<'a>, String> {
+        if data.len() != len {
+            return Err(format!("{} files must be {} bytes, this is {}", format, len, data.len()));
+        }
+        Ok(Reader { data, pos: 0, operator: None })
+    }
+
+    fn byte(&mut self) -> u8 {
+        let v = self.data[self.pos];
+        self.pos += 1;
+        v
+    }
+
+    fn field(&mut self, name: &str, max: u8) -> Result<u8, String> {
+        let v = self.byte();
+        if v > max {
+            return Err(match self.operator {
+                Some(op) => format!("operator {} {} is {}, the maximum is {}", op + 1, name, v, max),
+                None => format!("{} is {}, the maximum is {}", name, v, max),
+            });
+        }
+        Ok(v)
+    }
+}
+
+/// Convert a detune where 3 means no detune into the YM2612's sign & magnitude.
+fn detune(v: u8) -> u8 {
+    const TABLE: [u8; 8] = [7, 6, 5, 0, 1, 2, 3, 4];
+    TABLE[v as usize & 7]
+}
+
+/// Parse the operators shared by the TFI & VGI formats.
+///
+/// VGI stores the AM flag in the top bit of the decay rate.
+fn tfi_operators(r: &mut Reader, vgi: bool) -> Result<[Operator; 4], String> {
+    let mut operators = [Operator::default(); 4];
+    for (i, op) in operators.iter_mut().enumerate() {
+        r.operator = Some(i);
+        op.multiply = r.field("MUL", 15)?;
+        op.detune = detune(r.field("DT", 7)?);
+        op.total_level = r.field("TL", 127)?;
+        op.rate_scale = r.field("RS", 3)?;
+        op.attack_rate = r.field("AR", 31)?;
+        if vgi {
+            let v = r.byte();
+            op.amplitude_modulation = (v & 0x80) != 0;
+            op.decay_rate = v & 0x7f;
+            if op.decay_rate > 31 {
+                return Err(format!("operator {} DR is {}, the maximum is 31", i + 1, op.decay_rate));
+            }
+        } else {
+            op.decay_rate = r.field("DR", 31)?;
+        }
+        op.sustain_rate = r.field("SR", 31)?;
+        op.release_rate = r.field("RR", 15)?;
+        op.sustain_level = r.field("SL", 15)?;
+        op.ssg_eg = r.field("SSG-EG", 15)?;
+    }
+    Ok(operators)
+}
+
+/// Parse a TFI Mega Drive instrument, as used by TFM Music Maker.
+pub fn parse_tfi(data: &[u8]) -> Result<Instrument, String> {
+    let mut r = Reader::new(data, 42, "TFI")?;
+    let algorithm = r.field("algorithm", 7)?;
+    let feedback = r.field("feedback", 7)?;
+    let operators = tfi_operators(&mut r, false)?;
+    Ok(Instrument { algorithm, feedback, ams: 0, fms: 0, operators })
+}
+
+/// Parse a VGI instrument, as used by VGM Music Maker.
+pub fn parse_vgi(data: &[u8]) -> Result<Instrument, String> {
+    let mut r = Reader::new(data, 43, "VGI")?;
+    let algorithm = r.field("algorithm", 7)?;
+    let feedback = r.field("feedback", 7)?;
+    let modulation = r.byte();
+    if (modulation & !0x37) != 0 {
+        return Err(format!("AMS/FMS is {:#04x}, which sets unused bits", modulation));
+    }
+    let operators = tfi_operators(&mut r, true)?;
+    Ok(Instrument {
+        algorithm,
+        feedback,
+        ams: (modulation >> 4) & 3,
+        fms: modulation & 7,
+        operators,
+    })
+}
+
+/// Parse a version 11 DefleMask FM instrument for the Genesis.
+pub fn parse_dmp(data: &[u8]) -> Result<Instrument, String> {
+    if data.len() < 3 {
+        return Err("DMP file is truncated".into());
+    }
+    if data[0] != DMP_VERSION {
+        return Err(format!("DMP version {} is not supported, only {}", data[0], DMP_VERSION));
+    }
+    if !DMP_SYSTEM_GENESIS.contains(&data[1]) {
+        return Err(format!("DMP system {:#04x} is not the Genesis", data[1]));
+    }
+    if data[2] != DMP_MODE_FM {
+        return Err("DMP instrument is not an FM instrument".into());
+    }
+
+    let mut r = Reader::new(data, 51, "Genesis DMP")?;
+    r.pos = 3;
+    let fms = r.field("LFO (FMS)", 7)?;
+    let feedback = r.field("feedback", 7)?;
+    let algorithm = r.field("algorithm", 7)?;
+    let ams = r.field("LFO2 (AMS)", 3)?;
+
+    let mut operators = [Operator::default(); 4];
+    for (i, op) in operators.iter_mut().enumerate() {
+        r.operator = Some(i);
+        op.multiply = r.field("MUL", 15)?;
+        op.total_level = r.field("TL", 127)?;
+        op.attack_rate = r.field("AR", 31)?;
+        op.decay_rate = r.field("DR", 31)?;
+        op.sustain_level = r.field("SL", 15)?;
+        op.release_rate = r.field("RR", 15)?;
+        op.amplitude_modulation = r.field("AM", 1)? != 0;
+        op.rate_scale = r.field("RS", 3)?;
+        op.detune = detune(r.field("DT", 7)?);
+        op.sustain_rate = r.field("D2R", 31)?;
+        op.ssg_eg = r.field("SSG-EG", 15)?;
+    }
+
+    Ok(Instrument { algorithm, feedback, ams, fms, operators })
+}
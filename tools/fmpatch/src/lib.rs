@@ -0,0 +1,196 @@
+//! Import YM2612 instruments from the files written by FM editors.
+//!
+//! The following formats are supported:
+//!
+//! - `.tfi`, from TFM Music Maker
+//! - `.vgi`, from VGM Music Maker
+//! - `.dmp`, from DefleMask (version 11, Genesis FM instruments only)
+//!
+//! Instruments can be converted into Rust source declaring a
+//! `megadrive_sys::fm::FmPatch`, or into a table of register writes. The
+//! [`build`] function does this from a build script.
+
+use std::env;
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+mod formats;
+
+/// An error encountered while importing an instrument.
+#[derive(Debug, Clone)]
+pub struct Error {
+    /// The file which contains the error.
+    pub file: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.file.display(), self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The format of an instrument file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Tfi,
+    Vgi,
+    Dmp,
+}
+
+impl Format {
+    /// Guess the format of a file from its extension.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Format> {
+        let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "tfi" => Some(Format::Tfi),
+            "vgi" => Some(Format::Vgi),
+            "dmp" => Some(Format::Dmp),
+            _ => None,
+        }
+    }
+}
+
+/// The settings for a single operator.
+///
+/// The values are as they are written to the YM2612. In particular, the
+/// detune is in the chip's sign & magnitude form.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Operator {
+    pub multiply: u8,
+    pub detune: u8,
+    pub total_level: u8,
+    pub attack_rate: u8,
+    pub rate_scale: u8,
+    pub decay_rate: u8,
+    pub amplitude_modulation: bool,
+    pub sustain_rate: u8,
+    pub sustain_level: u8,
+    pub release_rate: u8,
+    pub ssg_eg: u8,
+}
+
+/// An FM instrument.
+///
+/// The operators are in register order, which is operators 1, 3, 2 & 4.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Instrument {
+    pub algorithm: u8,
+    pub feedback: u8,
+    pub ams: u8,
+    pub fms: u8,
+    pub operators: [Operator; 4],
+}
+
+impl Instrument {
+    /// Parse an instrument from the contents of a file.
+    pub fn parse(data: &[u8], format: Format) -> Result<Instrument, String> {
+        match format {
+            Format::Tfi => formats::parse_tfi(data),
+            Format::Vgi => formats::parse_vgi(data),
+            Format::Dmp => formats::parse_dmp(data),
+        }
+    }
+
+    /// Load an instrument from a file, using its extension to pick the format.
+    pub fn load(path: impl AsRef<Path>) -> Result<Instrument, Error> {
+        let path = path.as_ref();
+        let error = |message: String| Error { file: path.to_owned(), message };
+        let format = Format::from_path(path)
+            .ok_or_else(|| error("unknown instrument format, expected .tfi, .vgi or .dmp".into()))?;
+        let data = fs::read(path).map_err(|e| error(e.to_string()))?;
+        Instrument::parse(&data, format).map_err(error)
+    }
+
+    /// The register writes for this instrument on the first channel.
+    ///
+    /// The registers for other channels are found by adding the channel
+    /// within the bank (0-2) to the address. The panning is set to both
+    /// speakers.
+    pub fn registers(&self) -> Vec<(u8, u8)> {
+        let mut out = Vec::with_capacity(30);
+        for (i, op) in self.operators.iter().enumerate() {
+            let offset = (i as u8) << 2;
+            out.push((0x30 + offset, (op.detune << 4) | op.multiply));
+            out.push((0x40 + offset, op.total_level));
+            out.push((0x50 + offset, (op.rate_scale << 6) | op.attack_rate));
+            out.push((0x60 + offset, ((op.amplitude_modulation as u8) << 7) | op.decay_rate));
+            out.push((0x70 + offset, op.sustain_rate));
+            out.push((0x80 + offset, (op.sustain_level << 4) | op.release_rate));
+            out.push((0x90 + offset, op.ssg_eg));
+        }
+        out.push((0xb0, (self.feedback << 3) | self.algorithm));
+        out.push((0xb4, 0xc0 | (self.ams << 4) | self.fms));
+        out
+    }
+
+    /// Format the instrument as a Rust constant of type `FmPatch`.
+    pub fn to_rust(&self, name: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "pub const {}: ::megadrive_sys::fm::FmPatch = ::megadrive_sys::fm::FmPatch::new({}, {}, [",
+                         name, self.algorithm, self.feedback);
+        for op in self.operators.iter() {
+            let _ = writeln!(out, "    ::megadrive_sys::fm::FmOperator::new()\
+                .multiplier({}, {})\
+                .total_level({})\
+                .attack_rate({}, {})\
+                .decay_rate({}, {})\
+                .sustain_rate({})\
+                .release_rate({}, {})\
+                .ssg_eg({}),",
+                op.multiply, op.detune, op.total_level, op.attack_rate, op.rate_scale,
+                op.decay_rate, op.amplitude_modulation, op.sustain_rate,
+                op.release_rate, op.sustain_level, op.ssg_eg);
+        }
+        let _ = writeln!(out, "]).modulation({}, {});", self.ams, self.fms);
+        out
+    }
+}
+
+/// Make a constant name from a file name, e.g. `slap bass-2.tfi` becomes
+/// `SLAP_BASS_2`.
+pub fn const_name(path: impl AsRef<Path>) -> String {
+    let stem = path.as_ref().file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut name: String = stem.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert_str(0, "PATCH_");
+    }
+    name
+}
+
+/// Convert instrument files into Rust source, with a constant per file.
+pub fn convert<P: AsRef<Path>>(paths: impl IntoIterator<Item=P>) -> Result<String, Error> {
+    let mut out = String::new();
+    for path in paths {
+        let path = path.as_ref();
+        let instrument = Instrument::load(path)?;
+        let _ = writeln!(out, "/// Imported from `{}`.", path.file_name().unwrap_or_default().to_string_lossy());
+        out.push_str(&instrument.to_rust(&const_name(path)));
+    }
+    Ok(out)
+}
+
+/// Import instruments from a build script.
+///
+/// The constants are written to `<OUT_DIR>/fm_patches.rs`, and cargo is told
+/// to rerun the build script if any of the files change. Returns the path of
+/// the generated source, which can be used with `include!`.
+pub fn build<P: AsRef<Path>>(paths: impl IntoIterator<Item=P>) -> PathBuf {
+    let out_dir = env::var_os("OUT_DIR").expect("OUT_DIR is not set, is this a build script?");
+    let paths: Vec<PathBuf> = paths.into_iter().map(|p| p.as_ref().to_owned()).collect();
+    for path in paths.iter() {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+
+    let source = convert(&paths).unwrap_or_else(|e| panic!("failed to import instrument: {}", e));
+    let out = Path::new(&out_dir).join("fm_patches.rs");
+    fs::write(&out, source).expect("failed to write instruments");
+    out
+}
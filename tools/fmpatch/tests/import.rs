@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+
+use fmpatch::{Format, Instrument, Operator};
+
+fn data(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data").join(name)
+}
+
+/// Build an operator from MUL, DT, TL, AR, RS, DR, SR, RR & SL.
+fn op(v: [u8; 9]) -> Operator {
+    let [multiply, detune, total_level, attack_rate, rate_scale,
+         decay_rate, sustain_rate, release_rate, sustain_level] = v;
+    Operator {
+        multiply,
+        detune,
+        total_level,
+        attack_rate,
+        rate_scale,
+        decay_rate,
+        amplitude_modulation: false,
+        sustain_rate,
+        sustain_level,
+        release_rate,
+        ssg_eg: 0,
+    }
+}
+
+/// The instrument stored in each of the `piano.*` files.
+fn piano() -> Instrument {
+    Instrument {
+        algorithm: 2,
+        feedback: 6,
+        ams: 0,
+        fms: 0,
+        operators: [
+            op([1, 7, 35, 31, 1, 5, 2, 1, 1]),
+            op([13, 0, 45, 25, 2, 5, 2, 1, 1]),
+            op([3, 3, 38, 31, 1, 5, 2, 1, 1]),
+            op([1, 0, 0, 20, 2, 7, 2, 6, 10]),
+        ],
+    }
+}
+
+/// The piano, with the extra settings the VGI & DMP files can store.
+fn modulated_piano() -> Instrument {
+    let mut expected = piano();
+    expected.ams = 1;
+    expected.fms = 2;
+    expected.operators[3].amplitude_modulation = true;
+    expected
+}
+
+#[test]
+fn tfi() {
+    assert_eq!(Instrument::load(data("piano.tfi")).unwrap(), piano());
+}
+
+#[test]
+fn vgi() {
+    assert_eq!(Instrument::load(data("piano.vgi")).unwrap(), modulated_piano());
+}
+
+#[test]
+fn dmp() {
+    assert_eq!(Instrument::load(data("piano.dmp")).unwrap(), modulated_piano());
+}
+
+#[test]
+fn out_of_range() {
+    let e = Instrument::load(data("bad_level.tfi")).unwrap_err();
+    assert_eq!(e.message, "operator 2 TL is 200, the maximum is 127");
+}
+
+#[test]
+fn wrong_size() {
+    let e = Instrument::parse(&[0; 41], Format::Tfi).unwrap_err();
+    assert_eq!(e, "TFI files must be 42 bytes, this is 41");
+}
+
+#[test]
+fn dmp_other_system() {
+    let mut bytes = std::fs::read(data("piano.dmp")).unwrap();
+    bytes[1] = 0x08;
+    assert!(Instrument::parse(&bytes, Format::Dmp).is_err());
+}
+
+#[test]
+fn unknown_extension() {
+    assert!(Instrument::load(data("piano.bin")).is_err());
+}
+
+#[test]
+fn registers() {
+    let regs = modulated_piano().registers();
+    assert_eq!(regs.len(), 30);
+    assert_eq!(regs[0], (0x30, 0x71));
+    assert_eq!(regs[7 * 3 + 3], (0x6c, 0x87));
+    assert_eq!(regs[28], (0xb0, 0x32));
+    assert_eq!(regs[29], (0xb4, 0xd2));
+}
+
+#[test]
+fn rust_source() {
+    let source = fmpatch::convert(&[data("piano.tfi")]).unwrap();
+    assert!(source.contains("pub const PIANO: ::megadrive_sys::fm::FmPatch"));
+    assert!(source.contains(".multiplier(1, 7).total_level(35)"));
+    assert!(source.contains("]).modulation(0, 0);"));
+}
+
+#[test]
+fn const_names() {
+    assert_eq!(fmpatch::const_name("slap bass-2.tfi"), "SLAP_BASS_2");
+    assert_eq!(fmpatch::const_name("808.dmp"), "PATCH_808");
+}
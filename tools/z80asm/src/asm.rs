@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::expr::{eval, qualify, Env};
+use crate::instructions::encode;
+use crate::source::{parse_string, resolve, split_line, split_operands, Line};
+use crate::Error;
+
+/// All of the directives understood by the assembler.
+pub const DIRECTIVES: &[&str] = &[
+    "org", "equ", "=", "db", "defb", "byte", "dm", "defm", "dw", "defw", "word",
+    "ds", "defs", "block", "align", "incbin", "include", "end",
+];
+
+/// The maximum number of passes to resolve forward references.
+const MAX_PASSES: usize = 16;
+
+/// The output of a single pass over the source.
+pub struct Pass {
+    pub symbols: HashMap<String, i64>,
+    pub origin: Option<i64>,
+    pub bytes: Vec<u8>,
+    pub listing: String,
+    pub dependencies: Vec<PathBuf>,
+}
+
+/// Assemble the source, repeating passes until every symbol is resolved.
+pub fn assemble(lines: &[Line], defines: &HashMap<String, i64>, include_dirs: &[PathBuf]) -> Result<Pass, Error> {
+    let mut symbols = defines.clone();
+
+    for _ in 0..MAX_PASSES {
+        let pass = run(lines, &symbols, defines, include_dirs, false)?;
+        if pass.symbols == symbols {
+            return run(lines, &symbols, defines, include_dirs, true);
+        }
+        symbols = pass.symbols;
+    }
+
+    Err(Error {
+        file: lines.first().map(|l| (*l.file).clone()).unwrap_or_default(),
+        line: 0,
+        message: "symbols did not settle, check for circular definitions".into(),
+    })
+}
+
+fn run(lines: &[Line], previous: &HashMap<String, i64>, defines: &HashMap<String, i64>,
+       include_dirs: &[PathBuf], strict: bool) -> Result<Pass, Error> {
+    let mut pass = Pass {
+        symbols: defines.clone(),
+        origin: None,
+        bytes: Vec::new(),
+        listing: String::new(),
+        dependencies: Vec::new(),
+    };
+    let mut pc: i64 = 0;
+    let mut scope = String::new();
+    let mut line_bytes = Vec::new();
+
+    for line in lines {
+        let (label, mnemonic, operands) = split_line(&line.text);
+        let directive = mnemonic.map(|m| m.to_ascii_lowercase());
+        let directive = directive.as_deref();
+        let operands = split_operands(operands);
+
+        if directive == Some("end") {
+            break;
+        }
+
+        // Define the label, either as the current address or a constant.
+        if let Some(label) = label {
+            if !label.starts_with('.') && directive != Some("equ") && directive != Some("=") {
+                scope = label.to_string();
+            }
+            let name = qualify(&scope, label);
+            if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '.')
+                || !name.bytes().all(crate::expr::is_symbol_char) {
+                return Err(line.error(format!("invalid label `{}`", label)));
+            }
+
+            let value = match directive {
+                Some("equ") | Some("=") => {
+                    let env = Env { symbols: previous, pc, scope: &scope, strict };
+                    let text = operands.first().cloned().unwrap_or("");
+                    eval(text, &env).map_err(|e| line.error(e))?
+                },
+                _ => pc,
+            };
+            if pass.symbols.insert(name.clone(), value).is_some() && !defines.contains_key(&name) {
+                return Err(line.error(format!("`{}` is defined more than once", name)));
+            }
+        }
+
+        let env = Env { symbols: previous, pc, scope: &scope, strict };
+        line_bytes.clear();
+
+        match directive {
+            None | Some("equ") | Some("=") => {},
+            Some("org") => {
+                let addr = single(&operands, &env).map_err(|e| line.error(e))?;
+                if pass.origin.is_none() {
+                    pass.origin = Some(addr);
+                    pc = addr;
+                } else if addr >= pc {
+                    line_bytes.resize((addr - pc) as usize, 0);
+                } else if strict {
+                    return Err(line.error("`org` cannot move backwards"));
+                }
+            },
+            Some("db") | Some("defb") | Some("byte") | Some("dm") | Some("defm") => {
+                for op in operands.iter() {
+                    if let Some(s) = parse_string(op) {
+                        line_bytes.extend_from_slice(s.as_bytes());
+                        continue;
+                    }
+                    let v = eval(op, &env).map_err(|e| line.error(e))?;
+                    if strict && !(-128..=255).contains(&v) {
+                        return Err(line.error(format!("value {} does not fit in a byte", v)));
+                    }
+                    line_bytes.push(v as u8);
+                }
+            },
+            Some("dw") | Some("defw") | Some("word") => {
+                for op in operands.iter() {
+                    let v = eval(op, &env).map_err(|e| line.error(e))?;
+                    if strict && !(-32768..=65535).contains(&v) {
+                        return Err(line.error(format!("value {} does not fit in a word", v)));
+                    }
+                    line_bytes.push(v as u8);
+                    line_bytes.push((v >> 8) as u8);
+                }
+            },
+            Some("ds") | Some("defs") | Some("block") => {
+                let (count, fill) = match operands.as_slice() {
+                    [count] => (eval(count, &env), Ok(0)),
+                    [count, fill] => (eval(count, &env), eval(fill, &env)),
+                    _ => return Err(line.error("expected a size and optional fill value")),
+                };
+                let count = count.map_err(|e| line.error(e))?;
+                let fill = fill.map_err(|e| line.error(e))?;
+                if count < 0 && strict {
+                    return Err(line.error("size cannot be negative"));
+                }
+                line_bytes.resize(count.max(0) as usize, fill as u8);
+            },
+            Some("align") => {
+                let align = single(&operands, &env).map_err(|e| line.error(e))?;
+                if align <= 0 || (align & (align - 1)) != 0 {
+                    return Err(line.error("alignment must be a power of two"));
+                }
+                let padding = (align - (pc & (align - 1))) & (align - 1);
+                line_bytes.resize(padding as usize, 0);
+            },
+            Some("incbin") => {
+                let name = operands.first().and_then(|o| parse_string(o))
+                    .ok_or_else(|| line.error("expected a quoted file name"))?;
+                let path = resolve(name, &line.file, include_dirs)
+                    .ok_or_else(|| line.error(format!("cannot find `{}`", name)))?;
+                let data = fs::read(&path).map_err(|e| line.error(e.to_string()))?;
+                line_bytes.extend_from_slice(&data);
+                pass.dependencies.push(path);
+            },
+            Some(_) => {
+                encode(mnemonic.unwrap(), &operands, &env, &mut line_bytes)
+                    .map_err(|e| line.error(e))?;
+            },
+        }
+
+        if pass.origin.is_none() && !line_bytes.is_empty() {
+            pass.origin = Some(pc);
+        }
+        if strict {
+            list(&mut pass.listing, pc, &line_bytes, line);
+        }
+
+        pass.bytes.extend_from_slice(&line_bytes);
+        pc += line_bytes.len() as i64;
+        if pc > 0x10000 {
+            return Err(line.error("program does not fit in the Z80 address space"));
+        }
+    }
+
+    Ok(pass)
+}
+
+fn single(operands: &[&str], env: &Env) -> Result<i64, String> {
+    match operands {
+        [op] => eval(op, env),
+        _ => Err("expected a single value".into()),
+    }
+}
+
+/// Append a line to the listing, wrapping long runs of bytes.
+fn list(listing: &mut String, pc: i64, bytes: &[u8], line: &Line) {
+    const BYTES_PER_LINE: usize = 4;
+    let mut chunks = bytes.chunks(BYTES_PER_LINE);
+    let first = chunks.next().unwrap_or(&[]);
+
+    let hex = |chunk: &[u8]| chunk.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ");
+    let _ = writeln!(listing, "{:04X}  {:<12} {:>5}  {}", pc, hex(first), line.number, line.text);
+
+    let mut addr = pc + first.len() as i64;
+    for chunk in chunks {
+        let _ = writeln!(listing, "{:04X}  {}", addr, hex(chunk));
+        addr += chunk.len() as i64;
+    }
+}
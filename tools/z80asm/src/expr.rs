@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+
+/// The state needed to evaluate an expression.
+pub struct Env<'a> {
+    pub symbols: &'a HashMap<String, i64>,
+    /// The address of the start of the current line, for `$`.
+    pub pc: i64,
+    /// The last global label, which local labels are relative to.
+    pub scope: &'a str,
+    /// If false, undefined symbols evaluate to 0 so that later passes can
+    /// resolve forward references.
+    pub strict: bool,
+}
+
+impl<'a> Env<'a> {
+    /// Expand a label name to its full name, resolving local labels.
+    pub fn qualify(&self, name: &str) -> String {
+        qualify(self.scope, name)
+    }
+}
+
+/// Expand a label name to its full name, resolving local labels.
+pub fn qualify(scope: &str, name: &str) -> String {
+    if name.starts_with('.') {
+        format!("{}{}", scope, name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Evaluate an expression.
+pub fn eval(text: &str, env: &Env) -> Result<i64, String> {
+    let mut parser = Parser { text: text.as_bytes(), pos: 0, env };
+    let v = parser.binary(0)?;
+    parser.skip_space();
+    if parser.pos < parser.text.len() {
+        return Err(format!("unexpected `{}` in expression", &text[parser.pos..]));
+    }
+    Ok(v)
+}
+
+/// Returns true if `c` can appear in a symbol name.
+pub fn is_symbol_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b'.'
+}
+
+/// Parse a number literal in any of the supported forms.
+///
+/// This accepts decimal, `$FF`, `0xFF`, `0FFh`, `%1010`, `0b1010` and
+/// `1010b`.
+pub fn parse_number(s: &str) -> Option<i64> {
+    let lower = s.to_ascii_lowercase();
+    let (digits, radix) = if let Some(d) = lower.strip_prefix('$') {
+        (d, 16)
+    } else if let Some(d) = lower.strip_prefix("0x") {
+        (d, 16)
+    } else if let Some(d) = lower.strip_prefix('%') {
+        (d, 2)
+    } else if let Some(d) = lower.strip_suffix('h') {
+        // This must come before `0b`, so that `0b6h` is hexadecimal.
+        (d, 16)
+    } else if let Some(d) = lower.strip_prefix("0b").filter(|d| !d.is_empty()) {
+        (d, 2)
+    } else if let Some(d) = lower.strip_suffix('b') {
+        (d, 2)
+    } else {
+        (lower.as_str(), 10)
+    };
+
+    if digits.is_empty() || !digits.starts_with(|c: char| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    i64::from_str_radix(digits, radix).ok()
+}
+
+struct Parser<'a, 'b> {
+    text: &'a [u8],
+    pos: usize,
+    env: &'a Env<'b>,
+}
+
+const BINARY_OPS: &[(&str, u8)] = &[
+    ("|", 1), ("^", 2), ("&", 3),
+    ("<<", 4), (">>", 4),
+    ("+", 5), ("-", 5),
+    ("*", 6), ("/", 6), ("%", 6),
+];
+
+impl<'a, 'b> Parser<'a, 'b> {
+    fn skip_space(&mut self) {
+        while self.pos < self.text.len() && self.text[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek_op(&mut self) -> Option<(&'static str, u8)> {
+        self.skip_space();
+        let rest = &self.text[self.pos..];
+        BINARY_OPS.iter()
+            .find(|(op, _)| rest.starts_with(op.as_bytes()))
+            .cloned()
+    }
+
+    fn binary(&mut self, min_prec: u8) -> Result<i64, String> {
+        let mut lhs = self.unary()?;
+
+        while let Some((op, prec)) = self.peek_op() {
+            if prec <= min_prec {
+                break;
+            }
+            self.pos += op.len();
+            let rhs = self.binary(prec)?;
+            lhs = match op {
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "<<" => lhs.wrapping_shl(rhs as u32),
+                ">>" => lhs.wrapping_shr(rhs as u32),
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" | "%" if rhs == 0 => {
+                    if self.env.strict {
+                        return Err("division by zero".into());
+                    }
+                    0
+                },
+                "/" => lhs / rhs,
+                _ => lhs % rhs,
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        self.skip_space();
+        match self.text.get(self.pos) {
+            Some(b'-') => {
+                self.pos += 1;
+                Ok(self.unary()?.wrapping_neg())
+            },
+            Some(b'+') => {
+                self.pos += 1;
+                self.unary()
+            },
+            Some(b'~') => {
+                self.pos += 1;
+                Ok(!self.unary()?)
+            },
+            _ => self.atom(),
+        }
+    }
+
+    fn atom(&mut self) -> Result<i64, String> {
+        self.skip_space();
+        let start = self.pos;
+        let c = match self.text.get(self.pos) {
+            Some(&c) => c,
+            None => return Err("expected a value".into()),
+        };
+
+        if c == b'(' {
+            self.pos += 1;
+            let v = self.binary(0)?;
+            self.skip_space();
+            if self.text.get(self.pos) != Some(&b')') {
+                return Err("missing `)`".into());
+            }
+            self.pos += 1;
+            return Ok(v);
+        }
+
+        if c == b'\'' {
+            return match (self.text.get(self.pos + 1), self.text.get(self.pos + 2)) {
+                (Some(&v), Some(b'\'')) => {
+                    self.pos += 3;
+                    Ok(v as i64)
+                },
+                _ => Err("invalid character literal".into()),
+            };
+        }
+
+        if c == b'$' || c == b'%' {
+            self.pos += 1;
+            let digits_start = self.pos;
+            while self.pos < self.text.len() && self.text[self.pos].is_ascii_alphanumeric() {
+                self.pos += 1;
+            }
+            if self.pos == digits_start {
+                if c == b'$' {
+                    return Ok(self.env.pc);
+                }
+                return Err("expected a value".into());
+            }
+        } else if is_symbol_char(c) {
+            while self.pos < self.text.len() && is_symbol_char(self.text[self.pos]) {
+                self.pos += 1;
+            }
+        } else {
+            return Err(format!("unexpected `{}` in expression", c as char));
+        }
+
+        let word = std::str::from_utf8(&self.text[start..self.pos]).unwrap();
+        if c.is_ascii_digit() || c == b'$' || c == b'%' {
+            return parse_number(word).ok_or_else(|| format!("invalid number `{}`", word));
+        }
+
+        let name = self.env.qualify(word);
+        match self.env.symbols.get(&name) {
+            Some(&v) => Ok(v),
+            None if self.env.strict => Err(format!("undefined symbol `{}`", name)),
+            None => Ok(0),
+        }
+    }
+}
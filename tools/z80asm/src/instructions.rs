@@ -0,0 +1,648 @@
+use crate::expr::{eval, Env};
+
+const MNEMONICS: &[&str] = &[
+    "adc", "add", "and", "bit", "call", "ccf", "cp", "cpd", "cpdr", "cpi", "cpir", "cpl",
+    "daa", "dec", "di", "djnz", "ei", "ex", "exx", "halt", "im", "in", "inc", "ind", "indr",
+    "ini", "inir", "jp", "jr", "ld", "ldd", "lddr", "ldi", "ldir", "neg", "nop", "or",
+    "otdr", "otir", "out", "outd", "outi", "pop", "push", "res", "ret", "reti", "retn", "rl",
+    "rla", "rlc", "rlca", "rld", "rr", "rra", "rrc", "rrca", "rrd", "rst", "sbc", "scf",
+    "set", "sla", "sll", "sra", "srl", "sub", "xor",
+];
+
+/// Returns true if `word` is a Z80 instruction.
+pub fn is_mnemonic(word: &str) -> bool {
+    MNEMONICS.contains(&word.to_ascii_lowercase().as_str())
+}
+
+const IX: u8 = 0xdd;
+const IY: u8 = 0xfd;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Reg {
+    A, B, C, D, E, H, L, I, R,
+    Ixh, Ixl, Iyh, Iyl,
+    AF, AFx, BC, DE, HL, SP, IX, IY,
+}
+
+impl Reg {
+    fn parse(s: &str) -> Option<Reg> {
+        Some(match s.to_ascii_lowercase().as_str() {
+            "a" => Reg::A,
+            "b" => Reg::B,
+            "c" => Reg::C,
+            "d" => Reg::D,
+            "e" => Reg::E,
+            "h" => Reg::H,
+            "l" => Reg::L,
+            "i" => Reg::I,
+            "r" => Reg::R,
+            "ixh" => Reg::Ixh,
+            "ixl" => Reg::Ixl,
+            "iyh" => Reg::Iyh,
+            "iyl" => Reg::Iyl,
+            "af" => Reg::AF,
+            "af'" => Reg::AFx,
+            "bc" => Reg::BC,
+            "de" => Reg::DE,
+            "hl" => Reg::HL,
+            "sp" => Reg::SP,
+            "ix" => Reg::IX,
+            "iy" => Reg::IY,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Reg(Reg),
+    /// A register used as a pointer, e.g. `(hl)`.
+    Ind(Reg),
+    /// An index register with a displacement, e.g. `(ix+4)`.
+    Idx(u8, i64),
+    Imm(i64),
+    /// A memory address, e.g. `(0x1000)`.
+    Mem(i64),
+}
+
+/// Returns the index of the bracket which closes the one at the start of `s`.
+fn closing_bracket(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+fn parse_operand(text: &str, env: &Env) -> Result<Operand, String> {
+    if text.is_empty() {
+        return Err("missing operand".into());
+    }
+    if let Some(r) = Reg::parse(text) {
+        return Ok(Operand::Reg(r));
+    }
+
+    if text.starts_with('(') && closing_bracket(text) == Some(text.len() - 1) {
+        let inner = text[1..text.len() - 1].trim();
+        match Reg::parse(inner) {
+            Some(Reg::IX) => return Ok(Operand::Idx(IX, 0)),
+            Some(Reg::IY) => return Ok(Operand::Idx(IY, 0)),
+            Some(r @ Reg::BC) | Some(r @ Reg::DE) | Some(r @ Reg::HL)
+            | Some(r @ Reg::SP) | Some(r @ Reg::C) => return Ok(Operand::Ind(r)),
+            Some(r) => return Err(format!("cannot use {:?} as a pointer", r)),
+            None => {},
+        }
+
+        if inner.len() > 2 {
+            let prefix = match inner[..2].to_ascii_lowercase().as_str() {
+                "ix" => Some(IX),
+                "iy" => Some(IY),
+                _ => None,
+            };
+            let rest = inner[2..].trim_start();
+            if let Some(prefix) = prefix {
+                if rest.starts_with('+') || rest.starts_with('-') {
+                    return Ok(Operand::Idx(prefix, eval(rest, env)?));
+                }
+            }
+        }
+
+        return Ok(Operand::Mem(eval(inner, env)?));
+    }
+
+    Ok(Operand::Imm(eval(text, env)?))
+}
+
+/// An 8-bit register operand, encoded in 3 bits of an opcode.
+#[derive(Clone, Copy)]
+struct R8 {
+    code: u8,
+    prefix: Option<u8>,
+    disp: Option<i64>,
+    /// True for H, L and (HL), which are changed by an index prefix.
+    hl: bool,
+}
+
+fn r8(op: Operand) -> Option<R8> {
+    let (code, prefix, disp, hl) = match op {
+        Operand::Reg(Reg::B) => (0, None, None, false),
+        Operand::Reg(Reg::C) => (1, None, None, false),
+        Operand::Reg(Reg::D) => (2, None, None, false),
+        Operand::Reg(Reg::E) => (3, None, None, false),
+        Operand::Reg(Reg::H) => (4, None, None, true),
+        Operand::Reg(Reg::L) => (5, None, None, true),
+        Operand::Ind(Reg::HL) => (6, None, None, true),
+        Operand::Reg(Reg::A) => (7, None, None, false),
+        Operand::Reg(Reg::Ixh) => (4, Some(IX), None, false),
+        Operand::Reg(Reg::Ixl) => (5, Some(IX), None, false),
+        Operand::Reg(Reg::Iyh) => (4, Some(IY), None, false),
+        Operand::Reg(Reg::Iyl) => (5, Some(IY), None, false),
+        Operand::Idx(p, d) => (6, Some(p), Some(d), false),
+        _ => return None,
+    };
+    Some(R8 { code, prefix, disp, hl })
+}
+
+/// Check that two 8-bit operands can be used in the same instruction.
+fn combine(a: R8, b: R8) -> Result<(Option<u8>, Option<i64>), String> {
+    let invalid = Err("invalid combination of operands".to_string());
+    if let (Some(pa), Some(pb)) = (a.prefix, b.prefix) {
+        if pa != pb || a.disp.is_some() || b.disp.is_some() {
+            return invalid;
+        }
+    }
+    if (a.prefix.is_some() && a.disp.is_none() && b.hl)
+        || (b.prefix.is_some() && b.disp.is_none() && a.hl) {
+        return invalid;
+    }
+    Ok((a.prefix.or(b.prefix), a.disp.or(b.disp)))
+}
+
+/// A 16-bit register pair, encoded in 2 bits of an opcode.
+fn rr(op: Operand, alt: Reg) -> Option<(Option<u8>, u8)> {
+    match op {
+        Operand::Reg(Reg::BC) => Some((None, 0)),
+        Operand::Reg(Reg::DE) => Some((None, 1)),
+        Operand::Reg(Reg::HL) => Some((None, 2)),
+        Operand::Reg(Reg::IX) => Some((Some(IX), 2)),
+        Operand::Reg(Reg::IY) => Some((Some(IY), 2)),
+        Operand::Reg(r) if r == alt => Some((None, 3)),
+        _ => None,
+    }
+}
+
+fn index_prefix(op: Operand) -> Option<Option<u8>> {
+    match op {
+        Operand::Reg(Reg::HL) => Some(None),
+        Operand::Reg(Reg::IX) => Some(Some(IX)),
+        Operand::Reg(Reg::IY) => Some(Some(IY)),
+        _ => None,
+    }
+}
+
+fn condition(s: &str) -> Option<u8> {
+    Some(match s.to_ascii_lowercase().as_str() {
+        "nz" => 0,
+        "z" => 1,
+        "nc" => 2,
+        "c" => 3,
+        "po" => 4,
+        "pe" => 5,
+        "p" => 6,
+        "m" => 7,
+        _ => return None,
+    })
+}
+
+struct Out<'a, 'b> {
+    bytes: &'a mut Vec<u8>,
+    env: &'a Env<'b>,
+}
+
+impl<'a, 'b> Out<'a, 'b> {
+    fn push(&mut self, b: u8) {
+        self.bytes.push(b);
+    }
+
+    fn prefix(&mut self, p: Option<u8>) {
+        if let Some(p) = p {
+            self.push(p);
+        }
+    }
+
+    fn byte(&mut self, v: i64) -> Result<(), String> {
+        if self.env.strict && !(-128..=255).contains(&v) {
+            return Err(format!("value {} does not fit in a byte", v));
+        }
+        self.push(v as u8);
+        Ok(())
+    }
+
+    fn disp(&mut self, v: i64) -> Result<(), String> {
+        if self.env.strict && !(-128..=127).contains(&v) {
+            return Err(format!("displacement {} is out of range", v));
+        }
+        self.push(v as u8);
+        Ok(())
+    }
+
+    fn word(&mut self, v: i64) -> Result<(), String> {
+        if self.env.strict && !(-32768..=65535).contains(&v) {
+            return Err(format!("value {} does not fit in a word", v));
+        }
+        self.push(v as u8);
+        self.push((v >> 8) as u8);
+        Ok(())
+    }
+
+    /// Emit an opcode with an 8-bit register operand.
+    fn r8(&mut self, prefix: Option<u8>, opcode: u8, disp: Option<i64>) -> Result<(), String> {
+        self.prefix(prefix);
+        self.push(opcode);
+        if let Some(d) = disp {
+            self.disp(d)?;
+        }
+        Ok(())
+    }
+}
+
+/// Assemble a single instruction, appending the machine code to `bytes`.
+pub fn encode(mnemonic: &str, operands: &[&str], env: &Env, bytes: &mut Vec<u8>) -> Result<(), String> {
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    let mut out = Out { bytes, env };
+
+    // Instructions with a condition code need to check for it before
+    // parsing the operands, since `c` is also a register.
+    match mnemonic.as_str() {
+        "jp" | "jr" | "call" | "ret" => {
+            let cc = operands.first().and_then(|c| condition(c));
+            let cc = if mnemonic == "ret" || operands.len() == 2 { cc } else { None };
+            let target = match (cc.is_some(), operands.len()) {
+                (true, 2) => Some(parse_operand(operands[1], env)?),
+                (false, 1) => Some(parse_operand(operands[0], env)?),
+                (_, 0) | (true, 1) => None,
+                _ => return Err("wrong number of operands".into()),
+            };
+            return encode_flow(&mnemonic, cc, target, &mut out);
+        },
+        _ => {},
+    }
+
+    let ops = operands.iter()
+        .map(|o| parse_operand(o, env))
+        .collect::<Result<Vec<_>, _>>()?;
+    let simple: Option<&[u8]> = match mnemonic.as_str() {
+        "nop" => Some(&[0x00]),
+        "halt" => Some(&[0x76]),
+        "di" => Some(&[0xf3]),
+        "ei" => Some(&[0xfb]),
+        "exx" => Some(&[0xd9]),
+        "daa" => Some(&[0x27]),
+        "cpl" => Some(&[0x2f]),
+        "scf" => Some(&[0x37]),
+        "ccf" => Some(&[0x3f]),
+        "rla" => Some(&[0x17]),
+        "rra" => Some(&[0x1f]),
+        "rlca" => Some(&[0x07]),
+        "rrca" => Some(&[0x0f]),
+        "neg" => Some(&[0xed, 0x44]),
+        "retn" => Some(&[0xed, 0x45]),
+        "reti" => Some(&[0xed, 0x4d]),
+        "rrd" => Some(&[0xed, 0x67]),
+        "rld" => Some(&[0xed, 0x6f]),
+        "ldi" => Some(&[0xed, 0xa0]),
+        "cpi" => Some(&[0xed, 0xa1]),
+        "ini" => Some(&[0xed, 0xa2]),
+        "outi" => Some(&[0xed, 0xa3]),
+        "ldd" => Some(&[0xed, 0xa8]),
+        "cpd" => Some(&[0xed, 0xa9]),
+        "ind" => Some(&[0xed, 0xaa]),
+        "outd" => Some(&[0xed, 0xab]),
+        "ldir" => Some(&[0xed, 0xb0]),
+        "cpir" => Some(&[0xed, 0xb1]),
+        "inir" => Some(&[0xed, 0xb2]),
+        "otir" => Some(&[0xed, 0xb3]),
+        "lddr" => Some(&[0xed, 0xb8]),
+        "cpdr" => Some(&[0xed, 0xb9]),
+        "indr" => Some(&[0xed, 0xba]),
+        "otdr" => Some(&[0xed, 0xbb]),
+        _ => None,
+    };
+    if let Some(code) = simple {
+        if !ops.is_empty() {
+            return invalid(&mnemonic);
+        }
+        code.iter().for_each(|&b| out.push(b));
+        return Ok(());
+    }
+
+    match (mnemonic.as_str(), ops.as_slice()) {
+        ("ld", &[dst, src]) => encode_ld(dst, src, &mut out),
+
+        ("push", &[op]) | ("pop", &[op]) => {
+            let (prefix, code) = rr(op, Reg::AF).map_or_else(|| invalid(&mnemonic), Ok)?;
+            out.prefix(prefix);
+            out.push(if mnemonic == "push" { 0xc5 } else { 0xc1 } | (code << 4));
+            Ok(())
+        },
+
+        ("ex", &[Operand::Reg(Reg::DE), Operand::Reg(Reg::HL)]) => {
+            out.push(0xeb);
+            Ok(())
+        },
+        ("ex", &[Operand::Reg(Reg::AF), Operand::Reg(Reg::AFx)]) => {
+            out.push(0x08);
+            Ok(())
+        },
+        ("ex", &[Operand::Ind(Reg::SP), hl]) => {
+            let prefix = index_prefix(hl).map_or_else(|| invalid(&mnemonic), Ok)?;
+            out.prefix(prefix);
+            out.push(0xe3);
+            Ok(())
+        },
+
+        ("add", &[dst @ Operand::Reg(Reg::HL), src])
+        | ("add", &[dst @ Operand::Reg(Reg::IX), src])
+        | ("add", &[dst @ Operand::Reg(Reg::IY), src]) => {
+            let prefix = index_prefix(dst).unwrap();
+            let (src_prefix, code) = rr(src, Reg::SP).map_or_else(|| invalid(&mnemonic), Ok)?;
+            if (code == 2 || src_prefix.is_some()) && src_prefix != prefix {
+                return invalid(&mnemonic);
+            }
+            out.prefix(prefix);
+            out.push(0x09 | (code << 4));
+            Ok(())
+        },
+        ("adc", &[Operand::Reg(Reg::HL), src]) | ("sbc", &[Operand::Reg(Reg::HL), src]) => {
+            match rr(src, Reg::SP) {
+                Some((None, code)) => {
+                    out.push(0xed);
+                    out.push(if mnemonic == "adc" { 0x4a } else { 0x42 } | (code << 4));
+                    Ok(())
+                },
+                _ => invalid(&mnemonic),
+            }
+        },
+
+        ("add", _) | ("adc", _) | ("sub", _) | ("sbc", _)
+        | ("and", _) | ("xor", _) | ("or", _) | ("cp", _) => {
+            let op = match *ops.as_slice() {
+                [Operand::Reg(Reg::A), op] => op,
+                [op] => op,
+                _ => return invalid(&mnemonic),
+            };
+            let alu = match mnemonic.as_str() {
+                "add" => 0,
+                "adc" => 1,
+                "sub" => 2,
+                "sbc" => 3,
+                "and" => 4,
+                "xor" => 5,
+                "or" => 6,
+                _ => 7,
+            };
+            if let Operand::Imm(v) = op {
+                out.push(0xc6 | (alu << 3));
+                out.byte(v)
+            } else if let Some(r) = r8(op) {
+                out.r8(r.prefix, 0x80 | (alu << 3) | r.code, r.disp)
+            } else {
+                invalid(&mnemonic)
+            }
+        },
+
+        ("inc", &[op]) | ("dec", &[op]) => {
+            let inc = mnemonic == "inc";
+            if let Some(r) = r8(op) {
+                out.r8(r.prefix, if inc { 0x04 } else { 0x05 } | (r.code << 3), r.disp)
+            } else if let Some((prefix, code)) = rr(op, Reg::SP) {
+                out.prefix(prefix);
+                out.push(if inc { 0x03 } else { 0x0b } | (code << 4));
+                Ok(())
+            } else {
+                invalid(&mnemonic)
+            }
+        },
+
+        ("rlc", _) | ("rrc", _) | ("rl", _) | ("rr", _)
+        | ("sla", _) | ("sra", _) | ("sll", _) | ("srl", _)
+        | ("bit", _) | ("res", _) | ("set", _) => {
+            let (base, bit, op) = match (mnemonic.as_str(), ops.as_slice()) {
+                ("bit", &[Operand::Imm(b), op]) => (0x40, b, op),
+                ("res", &[Operand::Imm(b), op]) => (0x80, b, op),
+                ("set", &[Operand::Imm(b), op]) => (0xc0, b, op),
+                ("rlc", &[op]) => (0x00, 0, op),
+                ("rrc", &[op]) => (0x08, 0, op),
+                ("rl", &[op]) => (0x10, 0, op),
+                ("rr", &[op]) => (0x18, 0, op),
+                ("sla", &[op]) => (0x20, 0, op),
+                ("sra", &[op]) => (0x28, 0, op),
+                ("sll", &[op]) => (0x30, 0, op),
+                ("srl", &[op]) => (0x38, 0, op),
+                _ => return invalid(&mnemonic),
+            };
+            if !(0..8).contains(&bit) {
+                return Err(format!("bit {} is out of range", bit));
+            }
+            let r = match r8(op) {
+                Some(r) if r.prefix.is_none() || r.disp.is_some() => r,
+                _ => return invalid(&mnemonic),
+            };
+            let opcode = base | ((bit as u8) << 3) | r.code;
+            out.prefix(r.prefix);
+            out.push(0xcb);
+            if let Some(d) = r.disp {
+                out.disp(d)?;
+            }
+            out.push(opcode);
+            Ok(())
+        },
+
+        ("djnz", &[Operand::Imm(target)]) => {
+            out.push(0x10);
+            relative(target, &mut out)
+        },
+
+        ("rst", &[Operand::Imm(v)]) => {
+            if v & !0x38 != 0 {
+                return Err(format!("invalid restart address {:#x}", v));
+            }
+            out.push(0xc7 | v as u8);
+            Ok(())
+        },
+
+        ("im", &[Operand::Imm(v)]) => {
+            let code = match v {
+                0 => 0x46,
+                1 => 0x56,
+                2 => 0x5e,
+                _ => return Err(format!("invalid interrupt mode {}", v)),
+            };
+            out.push(0xed);
+            out.push(code);
+            Ok(())
+        },
+
+        ("in", &[Operand::Reg(Reg::A), Operand::Mem(port)]) => {
+            out.push(0xdb);
+            out.byte(port)
+        },
+        ("in", &[Operand::Ind(Reg::C)]) => {
+            out.push(0xed);
+            out.push(0x70);
+            Ok(())
+        },
+        ("in", &[dst, Operand::Ind(Reg::C)]) => match r8(dst) {
+            Some(r) if r.prefix.is_none() && r.code != 6 => {
+                out.push(0xed);
+                out.push(0x40 | (r.code << 3));
+                Ok(())
+            },
+            _ => invalid(&mnemonic),
+        },
+        ("out", &[Operand::Mem(port), Operand::Reg(Reg::A)]) => {
+            out.push(0xd3);
+            out.byte(port)
+        },
+        ("out", &[Operand::Ind(Reg::C), Operand::Imm(0)]) => {
+            out.push(0xed);
+            out.push(0x71);
+            Ok(())
+        },
+        ("out", &[Operand::Ind(Reg::C), src]) => match r8(src) {
+            Some(r) if r.prefix.is_none() && r.code != 6 => {
+                out.push(0xed);
+                out.push(0x41 | (r.code << 3));
+                Ok(())
+            },
+            _ => invalid(&mnemonic),
+        },
+
+        _ if is_mnemonic(&mnemonic) => invalid(&mnemonic),
+        _ => Err(format!("unknown instruction `{}`", mnemonic)),
+    }
+}
+
+fn invalid<T>(mnemonic: &str) -> Result<T, String> {
+    Err(format!("invalid operands for `{}`", mnemonic))
+}
+
+fn relative(target: i64, out: &mut Out) -> Result<(), String> {
+    let offset = target - (out.env.pc + 2);
+    if out.env.strict && !(-128..=127).contains(&offset) {
+        return Err(format!("relative jump of {} bytes is out of range", offset));
+    }
+    out.push(offset as u8);
+    Ok(())
+}
+
+fn encode_flow(mnemonic: &str, cc: Option<u8>, target: Option<Operand>, out: &mut Out) -> Result<(), String> {
+    match (mnemonic, cc, target) {
+        ("jp", None, Some(Operand::Imm(v))) => {
+            out.push(0xc3);
+            out.word(v)
+        },
+        ("jp", Some(cc), Some(Operand::Imm(v))) => {
+            out.push(0xc2 | (cc << 3));
+            out.word(v)
+        },
+        ("jp", None, Some(Operand::Ind(Reg::HL))) => {
+            out.push(0xe9);
+            Ok(())
+        },
+        ("jp", None, Some(Operand::Idx(prefix, 0))) => {
+            out.push(prefix);
+            out.push(0xe9);
+            Ok(())
+        },
+        ("jr", None, Some(Operand::Imm(v))) => {
+            out.push(0x18);
+            relative(v, out)
+        },
+        ("jr", Some(cc), Some(Operand::Imm(v))) if cc < 4 => {
+            out.push(0x20 | (cc << 3));
+            relative(v, out)
+        },
+        ("call", None, Some(Operand::Imm(v))) => {
+            out.push(0xcd);
+            out.word(v)
+        },
+        ("call", Some(cc), Some(Operand::Imm(v))) => {
+            out.push(0xc4 | (cc << 3));
+            out.word(v)
+        },
+        ("ret", None, None) => {
+            out.push(0xc9);
+            Ok(())
+        },
+        ("ret", Some(cc), None) => {
+            out.push(0xc0 | (cc << 3));
+            Ok(())
+        },
+        _ => invalid(mnemonic),
+    }
+}
+
+fn encode_ld(dst: Operand, src: Operand, out: &mut Out) -> Result<(), String> {
+    match (dst, src) {
+        (Operand::Reg(Reg::A), Operand::Ind(Reg::BC)) => out.push(0x0a),
+        (Operand::Reg(Reg::A), Operand::Ind(Reg::DE)) => out.push(0x1a),
+        (Operand::Ind(Reg::BC), Operand::Reg(Reg::A)) => out.push(0x02),
+        (Operand::Ind(Reg::DE), Operand::Reg(Reg::A)) => out.push(0x12),
+        (Operand::Reg(Reg::A), Operand::Mem(addr)) => {
+            out.push(0x3a);
+            out.word(addr)?;
+        },
+        (Operand::Mem(addr), Operand::Reg(Reg::A)) => {
+            out.push(0x32);
+            out.word(addr)?;
+        },
+        (Operand::Reg(Reg::A), Operand::Reg(Reg::I)) => out.bytes.extend_from_slice(&[0xed, 0x57]),
+        (Operand::Reg(Reg::A), Operand::Reg(Reg::R)) => out.bytes.extend_from_slice(&[0xed, 0x5f]),
+        (Operand::Reg(Reg::I), Operand::Reg(Reg::A)) => out.bytes.extend_from_slice(&[0xed, 0x47]),
+        (Operand::Reg(Reg::R), Operand::Reg(Reg::A)) => out.bytes.extend_from_slice(&[0xed, 0x4f]),
+        (Operand::Reg(Reg::SP), src) if index_prefix(src).is_some() => {
+            out.prefix(index_prefix(src).unwrap());
+            out.push(0xf9);
+        },
+        (dst, Operand::Imm(v)) if r8(dst).is_some() => {
+            let r = r8(dst).unwrap();
+            out.r8(r.prefix, 0x06 | (r.code << 3), r.disp)?;
+            out.byte(v)?;
+        },
+        (dst, Operand::Imm(v)) if rr(dst, Reg::SP).is_some() => {
+            let (prefix, code) = rr(dst, Reg::SP).unwrap();
+            out.prefix(prefix);
+            out.push(0x01 | (code << 4));
+            out.word(v)?;
+        },
+        (dst, Operand::Mem(addr)) if rr(dst, Reg::SP).is_some() => {
+            match rr(dst, Reg::SP).unwrap() {
+                (prefix, 2) => {
+                    out.prefix(prefix);
+                    out.push(0x2a);
+                },
+                (_, code) => {
+                    out.push(0xed);
+                    out.push(0x4b | (code << 4));
+                },
+            }
+            out.word(addr)?;
+        },
+        (Operand::Mem(addr), src) if rr(src, Reg::SP).is_some() => {
+            match rr(src, Reg::SP).unwrap() {
+                (prefix, 2) => {
+                    out.prefix(prefix);
+                    out.push(0x22);
+                },
+                (_, code) => {
+                    out.push(0xed);
+                    out.push(0x43 | (code << 4));
+                },
+            }
+            out.word(addr)?;
+        },
+        (dst, src) => {
+            let (d, s) = match (r8(dst), r8(src)) {
+                (Some(d), Some(s)) => (d, s),
+                _ => return invalid("ld"),
+            };
+            if d.code == 6 && s.code == 6 {
+                return invalid("ld");
+            }
+            let (prefix, disp) = combine(d, s).or_else(|_| invalid("ld"))?;
+            out.r8(prefix, 0x40 | (d.code << 3) | s.code, disp)?;
+        },
+    }
+
+    Ok(())
+}
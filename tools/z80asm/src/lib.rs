@@ -0,0 +1,201 @@
+//! A small Z80 assembler for building Mega Drive sound drivers.
+//!
+//! The assembler supports the full documented Z80 instruction set (plus the
+//! `ixh`/`ixl`/`iyh`/`iyl` halves), labels (including `.local` labels scoped
+//! to the last global label), expressions and the following directives:
+//!
+//! - `org`, `align`, `end`
+//! - `equ` / `=`
+//! - `db`, `dw`, `ds` (and the `def*` spellings)
+//! - `include "file.s"`, `incbin "file.bin"`
+//!
+//! It can be used from a build script with [`build`], or directly with an
+//! [`Assembler`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+mod asm;
+mod expr;
+mod instructions;
+mod source;
+
+/// An error encountered while assembling.
+#[derive(Debug, Clone)]
+pub struct Error {
+    /// The file which contains the error.
+    pub file: PathBuf,
+    /// The 1-based line number of the error, or 0 if it has no line.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+        } else {
+            write!(f, "{}: {}", self.file.display(), self.message)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A configurable assembler.
+#[derive(Debug, Clone, Default)]
+pub struct Assembler {
+    include_dirs: Vec<PathBuf>,
+    defines: HashMap<String, i64>,
+}
+
+impl Assembler {
+    /// Create a new assembler with the default settings.
+    pub fn new() -> Assembler {
+        Default::default()
+    }
+
+    /// Add a directory to search for `include` and `incbin` files.
+    ///
+    /// Files are always looked for relative to the including file first.
+    pub fn include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.include_dirs.push(dir.into());
+        self
+    }
+
+    /// Define a symbol before assembling.
+    pub fn define(mut self, name: impl Into<String>, value: i64) -> Self {
+        self.defines.insert(name.into(), value);
+        self
+    }
+
+    /// Assemble a source file.
+    pub fn assemble_file(&self, path: impl AsRef<Path>) -> Result<Program, Error> {
+        let path = path.as_ref();
+        let mut lines = Vec::new();
+        source::load(path, &self.include_dirs, 0, &mut lines)?;
+        self.assemble_lines(&lines)
+    }
+
+    /// Assemble source text.
+    ///
+    /// `path` is used for error messages and for resolving relative includes.
+    pub fn assemble_str(&self, path: impl AsRef<Path>, text: &str) -> Result<Program, Error> {
+        let mut lines = Vec::new();
+        source::load_str(path.as_ref(), text, &self.include_dirs, 0, &mut lines)?;
+        self.assemble_lines(&lines)
+    }
+
+    fn assemble_lines(&self, lines: &[source::Line]) -> Result<Program, Error> {
+        let pass = asm::assemble(lines, &self.defines, &self.include_dirs)?;
+
+        let mut dependencies: Vec<PathBuf> = Vec::new();
+        for path in lines.iter().map(|l| &*l.file).chain(pass.dependencies.iter()) {
+            if !dependencies.contains(path) {
+                dependencies.push(path.clone());
+            }
+        }
+
+        Ok(Program {
+            origin: pass.origin.unwrap_or(0) as u16,
+            bytes: pass.bytes,
+            symbols: pass.symbols.into_iter().collect(),
+            listing: pass.listing,
+            dependencies,
+        })
+    }
+}
+
+/// An assembled Z80 program.
+#[derive(Debug, Clone)]
+pub struct Program {
+    origin: u16,
+    bytes: Vec<u8>,
+    symbols: BTreeMap<String, i64>,
+    listing: String,
+    dependencies: Vec<PathBuf>,
+}
+
+impl Program {
+    /// The address of the first byte of the program.
+    pub fn origin(&self) -> u16 {
+        self.origin
+    }
+
+    /// The assembled machine code.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Take the assembled machine code.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// All of the symbols defined by the program.
+    pub fn symbols(&self) -> &BTreeMap<String, i64> {
+        &self.symbols
+    }
+
+    /// Look up the value of a symbol.
+    pub fn symbol(&self, name: &str) -> Option<i64> {
+        self.symbols.get(name).cloned()
+    }
+
+    /// The listing, showing the address and bytes of each source line.
+    pub fn listing(&self) -> &str {
+        &self.listing
+    }
+
+    /// Every file which was read to assemble this program.
+    pub fn dependencies(&self) -> &[PathBuf] {
+        &self.dependencies
+    }
+
+    /// Format the symbols as `NAME = $VALUE` lines.
+    pub fn symbol_file(&self) -> String {
+        let mut out = String::new();
+        for (name, value) in self.symbols.iter() {
+            let _ = writeln!(out, "{} = ${:04X}", name, value);
+        }
+        out
+    }
+
+    /// Write `<stem>.bin`, `<stem>.lst` and `<stem>.sym` into `dir`.
+    ///
+    /// Returns the path of the binary.
+    pub fn write(&self, dir: impl AsRef<Path>, stem: &str) -> std::io::Result<PathBuf> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let bin = dir.join(format!("{}.bin", stem));
+        fs::write(&bin, &self.bytes)?;
+        fs::write(dir.join(format!("{}.lst", stem)), &self.listing)?;
+        fs::write(dir.join(format!("{}.sym", stem)), self.symbol_file())?;
+        Ok(bin)
+    }
+}
+
+/// Assemble a source file from a build script.
+///
+/// The output is written into `OUT_DIR`, and cargo is told to rerun the
+/// build script if any of the source files change. The binary is written to
+/// `<OUT_DIR>/<stem>.bin`, for use with `include_bytes!`.
+pub fn build(path: impl AsRef<Path>) -> Program {
+    let path = path.as_ref();
+    let out_dir = env::var_os("OUT_DIR").expect("OUT_DIR is not set, is this a build script?");
+    let stem = path.file_stem()
+        .and_then(|s| s.to_str())
+        .expect("source file has no name");
+
+    let program = Assembler::new()
+        .assemble_file(path)
+        .unwrap_or_else(|e| panic!("failed to assemble: {}", e));
+    for dependency in program.dependencies() {
+        println!("cargo:rerun-if-changed={}", dependency.display());
+    }
+    program.write(out_dir, stem).expect("failed to write assembled output");
+    program
+}
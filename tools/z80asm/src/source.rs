@@ -0,0 +1,177 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::Error;
+
+/// A single line of source, after includes have been expanded.
+pub struct Line {
+    pub file: Rc<PathBuf>,
+    pub number: usize,
+    pub text: String,
+}
+
+impl Line {
+    /// Create an error pointing at this line.
+    pub fn error(&self, message: impl Into<String>) -> Error {
+        Error {
+            file: (*self.file).clone(),
+            line: self.number,
+            message: message.into(),
+        }
+    }
+}
+
+/// Split a line into its label, mnemonic and operands, ignoring comments.
+pub fn split_line(text: &str) -> (Option<&str>, Option<&str>, &str) {
+    let text = strip_comment(text);
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return (None, None, "");
+    }
+
+    let mut rest = trimmed;
+    let mut label = None;
+
+    // Labels either end in a colon or start in the first column.
+    let first_len = trimmed.find(|c: char| c.is_whitespace() || c == ':').unwrap_or(trimmed.len());
+    if trimmed[first_len..].starts_with(':') {
+        label = Some(&trimmed[..first_len]);
+        rest = trimmed[first_len + 1..].trim_start();
+    } else if !text.starts_with(char::is_whitespace) {
+        let word = &trimmed[..first_len];
+        if !is_keyword(word) {
+            label = Some(word);
+            rest = trimmed[first_len..].trim_start();
+        }
+    }
+
+    if rest.is_empty() {
+        return (label, None, "");
+    }
+
+    let mnemonic_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let mnemonic = &rest[..mnemonic_len];
+    let operands = rest[mnemonic_len..].trim();
+
+    // Indented constants: `  NAME equ 5` or `  NAME = 5`.
+    if label.is_none() {
+        let next_len = operands.find(char::is_whitespace).unwrap_or(operands.len());
+        let next = &operands[..next_len];
+        if next == "=" || next.eq_ignore_ascii_case("equ") {
+            return (Some(mnemonic), Some(next), operands[next_len..].trim());
+        }
+    }
+
+    (label, Some(mnemonic), operands)
+}
+
+fn is_keyword(word: &str) -> bool {
+    crate::asm::DIRECTIVES.contains(&word.to_ascii_lowercase().as_str())
+        || crate::instructions::is_mnemonic(word)
+}
+
+/// Remove a trailing `;` comment, respecting quotes.
+fn strip_comment(text: &str) -> &str {
+    let mut quote = None;
+    let bytes = text.as_bytes();
+    for (i, &c) in bytes.iter().enumerate() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            // `af'` is a register, not the start of a character literal.
+            None if c == b'\'' && i > 0 && bytes[i - 1].is_ascii_alphabetic() => {},
+            None if c == b'"' || c == b'\'' => quote = Some(c),
+            None if c == b';' => return &text[..i],
+            None => {},
+        }
+    }
+    text
+}
+
+/// Split operands on commas which are not inside brackets or quotes.
+pub fn split_operands(text: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    if text.trim().is_empty() {
+        return result;
+    }
+
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (i, &c) in bytes.iter().enumerate() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None if c == b'\'' && i > 0 && bytes[i - 1].is_ascii_alphabetic() => {},
+            None if c == b'"' || c == b'\'' => quote = Some(c),
+            None if c == b'(' => depth += 1,
+            None if c == b')' => depth -= 1,
+            None if c == b',' && depth == 0 => {
+                result.push(text[start..i].trim());
+                start = i + 1;
+            },
+            None => {},
+        }
+    }
+    result.push(text[start..].trim());
+    result
+}
+
+/// Parse a quoted string operand.
+pub fn parse_string(text: &str) -> Option<&str> {
+    let text = text.trim();
+    if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        Some(&text[1..text.len() - 1])
+    } else {
+        None
+    }
+}
+
+/// Find a file named by an `include` or `incbin` directive.
+pub fn resolve(name: &str, from: &Path, include_dirs: &[PathBuf]) -> Option<PathBuf> {
+    let relative = from.parent().map(|p| p.join(name));
+    relative.into_iter()
+        .chain(include_dirs.iter().map(|d| d.join(name)))
+        .find(|p| p.is_file())
+}
+
+/// Load a source file, recursively expanding `include` directives.
+pub fn load(path: &Path, include_dirs: &[PathBuf], depth: usize, lines: &mut Vec<Line>) -> Result<(), Error> {
+    let text = fs::read_to_string(path).map_err(|e| Error {
+        file: path.to_path_buf(),
+        line: 0,
+        message: e.to_string(),
+    })?;
+    load_str(path, &text, include_dirs, depth, lines)
+}
+
+/// Load source text, recursively expanding `include` directives.
+pub fn load_str(path: &Path, text: &str, include_dirs: &[PathBuf], depth: usize, lines: &mut Vec<Line>) -> Result<(), Error> {
+    let file = Rc::new(path.to_path_buf());
+
+    for (idx, text) in text.lines().enumerate() {
+        let line = Line { file: file.clone(), number: idx + 1, text: text.to_string() };
+        let (label, mnemonic, operands) = split_line(text);
+
+        if mnemonic.map(str::to_ascii_lowercase).as_deref() == Some("include") {
+            if label.is_some() {
+                return Err(line.error("labels are not allowed on `include`"));
+            }
+            if depth >= 16 {
+                return Err(line.error("includes are nested too deeply"));
+            }
+
+            let name = parse_string(operands)
+                .ok_or_else(|| line.error("expected a quoted file name"))?;
+            let include = resolve(name, path, include_dirs)
+                .ok_or_else(|| line.error(format!("cannot find include `{}`", name)))?;
+            load(&include, include_dirs, depth + 1, lines)?;
+        } else {
+            lines.push(line);
+        }
+    }
+
+    Ok(())
+}
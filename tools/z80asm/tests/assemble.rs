@@ -0,0 +1,265 @@
+use std::path::PathBuf;
+
+use z80asm::{Assembler, Error};
+
+fn data(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data").join(name)
+}
+
+fn assemble(source: &str) -> Result<Vec<u8>, Error> {
+    Assembler::new().assemble_str("test.s", source).map(|p| p.into_bytes())
+}
+
+/// Assemble source which is expected to be valid.
+fn asm(source: &str) -> Vec<u8> {
+    assemble(source).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Assemble source which is expected to fail, returning the message.
+fn error(source: &str) -> String {
+    match assemble(source) {
+        Ok(bytes) => panic!("assembled to {:02x?}", bytes),
+        Err(e) => e.message,
+    }
+}
+
+#[test]
+fn simple() {
+    assert_eq!(asm(" nop\n halt\n di\n ei\n exx"), [0x00, 0x76, 0xf3, 0xfb, 0xd9]);
+    assert_eq!(asm(" ldir\n neg\n reti\n rld"), [0xed, 0xb0, 0xed, 0x44, 0xed, 0x4d, 0xed, 0x6f]);
+    assert_eq!(error(" nop a"), "invalid operands for `nop`");
+    assert_eq!(error(" frob a"), "unknown instruction `frob`");
+}
+
+#[test]
+fn ld_8_bit() {
+    assert_eq!(asm(" ld a, b"), [0x78]);
+    assert_eq!(asm(" ld h, (hl)"), [0x66]);
+    assert_eq!(asm(" ld (hl), e"), [0x73]);
+    assert_eq!(asm(" ld c, 0x12"), [0x0e, 0x12]);
+    assert_eq!(asm(" ld (hl), 0x12"), [0x36, 0x12]);
+    assert_eq!(asm(" ld a, (bc)"), [0x0a]);
+    assert_eq!(asm(" ld a, (de)"), [0x1a]);
+    assert_eq!(asm(" ld (bc), a"), [0x02]);
+    assert_eq!(asm(" ld (de), a"), [0x12]);
+    assert_eq!(asm(" ld a, (0x1234)"), [0x3a, 0x34, 0x12]);
+    assert_eq!(asm(" ld (0x1234), a"), [0x32, 0x34, 0x12]);
+    assert_eq!(asm(" ld a, i\n ld a, r\n ld i, a\n ld r, a"),
+               [0xed, 0x57, 0xed, 0x5f, 0xed, 0x47, 0xed, 0x4f]);
+    assert_eq!(error(" ld (hl), (hl)"), "invalid operands for `ld`");
+    assert_eq!(error(" ld b, (bc)"), "invalid operands for `ld`");
+    assert_eq!(error(" ld a, 0x100"), "value 256 does not fit in a byte");
+}
+
+#[test]
+fn ld_16_bit() {
+    assert_eq!(asm(" ld bc, 0x1234"), [0x01, 0x34, 0x12]);
+    assert_eq!(asm(" ld sp, 0x2000"), [0x31, 0x00, 0x20]);
+    assert_eq!(asm(" ld hl, (0x1234)"), [0x2a, 0x34, 0x12]);
+    assert_eq!(asm(" ld (0x1234), hl"), [0x22, 0x34, 0x12]);
+    assert_eq!(asm(" ld de, (0x1234)"), [0xed, 0x5b, 0x34, 0x12]);
+    assert_eq!(asm(" ld (0x1234), sp"), [0xed, 0x73, 0x34, 0x12]);
+    assert_eq!(asm(" ld sp, hl\n ld sp, ix"), [0xf9, 0xdd, 0xf9]);
+    assert_eq!(asm(" ld ix, 0x1234"), [0xdd, 0x21, 0x34, 0x12]);
+    assert_eq!(asm(" ld iy, (0x1234)"), [0xfd, 0x2a, 0x34, 0x12]);
+    assert_eq!(asm(" ld (0x1234), ix"), [0xdd, 0x22, 0x34, 0x12]);
+    assert_eq!(error(" ld bc, de"), "invalid operands for `ld`");
+    assert_eq!(error(" ld hl, 0x10000"), "value 65536 does not fit in a word");
+}
+
+#[test]
+fn index_registers() {
+    assert_eq!(asm(" ld a, (ix+5)"), [0xdd, 0x7e, 0x05]);
+    assert_eq!(asm(" ld (iy-2), b"), [0xfd, 0x70, 0xfe]);
+    assert_eq!(asm(" ld (ix), 0x12"), [0xdd, 0x36, 0x00, 0x12]);
+    assert_eq!(asm(" ld (ix+1), h"), [0xdd, 0x74, 0x01]);
+    assert_eq!(asm(" ld ixh, a\n ld a, iyl"), [0xdd, 0x67, 0xfd, 0x7d]);
+    assert_eq!(asm(" ld ixl, 3"), [0xdd, 0x2e, 0x03]);
+    assert_eq!(asm(" add a, (ix+3)"), [0xdd, 0x86, 0x03]);
+    assert_eq!(asm(" inc (iy+0x10)"), [0xfd, 0x34, 0x10]);
+    assert_eq!(asm(" bit 3, (ix+4)"), [0xdd, 0xcb, 0x04, 0x5e]);
+    assert_eq!(asm(" rlc (iy-1)"), [0xfd, 0xcb, 0xff, 0x06]);
+    assert_eq!(asm(" add ix, bc\n add iy, iy"), [0xdd, 0x09, 0xfd, 0x29]);
+    assert_eq!(asm(" push ix\n pop iy"), [0xdd, 0xe5, 0xfd, 0xe1]);
+    assert_eq!(asm(" jp (ix)\n ex (sp), iy"), [0xdd, 0xe9, 0xfd, 0xe3]);
+
+    assert_eq!(error(" ld a, (ix+128)"), "displacement 128 is out of range");
+    assert_eq!(error(" ld a, (ix-129)"), "displacement -129 is out of range");
+    assert_eq!(error(" ld ixh, iyl"), "invalid operands for `ld`");
+    assert_eq!(error(" ld ixh, h"), "invalid operands for `ld`");
+    assert_eq!(error(" add ix, hl"), "invalid operands for `add`");
+    assert_eq!(error(" bit 0, ixh"), "invalid operands for `bit`");
+}
+
+#[test]
+fn alu_and_bits() {
+    assert_eq!(asm(" add a, b\n sub c\n and 0x0f\n cp (hl)"), [0x80, 0x91, 0xe6, 0x0f, 0xbe]);
+    assert_eq!(asm(" adc hl, de\n sbc hl, sp"), [0xed, 0x5a, 0xed, 0x72]);
+    assert_eq!(asm(" inc a\n dec bc\n inc sp"), [0x3c, 0x0b, 0x33]);
+    assert_eq!(asm(" set 7, a\n res 0, (hl)\n srl b"), [0xcb, 0xff, 0xcb, 0x86, 0xcb, 0x38]);
+    assert_eq!(error(" bit 8, a"), "bit 8 is out of range");
+}
+
+#[test]
+fn flow() {
+    assert_eq!(asm(" jp 0x1234\n jp nz, 0x1234"), [0xc3, 0x34, 0x12, 0xc2, 0x34, 0x12]);
+    assert_eq!(asm(" call m, 0x10\n ret\n ret c"), [0xfc, 0x10, 0x00, 0xc9, 0xd8]);
+    assert_eq!(asm(" rst 0x38\n im 1"), [0xff, 0xed, 0x56]);
+    assert_eq!(error(" rst 0x39"), "invalid restart address 0x39");
+    assert_eq!(error(" jr po, 0"), "invalid operands for `jr`");
+}
+
+#[test]
+fn relative_jumps() {
+    assert_eq!(asm("back: jr back"), [0x18, 0xfe]);
+    assert_eq!(asm(" jr nc, fwd\n nop\nfwd: nop"), [0x30, 0x01, 0x00, 0x00]);
+    assert_eq!(asm("back: djnz back"), [0x10, 0xfe]);
+
+    // The offset is from the end of the instruction, so -128 to 127 bytes.
+    assert_eq!(asm("back: ds 126\n jr back")[126..], [0x18, 0x80]);
+    assert_eq!(asm(" jr fwd\n ds 127\nfwd:")[..2], [0x18, 0x7f]);
+    assert_eq!(error("back: ds 127\n jr back"), "relative jump of -129 bytes is out of range");
+    assert_eq!(error(" jr fwd\n ds 128\nfwd:"), "relative jump of 128 bytes is out of range");
+    assert_eq!(error(" djnz fwd\n ds 128\nfwd:"), "relative jump of 128 bytes is out of range");
+}
+
+#[test]
+fn io() {
+    assert_eq!(asm(" in a, (0x10)\n out (0x11), a"), [0xdb, 0x10, 0xd3, 0x11]);
+    assert_eq!(asm(" in b, (c)\n out (c), e\n out (c), 0"), [0xed, 0x40, 0xed, 0x59, 0xed, 0x71]);
+    assert_eq!(asm(" ex af, af'\n ex de, hl"), [0x08, 0xeb]);
+}
+
+#[test]
+fn numbers() {
+    assert_eq!(asm(" db 10, $1f, 0x1F, 1fh, 0FFh, %101, 0b101, 101b"),
+               [10, 0x1f, 0x1f, 0x1f, 0xff, 5, 5, 5]);
+    // A hex suffix wins over a binary prefix.
+    assert_eq!(asm(" db 0b6h"), [0xb6]);
+    assert_eq!(asm(" db 'A', -1"), [0x41, 0xff]);
+    assert_eq!(error(" db 12g"), "invalid number `12g`");
+    assert_eq!(error(" db 256"), "value 256 does not fit in a byte");
+}
+
+#[test]
+fn expressions() {
+    assert_eq!(asm(" db 1 + 2 * 3, (1 + 2) * 3, 7 / 2, 7 % 4"), [7, 9, 3, 3]);
+    assert_eq!(asm(" db 1 << 4 | 1, 0xff & ~0x0f, 6 ^ 3, 0x1234 >> 8"), [0x11, 0xf0, 5, 0x12]);
+    assert_eq!(asm(" db -(2 - 5), 10 - 3 - 2"), [3, 5]);
+    assert_eq!(asm(" org 0x10\n dw $, $ + 2"), [0x10, 0x00, 0x12, 0x00]);
+    assert_eq!(error(" db 1 / 0"), "division by zero");
+    assert_eq!(error(" db (1"), "missing `)`");
+    assert_eq!(error(" db 1 +"), "expected a value");
+    assert_eq!(error(" db nothing"), "undefined symbol `nothing`");
+}
+
+#[test]
+fn data_directives() {
+    assert_eq!(asm(" db \"Hi; there\", 0"), b"Hi; there\0");
+    assert_eq!(asm(" dw 0x1234, -1"), [0x34, 0x12, 0xff, 0xff]);
+    assert_eq!(asm(" ds 3, 0xaa\n ds 1"), [0xaa, 0xaa, 0xaa, 0x00]);
+    assert_eq!(asm(" db 1\n align 4\n db 2"), [1, 0, 0, 0, 2]);
+    assert_eq!(asm(" org 0x10\n db 1\n org 0x13\n db 2"), [1, 0, 0, 2]);
+    assert_eq!(asm(" db 1\n end\n db 2"), [1]);
+    assert_eq!(error(" db 1\n org 0"), "`org` cannot move backwards");
+    assert_eq!(error(" ds -1"), "size cannot be negative");
+    assert_eq!(error(" align 3"), "alignment must be a power of two");
+}
+
+#[test]
+fn labels() {
+    let program = Assembler::new().assemble_str("test.s", "\
+        org 0x8000
+first:
+.loop:  djnz .loop
+second:
+.loop   jr .loop
+size    equ second - first
+        COUNT = 3
+").unwrap();
+    assert_eq!(program.origin(), 0x8000);
+    assert_eq!(program.bytes(), [0x10, 0xfe, 0x18, 0xfe]);
+    assert_eq!(program.symbol("first.loop"), Some(0x8000));
+    assert_eq!(program.symbol("second.loop"), Some(0x8002));
+    assert_eq!(program.symbol("size"), Some(2));
+    assert_eq!(program.symbol("COUNT"), Some(3));
+
+    assert_eq!(error("a: nop\na: nop"), "`a` is defined more than once");
+    assert_eq!(error("1a: nop"), "invalid label `1a`");
+}
+
+#[test]
+fn forward_references() {
+    // Both the size of `ds` and the jump depend on labels defined later.
+    assert_eq!(asm(" jp end_\n ds pad\nend_: nop\npad equ 2"), [0xc3, 0x05, 0x00, 0, 0, 0x00]);
+    assert_eq!(asm(" org start\n nop\nstart equ 0x40"), [0x00]);
+    assert_eq!(error("a equ b\nb equ a + 1"), "symbols did not settle, check for circular definitions");
+}
+
+#[test]
+fn defines() {
+    let program = Assembler::new().define("RATE", 5).assemble_str("test.s", " ld a, RATE").unwrap();
+    assert_eq!(program.bytes(), [0x3e, 0x05]);
+}
+
+#[test]
+fn includes() {
+    let program = Assembler::new().assemble_file(data("main.s")).unwrap();
+    assert_eq!(program.origin(), 0x100);
+    assert_eq!(program.bytes(), [0x3e, 0x42, 0xc3, 0x00, 0x01, 0x01, 0x02, 0x03]);
+    assert_eq!(program.symbol("VALUE"), Some(0x42));
+    assert_eq!(program.symbol("table"), Some(0x105));
+    assert_eq!(program.dependencies(), [data("main.s"), data("consts.s"), data("table.bin")]);
+}
+
+#[test]
+fn include_dirs() {
+    let source = " include \"lib.s\"\n db LIB_VALUE";
+    let e = Assembler::new().assemble_str(data("test.s"), source).unwrap_err();
+    assert_eq!(e.message, "cannot find include `lib.s`");
+    assert_eq!(e.line, 1);
+
+    let program = Assembler::new()
+        .include_dir(data("lib"))
+        .assemble_str(data("test.s"), source)
+        .unwrap();
+    assert_eq!(program.bytes(), [7]);
+}
+
+#[test]
+fn error_lines() {
+    let e = assemble(" nop\n\n ld a, (ix+200)").unwrap_err();
+    assert_eq!(e.line, 3);
+    assert_eq!(e.to_string(), "test.s:3: displacement 200 is out of range");
+}
+
+#[test]
+fn symbol_file() {
+    let program = Assembler::new().assemble_str("test.s", " org 0x10\nstart: nop\nVALUE = 0xabc").unwrap();
+    assert_eq!(program.symbol_file(), "VALUE = $0ABC\nstart = $0010\n");
+}
+
+#[test]
+fn listing() {
+    let program = Assembler::new()
+        .assemble_str("test.s", " org 0x10\nstart: ld a, 1 ; one\n db 1, 2, 3, 4, 5, 6")
+        .unwrap();
+    let expected = "\
+0010                   1   org 0x10
+0010  3E 01            2  start: ld a, 1 ; one
+0012  01 02 03 04      3   db 1, 2, 3, 4, 5, 6
+0016  05 06
+";
+    assert_eq!(program.listing(), expected);
+}
+
+#[test]
+fn write() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("z80asm-write");
+    let program = Assembler::new().assemble_str("test.s", "start: nop").unwrap();
+    let bin = program.write(&dir, "out").unwrap();
+    assert_eq!(bin, dir.join("out.bin"));
+    assert_eq!(std::fs::read(&bin).unwrap(), [0x00]);
+    assert_eq!(std::fs::read_to_string(dir.join("out.sym")).unwrap(), "start = $0000\n");
+    assert!(dir.join("out.lst").is_file());
+}
@@ -0,0 +1 @@
+VALUE equ 0x42
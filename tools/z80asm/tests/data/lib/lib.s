@@ -0,0 +1 @@
+LIB_VALUE = 7
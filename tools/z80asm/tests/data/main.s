@@ -0,0 +1,8 @@
+; Used by the include tests.
+	org 0x100
+	include "consts.s"
+start:
+	ld a, VALUE
+	jp start
+table:
+	incbin "table.bin"